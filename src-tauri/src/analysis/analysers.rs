@@ -1,24 +1,51 @@
-use crate::models::{FastaSeqResult, FastqSeqResult};
+use crate::analysis::complexity::sequence_complexity;
+use crate::analysis::illumina::parse_illumina_header;
+use crate::models::{
+    AlphabetError, FastaSeqResult, FastqSeqResult, OrfSpan, SeqAlphabet, SeqComplexity,
+    SeqRecordDetail,
+};
+use bio::alphabets::{dna, protein, rna, Alphabet};
 use bio::io::{fasta, fastq};
 use bio::seq_analysis::{gc, orf};
 use bio::utils::TextSlice;
 
-pub fn analyse_fastq_records(records: &Vec<fastq::Record>) -> Vec<FastqSeqResult> {
+pub fn analyse_fastq_records(
+    records: &Vec<fastq::Record>,
+    alphabet: Option<SeqAlphabet>,
+) -> Vec<FastqSeqResult> {
     let mut results = Vec::new();
+    let symbols = alphabet.map(alphabet_symbols);
 
     // Iterate over results and find GC content and ORFs
     for rec in records {
         if rec.check().is_ok() {
-            let gc_ = gc::gc_content(rec.seq());
-            let n_orfs = find_orfs(rec.seq());
+            let alphabet_error = symbols
+                .as_ref()
+                .and_then(|symbols| find_invalid_symbol(rec.seq(), symbols));
+            let metrics = NucleotideMetrics::new(rec.seq(), alphabet);
+            let expected_errors: f64 = rec.qual().iter().map(|q| error_probability(*q)).sum();
+            let mean_quality = if rec.seq().is_empty() {
+                0.0
+            } else {
+                phred_from_error_probability(expected_errors / rec.seq().len() as f64)
+            };
             results.push(FastqSeqResult {
-                n_orfs,
+                n_orfs: metrics.n_orfs,
                 id: rec.id().to_owned(),
                 desc: rec.desc().unwrap_or("").to_owned(),
-                gc: gc_,
-                is_valid: rec.check().is_ok(),
+                gc: metrics.gc,
+                is_valid: alphabet_error.is_none(),
                 phred_score: calc_phred_score(rec.qual()),
+                mean_quality,
+                expected_errors: expected_errors as f32,
                 seq_len: rec.seq().len(),
+                n_count: metrics.n_count,
+                ambiguous_count: metrics.ambiguous_count,
+                longest_n_run: metrics.longest_n_run,
+                complexity: metrics.complexity,
+                alphabet,
+                alphabet_error,
+                illumina: parse_illumina_header(rec.id()),
                 ..Default::default()
             });
         } else {
@@ -33,21 +60,33 @@ pub fn analyse_fastq_records(records: &Vec<fastq::Record>) -> Vec<FastqSeqResult
     results
 }
 
-pub fn analyse_fasta_records(records: &Vec<fasta::Record>) -> Vec<FastaSeqResult> {
+pub fn analyse_fasta_records(
+    records: &Vec<fasta::Record>,
+    alphabet: Option<SeqAlphabet>,
+) -> Vec<FastaSeqResult> {
     let mut results = Vec::new();
+    let symbols = alphabet.map(alphabet_symbols);
 
     // Iterate over results and find GC content and ORFs
     for rec in records {
         if rec.check().is_ok() {
-            let gc_ = gc::gc_content(rec.seq());
-            let n_orfs = find_orfs(rec.seq());
+            let alphabet_error = symbols
+                .as_ref()
+                .and_then(|symbols| find_invalid_symbol(rec.seq(), symbols));
+            let metrics = NucleotideMetrics::new(rec.seq(), alphabet);
             results.push(FastaSeqResult {
-                n_orfs,
+                n_orfs: metrics.n_orfs,
                 id: rec.id().to_owned(),
                 desc: rec.desc().unwrap_or("").to_owned(),
-                gc: gc_,
-                is_valid: rec.check().is_ok(),
+                gc: metrics.gc,
+                is_valid: alphabet_error.is_none(),
                 seq_len: rec.seq().len(),
+                n_count: metrics.n_count,
+                ambiguous_count: metrics.ambiguous_count,
+                longest_n_run: metrics.longest_n_run,
+                complexity: metrics.complexity,
+                alphabet,
+                alphabet_error,
                 ..Default::default()
             });
         } else {
@@ -62,15 +101,161 @@ pub fn analyse_fasta_records(records: &Vec<fasta::Record>) -> Vec<FastaSeqResult
     results
 }
 
+/// Metrics that only make sense for nucleotide sequences, left at zero for
+/// protein records.
+#[derive(Default)]
+struct NucleotideMetrics {
+    gc: f32,
+    n_orfs: usize,
+    n_count: usize,
+    ambiguous_count: usize,
+    longest_n_run: usize,
+    complexity: SeqComplexity,
+}
+
+impl NucleotideMetrics {
+    fn new(seq: TextSlice, alphabet: Option<SeqAlphabet>) -> Self {
+        if alphabet == Some(SeqAlphabet::Protein) {
+            return NucleotideMetrics::default();
+        }
+
+        let (n_count, ambiguous_count, longest_n_run) = count_ambiguous_bases(seq);
+        NucleotideMetrics {
+            gc: gc::gc_content(seq),
+            n_orfs: find_orfs(seq),
+            n_count,
+            ambiguous_count,
+            longest_n_run,
+            complexity: sequence_complexity(seq),
+        }
+    }
+}
+
+pub fn alphabet_symbols(alphabet: SeqAlphabet) -> Alphabet {
+    match alphabet {
+        SeqAlphabet::Dna => dna::alphabet(),
+        SeqAlphabet::IupacDna => dna::iupac_alphabet(),
+        SeqAlphabet::Rna => rna::alphabet(),
+        SeqAlphabet::Protein => {
+            // Protein FASTA often marks the stop codon with '*'
+            let mut symbols = protein::iupac_alphabet();
+            symbols.insert(b'*');
+            symbols
+        }
+    }
+}
+
+/// The first character of a sequence outside the alphabet, and its position.
+pub fn find_invalid_symbol(seq: TextSlice, symbols: &Alphabet) -> Option<AlphabetError> {
+    seq.iter()
+        .position(|base| !symbols.symbols.contains(usize::from(*base)))
+        .map(|position| AlphabetError {
+            character: char::from(seq[position]),
+            position,
+        })
+}
+
+/// The adapters FastQC screens for, by the names its reports give them.
+pub const ADAPTERS: [(&str, &[u8]); 4] = [
+    ("Illumina Universal Adapter", b"AGATCGGAAGAGC"),
+    ("Nextera Transposase Sequence", b"CTGTCTCTTATACACATCT"),
+    ("Illumina Small RNA 3' Adapter", b"TGGAATTCTCGG"),
+    ("SOLID Small RNA Adapter", b"CGCCTTGGCCGT"),
+];
+
+/// Find where the given adapter sequence starts in a read, if anywhere.
+pub fn find_adapter_sequence(seq: TextSlice, adapter: &[u8]) -> Option<usize> {
+    seq.windows(adapter.len())
+        .position(|window| window.eq_ignore_ascii_case(adapter))
+}
+
+/// Find where the earliest known adapter sequence starts in a read, if any.
+pub fn find_adapter(seq: TextSlice) -> Option<usize> {
+    ADAPTERS
+        .iter()
+        .filter_map(|(_, adapter)| find_adapter_sequence(seq, adapter))
+        .min()
+}
+
+/// Count the Ns, the other IUPAC ambiguity codes and the longest run of Ns.
+fn count_ambiguous_bases(seq: TextSlice) -> (usize, usize, usize) {
+    let mut n_count = 0;
+    let mut ambiguous_count = 0;
+    let mut n_run = 0;
+    let mut longest_n_run = 0;
+    for base in seq.iter().map(|base| base.to_ascii_uppercase()) {
+        if base == b'N' {
+            n_count += 1;
+            n_run += 1;
+            longest_n_run = longest_n_run.max(n_run);
+            continue;
+        }
+        n_run = 0;
+        if b"RYSWKMBDHV".contains(&base) {
+            ambiguous_count += 1;
+        }
+    }
+    (n_count, ambiguous_count, longest_n_run)
+}
+
 fn find_orfs(seq: TextSlice) -> usize {
+    orf_finder().find_all(seq).count()
+}
+
+/// Where each open reading frame lies in a sequence.
+pub fn orf_spans(seq: TextSlice) -> Vec<OrfSpan> {
+    orf_finder()
+        .find_all(seq)
+        .map(|orf| OrfSpan {
+            start: orf.start,
+            end: orf.end,
+            frame: orf.offset,
+        })
+        .collect()
+}
+
+fn orf_finder() -> orf::Finder {
     // Hyperparameters for finding open reading frames (ORFs).
     // NB: DNA alphabet
     let start_codons = vec![b"ATG"];
     let stop_codons = vec![b"TGA", b"TAG", b"TAA"];
     let min_len = 50;
-    let finder = orf::Finder::new(start_codons, stop_codons, min_len);
+    orf::Finder::new(start_codons, stop_codons, min_len)
+}
+
+/// Probability that a base call with this Phred+33 quality character is wrong.
+pub fn error_probability(qual: u8) -> f64 {
+    let phred = f64::from(qual.saturating_sub(33));
+    10f64.powf(-phred / 10.0)
+}
+
+/// Convert an error probability back into a Phred score.
+pub fn phred_from_error_probability(prob: f64) -> f32 {
+    if prob <= 0.0 {
+        return 0.0;
+    }
+    (-10.0 * prob.log10()) as f32
+}
 
-    finder.find_all(seq).count()
+/// Everything needed to draw a single record: its sequence, the quality of each
+/// base, and where its ORFs and any adapter lie.
+pub fn record_detail(
+    index: usize,
+    id: &str,
+    desc: Option<&str>,
+    seq: TextSlice,
+    qual: Option<&[u8]>,
+) -> SeqRecordDetail {
+    SeqRecordDetail {
+        index,
+        id: id.to_owned(),
+        desc: desc.unwrap_or("").to_owned(),
+        seq: String::from_utf8_lossy(seq).into_owned(),
+        qual: qual.map(|qual| String::from_utf8_lossy(qual).into_owned()),
+        phred: qual.map(|qual| qual.iter().map(|q| q.saturating_sub(33)).collect()),
+        orfs: orf_spans(seq),
+        adapter_start: find_adapter(seq),
+    }
 }
 
 fn calc_phred_score(qual: &[u8]) -> u32 {
@@ -84,11 +269,80 @@ fn calc_phred_score(qual: &[u8]) -> u32 {
 
 #[cfg(test)]
 mod tests {
-    use super::calc_phred_score;
+    use super::{
+        analyse_fasta_records, calc_phred_score, count_ambiguous_bases, error_probability,
+        find_adapter, phred_from_error_probability, record_detail,
+    };
+    use crate::models::SeqAlphabet;
+    use bio::io::fasta;
 
     #[test]
     fn test_calc_phred_score() {
         assert_eq!(calc_phred_score(b"!!!"), 0);
         assert_eq!(calc_phred_score(b"*+"), 19)
     }
+
+    #[test]
+    fn test_count_ambiguous_bases() {
+        assert_eq!(count_ambiguous_bases(b"ACNNGTnNNRYA"), (5, 2, 3));
+        assert_eq!(count_ambiguous_bases(b"ACGT"), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_find_adapter() {
+        assert_eq!(find_adapter(b"ACGTAGATCGGAAGAGCACAC"), Some(4));
+        assert_eq!(find_adapter(b"ACGTACGT"), None);
+    }
+
+    #[test]
+    fn test_error_probability_round_trip() {
+        assert!((error_probability(b'5') - 0.01).abs() < 1e-12);
+        assert!((phred_from_error_probability(0.001) - 30.0).abs() < 1e-4);
+        assert_eq!(phred_from_error_probability(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_analyse_fasta_records_validates_alphabet() {
+        let records = vec![
+            fasta::Record::with_attrs("dna", None, b"ACGTACGT"),
+            fasta::Record::with_attrs("rna", None, b"ACGUACGU"),
+            fasta::Record::with_attrs("protein", None, b"MKVLAAGIW*"),
+        ];
+
+        let dna = analyse_fasta_records(&records, Some(SeqAlphabet::Dna));
+        assert!(dna[0].is_valid);
+        assert!(!dna[1].is_valid);
+        let error = dna[1].alphabet_error.as_ref().unwrap();
+        assert_eq!((error.character, error.position), ('U', 3));
+
+        let protein = analyse_fasta_records(&records, Some(SeqAlphabet::Protein));
+        assert!(protein[2].is_valid);
+        assert_eq!(protein[2].gc, 0.0);
+        assert_eq!(protein[2].n_orfs, 0);
+
+        let unchecked = analyse_fasta_records(&records, None);
+        assert!(unchecked.iter().all(|res| res.is_valid));
+        assert_eq!(unchecked[0].gc, 0.5);
+    }
+
+    #[test]
+    fn test_record_detail() {
+        let mut seq = b"CCATG".to_vec();
+        seq.extend_from_slice(&b"GCA".repeat(20));
+        seq.extend_from_slice(b"TAAGGAGATCGGAAGAGCAC");
+        let qual = vec![b'I'; seq.len()];
+
+        let detail = record_detail(3, "r4", Some("desc"), &seq, Some(&qual));
+        assert_eq!(detail.index, 3);
+        assert_eq!(detail.seq.len(), 85);
+        assert_eq!(detail.phred.unwrap()[0], 40);
+        assert_eq!(detail.orfs.len(), 1);
+        assert_eq!((detail.orfs[0].start, detail.orfs[0].end), (2, 68));
+        assert_eq!(detail.adapter_start, Some(70));
+
+        let fasta = record_detail(0, "contig", None, b"ACGT", None);
+        assert!(fasta.qual.is_none());
+        assert!(fasta.phred.is_none());
+        assert_eq!(fasta.desc, "");
+    }
 }
@@ -0,0 +1,87 @@
+use crate::analysis::analysers::{analyse_fasta_records, analyse_fastq_records};
+use crate::analysis::profiles::evaluate_qc_profile;
+use crate::analysis::summary::SummaryBuilder;
+use crate::models::{BatchFileSummary, QcProfile, SeqFormat, SeqSummary};
+use crate::services::format::{detect_format, read_reads};
+use crate::services::io::{read_fasta, sample_name};
+use bio::io::fasta;
+use rayon::prelude::*;
+use std::io::Error as IOError;
+use std::io::ErrorKind::InvalidInput;
+use std::path::{Path, PathBuf};
+
+/// How many records of a file to analyse at a time, so that files summarised
+/// together are never all held in memory.
+const CHUNK_SIZE: usize = 10_000;
+
+/// Summarise each file concurrently, grading it against the QC profile if one
+/// is given. The format of each file is detected from its contents, so a run
+/// folder may mix them. A file that cannot be read records its error in its own
+/// entry rather than failing the whole batch.
+pub fn analyse_batch_files(
+    paths: &[PathBuf],
+    profile: Option<&QcProfile>,
+) -> Vec<BatchFileSummary> {
+    paths
+        .par_iter()
+        .map(|path| {
+            let summary = summarise_file(path);
+            let qc = match (&summary, profile) {
+                (Ok(summary), Some(profile)) => Some(evaluate_qc_profile(summary, profile)),
+                _ => None,
+            };
+            BatchFileSummary {
+                path: path.to_string_lossy().into_owned(),
+                sample: sample_name(path),
+                error: summary.as_ref().err().map(|err| err.to_string()),
+                summary: summary.ok(),
+                qc,
+            }
+        })
+        .collect()
+}
+
+fn summarise_file(path: &Path) -> Result<SeqSummary, IOError> {
+    let mut summary = SummaryBuilder::default();
+    match detect_format(path)?.format {
+        SeqFormat::Fasta => {
+            let mut records = read_fasta(path)?
+                .records()
+                .map(|rec| rec.unwrap_or_default());
+            loop {
+                let chunk: Vec<fasta::Record> = records.by_ref().take(CHUNK_SIZE).collect();
+                if chunk.is_empty() {
+                    return Ok(summary.finish("fasta"));
+                }
+                for res in analyse_fasta_records(&chunk, None).iter() {
+                    summary.add_fasta(res);
+                }
+                for rec in chunk.iter().filter(|rec| rec.check().is_ok()) {
+                    summary.add_sequence(rec.seq());
+                }
+            }
+        }
+        SeqFormat::Unknown => Err(IOError::new(
+            InvalidInput,
+            format!("Could not recognise the format of {}.", path.display()),
+        )),
+        _ => {
+            let mut reads = read_reads(path, None)?;
+            loop {
+                let chunk = reads
+                    .by_ref()
+                    .take(CHUNK_SIZE)
+                    .collect::<Result<Vec<_>, _>>()?;
+                if chunk.is_empty() {
+                    return Ok(summary.finish("fastq"));
+                }
+                for res in analyse_fastq_records(&chunk, None).iter() {
+                    summary.add_fastq(res);
+                }
+                for rec in chunk.iter().filter(|rec| rec.check().is_ok()) {
+                    summary.add_sequence(rec.seq());
+                }
+            }
+        }
+    }
+}
@@ -1,74 +1,877 @@
-use crate::analysis::analysers::{analyse_fasta_records, analyse_fastq_records};
-use crate::models::{FastaSeqResult, FastqSeqResult};
-use crate::services::io::{read_fasta, read_fastq};
+use crate::analysis::analysers::{analyse_fasta_records, analyse_fastq_records, record_detail};
+use crate::analysis::batch::analyse_batch_files;
+use crate::analysis::compare::{compare_summaries, DEFAULT_OUTLIER_THRESHOLD};
+use crate::analysis::contamination::{build_kmer_index, screen_reads};
+use crate::analysis::demux::demultiplex_file;
+use crate::analysis::fastqc::fastqc_modules;
+use crate::analysis::illumina::flowcell_quality;
+use crate::analysis::kmers::{kmer_spectrum, DEFAULT_K, MAX_K};
+use crate::analysis::longread::summarise_long_reads;
+use crate::analysis::profiles::{builtin_qc_profiles, evaluate_qc_profile};
+use crate::analysis::protein::analyse_protein_records;
+use crate::analysis::store::{RecordLookup, ResultStore};
+use crate::analysis::subsample::{subsample as subsample_records, subsample_fastq_files};
+use crate::analysis::summary::{
+    gc_distribution, length_distribution, n_content_by_position, quality_distribution,
+    summarise_fasta_results, summarise_fastq_results, with_sequence_metrics,
+};
+use crate::analysis::umi::{extract_umis, summarise_umis, UmiPattern};
+use crate::models::{
+    BarcodeLocation, BatchFileSummary, ContaminationReport, DemuxReport, FastaSeqResult,
+    FastqSeqResult, FastqcThresholds, FileAnalysis, FileResults, FlowcellQuality, KmerSpectrum,
+    LongReadSummary, ProteinSeqResult, QcProfile, QcReport, RegionSequence, ResultFilter,
+    ResultMatches, ResultSort, ResultsHandle, ResultsPage, SampleComparison, SeqAlphabet,
+    SeqFormat, SeqIndexSummary, SeqRecordDetail, SeqResults, SeqSummary, Subsample,
+    SubsampleReport, UmiAnalysis, UmiSource,
+};
+use crate::services::cram::{cram_slices, load_cram_slice};
+use crate::services::faidx::{
+    fai_path, find_entry, gzi_path, load_index, load_or_build_index, parse_region, IndexedReader,
+    Region, SeqIndex,
+};
+use crate::services::fastqc::save_fastqc_data;
+use crate::services::format::{
+    detect_format, format_from_bytes, load_read_record_range, load_read_records, load_record_at,
+    load_record_at_offset, load_record_offsets, load_seq_record_range, load_seq_records,
+    read_reads, SeqRecords,
+};
+use crate::services::io::{
+    collect_batch_paths, create_fastq_writer, load_fasta_record_range, load_fasta_records,
+    load_fastq_record_range, load_fastq_records, load_qc_profiles, read_fasta, sample_name,
+    RecordRange,
+};
+use crate::services::kmer_index::{
+    kmer_index_path, load_cached_kmer_index, save_kmer_index, KmerIndex,
+};
+use crate::services::multiqc::{save_multiqc_general_stats, save_multiqc_linegraph};
+use crate::services::samplesheet::load_sample_sheet;
 use bio::io::{fasta, fastq};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// How many reads are screened for contamination unless the caller asks otherwise.
+const DEFAULT_SCREEN_READS: usize = 100_000;
 
 #[tauri::command]
-pub fn analyse_fastq_sequences(sequences: &str) -> Vec<FastqSeqResult> {
+pub fn analyse_fastq_sequences(
+    sequences: &str,
+    alphabet: Option<SeqAlphabet>,
+) -> Vec<FastqSeqResult> {
     let reader = fastq::Reader::new(sequences.as_bytes());
     let records: Vec<fastq::Record> = reader
         .records()
         .map(|rec| rec.unwrap_or_default())
         .collect();
 
-    let results = analyse_fastq_records(&records);
+    let results = analyse_fastq_records(&records, alphabet);
 
     results
 }
 
+/// Analyse a FASTQ file, or only its first reads for a quick look. A preview
+/// can be continued later by passing its `next_record` as `start_record`, and
+/// its `next_offset` as `start_offset` to seek there instead of reading up to it.
 #[tauri::command]
-pub fn analyse_fastq_file(path: &std::path::Path) -> Vec<FastqSeqResult> {
-    let reader = read_fastq(path);
-    let records: Vec<fastq::Record> = reader
-        .records()
-        .map(|rec| rec.unwrap_or_default())
-        .collect();
-
-    let path_str = path.to_str().unwrap();
-    if path_str.ends_with(".gz") {
-        let unpacked = path_str.replace(".gz", "");
-        match std::fs::remove_file(unpacked) {
-            Ok(_) => (),
-            Err(_) => (),
-        }
-    }
-    let results = analyse_fastq_records(&records);
+pub fn analyse_fastq_file(
+    path: &std::path::Path,
+    alphabet: Option<SeqAlphabet>,
+    max_records: Option<usize>,
+    max_bytes: Option<u64>,
+    start_record: Option<usize>,
+    start_offset: Option<u64>,
+    profile: Option<QcProfile>,
+) -> Result<FileResults<FastqSeqResult>, String> {
+    let range = RecordRange {
+        start: start_record.unwrap_or(0),
+        start_offset,
+        max_records,
+        max_bytes,
+    };
+    let partial = load_fastq_record_range(path, &range).map_err(|err| err.to_string())?;
+    let results = analyse_fastq_records(&partial.records, alphabet);
 
-    results
+    Ok(FileResults {
+        qc: grade_fastq_results(&partial.records, &results, profile.as_ref()),
+        results,
+        start_record: range.start,
+        is_preview: partial.next_record.is_some(),
+        next_record: partial.next_record,
+        next_offset: partial.next_offset,
+    })
 }
 
 #[tauri::command]
-pub fn analyse_fasta_sequences(sequences: &str) -> Vec<FastaSeqResult> {
+pub fn analyse_fasta_sequences(
+    sequences: &str,
+    alphabet: Option<SeqAlphabet>,
+) -> Vec<FastaSeqResult> {
     let reader = fasta::Reader::new(sequences.as_bytes());
     let records: Vec<fasta::Record> = reader
         .records()
         .map(|rec| rec.unwrap_or_default())
         .collect();
 
-    let results = analyse_fasta_records(&records);
+    let results = analyse_fasta_records(&records, alphabet);
 
     results
 }
 
+/// Analyse a FASTA file, or only its first records for a quick look. A preview
+/// can be continued later by passing its `next_record` as `start_record`, and
+/// its `next_offset` as `start_offset` to seek there instead of reading up to it.
 #[tauri::command]
-pub fn analyse_fasta_file(path: &std::path::Path) -> Vec<FastaSeqResult> {
-    let reader = read_fasta(path);
+pub fn analyse_fasta_file(
+    path: &std::path::Path,
+    alphabet: Option<SeqAlphabet>,
+    max_records: Option<usize>,
+    max_bytes: Option<u64>,
+    start_record: Option<usize>,
+    start_offset: Option<u64>,
+    profile: Option<QcProfile>,
+) -> Result<FileResults<FastaSeqResult>, String> {
+    let range = RecordRange {
+        start: start_record.unwrap_or(0),
+        start_offset,
+        max_records,
+        max_bytes,
+    };
+    let partial = load_fasta_record_range(path, &range).map_err(|err| err.to_string())?;
+    let results = analyse_fasta_records(&partial.records, alphabet);
+
+    Ok(FileResults {
+        qc: grade_fasta_results(&partial.records, &results, profile.as_ref()),
+        results,
+        start_record: range.start,
+        is_preview: partial.next_record.is_some(),
+        next_record: partial.next_record,
+        next_offset: partial.next_offset,
+    })
+}
+
+#[tauri::command]
+pub fn analyse_protein_sequences(sequences: &str) -> Vec<ProteinSeqResult> {
+    let reader = fasta::Reader::new(sequences.as_bytes());
     let records: Vec<fasta::Record> = reader
         .records()
         .map(|rec| rec.unwrap_or_default())
         .collect();
 
-    let path_str = path.to_str().unwrap();
-    if path_str.ends_with(".gz") {
-        let unpacked = path_str.replace(".gz", "");
-        match std::fs::remove_file(unpacked) {
-            Ok(_) => (),
-            Err(_) => (),
+    analyse_protein_records(&records)
+}
+
+#[tauri::command]
+pub fn analyse_protein_file(path: &Path) -> Result<Vec<ProteinSeqResult>, String> {
+    let records = load_fasta_records(path).map_err(|err| err.to_string())?;
+
+    Ok(analyse_protein_records(&records))
+}
+
+/// Analyse pasted FASTQ or FASTA text, recognising the format from its content.
+#[tauri::command]
+pub fn analyse_sequences(
+    sequences: &str,
+    alphabet: Option<SeqAlphabet>,
+) -> Result<SeqResults, String> {
+    match format_from_bytes(sequences.as_bytes()) {
+        SeqFormat::Fastq => Ok(SeqResults::Fastq(analyse_fastq_sequences(
+            sequences, alphabet,
+        ))),
+        SeqFormat::Fasta => Ok(SeqResults::Fasta(analyse_fasta_sequences(
+            sequences, alphabet,
+        ))),
+        _ => Err("Could not recognise the sequences as FASTQ or FASTA.".to_owned()),
+    }
+}
+
+/// Analyse a file of any supported format, reporting the format that was detected.
+/// Only the first records can be analysed for a quick look at a large file, or a
+/// subsample of them, and the results graded against a QC profile. Aligned CRAM
+/// reads are rebuilt from `reference`, the FASTA file they were aligned against.
+#[tauri::command]
+pub fn analyse_file(
+    path: &Path,
+    alphabet: Option<SeqAlphabet>,
+    subsample: Option<Subsample>,
+    profile: Option<QcProfile>,
+    reference: Option<PathBuf>,
+    max_records: Option<usize>,
+    max_bytes: Option<u64>,
+) -> Result<FileAnalysis, String> {
+    let detected = detect_format(path).map_err(|err| err.to_string())?;
+    let range = RecordRange {
+        max_records,
+        max_bytes,
+        ..Default::default()
+    };
+    let is_limited = max_records.is_some() || max_bytes.is_some();
+    let (results, qc, next_record) = match detected.format {
+        SeqFormat::Fastq | SeqFormat::Sam | SeqFormat::Bam | SeqFormat::Cram => {
+            let (records, next_record) = if is_limited {
+                let partial = load_read_record_range(path, &range, reference.as_deref())
+                    .map_err(|err| err.to_string())?;
+                (
+                    preview(partial.records, subsample.as_ref())?,
+                    partial.next_record,
+                )
+            } else {
+                let reads =
+                    read_reads(path, reference.as_deref()).map_err(|err| err.to_string())?;
+                // Stop at the first unreadable record, keeping its error
+                let mut failed = None;
+                let records = preview(
+                    reads.map_while(|rec| rec.map_err(|err| failed = Some(err)).ok()),
+                    subsample.as_ref(),
+                )?;
+                if let Some(err) = failed {
+                    return Err(err.to_string());
+                }
+                (records, None)
+            };
+            let results = analyse_fastq_records(&records, alphabet);
+            let qc = grade_fastq_results(&records, &results, profile.as_ref());
+            (SeqResults::Fastq(results), qc, next_record)
+        }
+        SeqFormat::Fasta => {
+            let (records, next_record) = if is_limited {
+                let partial =
+                    load_fasta_record_range(path, &range).map_err(|err| err.to_string())?;
+                (
+                    preview(partial.records, subsample.as_ref())?,
+                    partial.next_record,
+                )
+            } else {
+                let reader = read_fasta(path).map_err(|err| err.to_string())?;
+                let records = preview(
+                    reader.records().map(|rec| rec.unwrap_or_default()),
+                    subsample.as_ref(),
+                )?;
+                (records, None)
+            };
+            let results = analyse_fasta_records(&records, alphabet);
+            let qc = grade_fasta_results(&records, &results, profile.as_ref());
+            (SeqResults::Fasta(results), qc, next_record)
+        }
+        SeqFormat::Unknown => {
+            return Err(format!(
+                "Could not recognise the format of {}.",
+                path.display()
+            ))
         }
+    };
+
+    Ok(FileAnalysis {
+        detected,
+        results,
+        subsample,
+        qc,
+        is_preview: next_record.is_some(),
+        next_record,
+    })
+}
+
+fn grade_fastq_results(
+    records: &[fastq::Record],
+    results: &[FastqSeqResult],
+    profile: Option<&QcProfile>,
+) -> Option<QcReport> {
+    let profile = profile?;
+    let valid = records.iter().filter(|rec| rec.check().is_ok());
+    let summary =
+        with_sequence_metrics(summarise_fastq_results(results), valid.map(|rec| rec.seq()));
+
+    Some(evaluate_qc_profile(&summary, profile))
+}
+
+fn grade_fasta_results(
+    records: &[fasta::Record],
+    results: &[FastaSeqResult],
+    profile: Option<&QcProfile>,
+) -> Option<QcReport> {
+    let profile = profile?;
+    let valid = records.iter().filter(|rec| rec.check().is_ok());
+    let summary =
+        with_sequence_metrics(summarise_fasta_results(results), valid.map(|rec| rec.seq()));
+
+    Some(evaluate_qc_profile(&summary, profile))
+}
+
+#[tauri::command]
+pub fn export_fastq_multiqc(path: &Path, dest_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let records = load_fastq_records(path).map_err(|err| err.to_string())?;
+    let results = analyse_fastq_records(&records, None);
+    let sample = sample_name(path);
+    let valid_records = records.iter().filter(|rec| rec.check().is_ok());
+    let summary = with_sequence_metrics(
+        summarise_fastq_results(&results),
+        valid_records.map(|rec| rec.seq()),
+    );
+    let valid: Vec<&FastqSeqResult> = results.iter().filter(|res| res.is_valid).collect();
+
+    let written = vec![
+        save_multiqc_general_stats(&sample, &summary, dest_dir),
+        save_multiqc_linegraph(
+            &sample,
+            "gc_content",
+            "Per sequence GC content",
+            "% GC",
+            &gc_distribution(valid.iter().map(|res| &res.gc)),
+            dest_dir,
+        ),
+        save_multiqc_linegraph(
+            &sample,
+            "length_distribution",
+            "Sequence length distribution",
+            "Sequence length (bp)",
+            &length_distribution(valid.iter().map(|res| &res.seq_len)),
+            dest_dir,
+        ),
+        save_multiqc_linegraph(
+            &sample,
+            "quality_scores",
+            "Per sequence quality scores",
+            "Mean sequence quality (Phred score)",
+            &quality_distribution(&results),
+            dest_dir,
+        ),
+    ];
+
+    written
+        .into_iter()
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn export_fasta_multiqc(path: &Path, dest_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let records = load_fasta_records(path).map_err(|err| err.to_string())?;
+    let results = analyse_fasta_records(&records, None);
+    let sample = sample_name(path);
+    let valid_records = records.iter().filter(|rec| rec.check().is_ok());
+    let summary = with_sequence_metrics(
+        summarise_fasta_results(&results),
+        valid_records.map(|rec| rec.seq()),
+    );
+    let valid: Vec<&FastaSeqResult> = results.iter().filter(|res| res.is_valid).collect();
+
+    let written = vec![
+        save_multiqc_general_stats(&sample, &summary, dest_dir),
+        save_multiqc_linegraph(
+            &sample,
+            "gc_content",
+            "Per sequence GC content",
+            "% GC",
+            &gc_distribution(valid.iter().map(|res| &res.gc)),
+            dest_dir,
+        ),
+        save_multiqc_linegraph(
+            &sample,
+            "length_distribution",
+            "Sequence length distribution",
+            "Sequence length (bp)",
+            &length_distribution(valid.iter().map(|res| &res.seq_len)),
+            dest_dir,
+        ),
+    ];
+
+    written
+        .into_iter()
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn export_fastqc_data(
+    path: &Path,
+    dest_dir: &Path,
+    thresholds: Option<FastqcThresholds>,
+) -> Result<PathBuf, String> {
+    let records = load_fastq_records(path).map_err(|err| err.to_string())?;
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let modules = fastqc_modules(&filename, &records, &thresholds.unwrap_or_default());
+
+    // Match FastQC's `<sample>_fastqc/fastqc_data.txt` layout so existing parsers find it
+    let report_dir = dest_dir.join(format!("{}_fastqc", sample_name(path)));
+    std::fs::create_dir_all(&report_dir).map_err(|err| err.to_string())?;
+    let dest = report_dir.join("fastqc_data.txt");
+    save_fastqc_data(&modules, &dest).map_err(|err| err.to_string())?;
+
+    Ok(dest)
+}
+
+#[tauri::command]
+pub fn analyse_batch(
+    paths: Vec<PathBuf>,
+    dir: Option<PathBuf>,
+    pattern: Option<String>,
+    profile: Option<QcProfile>,
+) -> Result<Vec<BatchFileSummary>, String> {
+    let batch = collect_batch_paths(&paths, dir.as_deref(), pattern.as_deref())
+        .map_err(|err| err.to_string())?;
+
+    Ok(analyse_batch_files(&batch, profile.as_ref()))
+}
+
+#[tauri::command]
+pub fn compare_samples(
+    summaries: Vec<BatchFileSummary>,
+    threshold: Option<f32>,
+) -> SampleComparison {
+    compare_summaries(&summaries, threshold.unwrap_or(DEFAULT_OUTLIER_THRESHOLD))
+}
+
+#[tauri::command]
+pub fn list_qc_profiles(profile_dir: Option<PathBuf>) -> Result<Vec<QcProfile>, String> {
+    let mut profiles = builtin_qc_profiles();
+    if let Some(dir) = profile_dir {
+        profiles.extend(load_qc_profiles(&dir).map_err(|err| err.to_string())?);
     }
-    let results = analyse_fasta_records(&records);
 
-    results
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub fn evaluate_qc(summary: SeqSummary, profile: QcProfile) -> QcReport {
+    evaluate_qc_profile(&summary, &profile)
+}
+
+#[tauri::command]
+pub fn analyse_long_reads(
+    path: &Path,
+    bin_minutes: Option<u64>,
+) -> Result<LongReadSummary, String> {
+    let records = load_read_records(path, None).map_err(|err| err.to_string())?;
+
+    Ok(summarise_long_reads(&records, bin_minutes.unwrap_or(60)))
+}
+
+#[tauri::command]
+pub fn analyse_flowcell_quality(path: &Path) -> Result<FlowcellQuality, String> {
+    let records = load_read_records(path, None).map_err(|err| err.to_string())?;
+
+    Ok(flowcell_quality(&analyse_fastq_records(&records, None)))
+}
+
+#[tauri::command]
+pub fn analyse_n_content(path: &Path) -> Result<Vec<f32>, String> {
+    let records = load_seq_records(path, None).map_err(|err| err.to_string())?;
+
+    Ok(n_content_by_position(records.valid_seqs()))
+}
+
+#[tauri::command]
+pub fn analyse_kmers(path: &Path, k: Option<usize>) -> Result<KmerSpectrum, String> {
+    let k = k.unwrap_or(DEFAULT_K);
+    if k == 0 || k > MAX_K {
+        return Err(format!("k must be between 1 and {}.", MAX_K));
+    }
+    let records = load_seq_records(path, None).map_err(|err| err.to_string())?;
+
+    Ok(kmer_spectrum(&records.valid_seqs(), k))
+}
+
+#[tauri::command]
+pub fn build_contamination_index(
+    reference: &Path,
+    index_dir: &Path,
+    k: Option<usize>,
+    scale: Option<u64>,
+) -> Result<PathBuf, String> {
+    let k = k.unwrap_or(DEFAULT_K);
+    if k == 0 || k > MAX_K {
+        return Err(format!("k must be between 1 and {}.", MAX_K));
+    }
+    let dest = kmer_index_path(index_dir, reference, k, scale.unwrap_or(1))
+        .map_err(|err| err.to_string())?;
+    let index = index_reference(reference, k, scale.unwrap_or(1)).map_err(|err| err.to_string())?;
+
+    save_kmer_index(&index, &dest).map_err(|err| err.to_string())
+}
+
+/// Screen the first reads of a file against each reference, building and
+/// caching any reference indexes that are missing or out of date.
+#[tauri::command]
+pub fn screen_contamination(
+    path: &Path,
+    references: Vec<PathBuf>,
+    index_dir: &Path,
+    max_reads: Option<usize>,
+    k: Option<usize>,
+    scale: Option<u64>,
+) -> Result<ContaminationReport, String> {
+    let k = k.unwrap_or(DEFAULT_K);
+    if k == 0 || k > MAX_K {
+        return Err(format!("k must be between 1 and {}.", MAX_K));
+    }
+    let scale = scale.unwrap_or(1);
+    if !index_dir.is_dir() {
+        return Err(format!("{} is not a directory.", index_dir.display()));
+    }
+
+    let mut indexes = Vec::new();
+    for reference in &references {
+        let cached =
+            kmer_index_path(index_dir, reference, k, scale).map_err(|err| err.to_string())?;
+        let index = match load_cached_kmer_index(&cached, reference, k, scale)
+            .map_err(|err| err.to_string())?
+        {
+            Some(index) => index,
+            None => {
+                let index = index_reference(reference, k, scale).map_err(|err| err.to_string())?;
+                save_kmer_index(&index, &cached).map_err(|err| err.to_string())?;
+                index
+            }
+        };
+        indexes.push(index);
+    }
+
+    let range = RecordRange {
+        max_records: Some(max_reads.unwrap_or(DEFAULT_SCREEN_READS)),
+        ..Default::default()
+    };
+    let records = load_seq_record_range(path, &range, None).map_err(|err| err.to_string())?;
+    Ok(screen_reads(&records.valid_seqs(), &indexes))
+}
+
+/// Split a FASTQ file by the barcodes in a sample sheet, by default reading them
+/// from the read headers and allowing one mismatch.
+#[tauri::command]
+pub fn demultiplex(
+    path: &Path,
+    sample_sheet: &Path,
+    dest_dir: &Path,
+    location: Option<BarcodeLocation>,
+    max_mismatches: Option<usize>,
+    compress: Option<bool>,
+) -> Result<DemuxReport, String> {
+    if !dest_dir.is_dir() {
+        return Err(format!("{} is not a directory.", dest_dir.display()));
+    }
+    let samples = load_sample_sheet(sample_sheet).map_err(|err| err.to_string())?;
+
+    demultiplex_file(
+        path,
+        &samples,
+        dest_dir,
+        location.unwrap_or(BarcodeLocation::Header),
+        max_mismatches.unwrap_or(1),
+        compress.unwrap_or(true),
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// Read the UMI of each read from its sequence with a pattern such as
+/// `NNNNNNNN`, or from its header, and summarise UMI diversity and duplication.
+/// Sequence UMIs are moved into the read IDs, and the reads written to `dest`.
+#[tauri::command]
+pub fn analyse_umis(
+    path: &Path,
+    source: UmiSource,
+    pattern: Option<String>,
+    dest: Option<PathBuf>,
+    compress: Option<bool>,
+) -> Result<UmiAnalysis, String> {
+    let pattern = match (source, pattern) {
+        (UmiSource::Sequence, Some(pattern)) => Some(UmiPattern::parse(&pattern)?),
+        (UmiSource::Sequence, None) => {
+            return Err("A UMI pattern is needed to read UMIs from the sequence.".to_owned())
+        }
+        (UmiSource::Header, _) => None,
+    };
+    let records = load_read_records(path, None).map_err(|err| err.to_string())?;
+    let reads = extract_umis(&records, source, pattern.as_ref());
+
+    if let Some(dest) = dest {
+        let mut writer =
+            create_fastq_writer(&dest, compress.unwrap_or(false)).map_err(|err| err.to_string())?;
+        for (_, rec) in &reads {
+            writer.write_record(rec).map_err(|err| err.to_string())?;
+        }
+        writer.finish().map_err(|err| err.to_string())?;
+    }
+
+    let extracted: Vec<fastq::Record> = reads.iter().map(|(_, rec)| rec.clone()).collect();
+    Ok(UmiAnalysis {
+        results: analyse_fastq_records(&extracted, None),
+        summary: summarise_umis(&reads),
+    })
+}
+
+/// Write a random subsample of a FASTQ file, or of both files of a read pair,
+/// into `dest_dir`.
+#[tauri::command]
+pub fn subsample_reads(
+    paths: Vec<PathBuf>,
+    dest_dir: &Path,
+    subsample: Subsample,
+    compress: Option<bool>,
+) -> Result<SubsampleReport, String> {
+    if !dest_dir.is_dir() {
+        return Err(format!("{} is not a directory.", dest_dir.display()));
+    }
+
+    subsample_fastq_files(&paths, dest_dir, &subsample, compress.unwrap_or(true))
+        .map_err(|err| err.to_string())
+}
+
+/// Analyse a file, or only its first records, and keep the results in the
+/// backend, returning a handle to page through them with.
+#[tauri::command]
+pub fn open_results(
+    path: &Path,
+    alphabet: Option<SeqAlphabet>,
+    reference: Option<PathBuf>,
+    max_records: Option<usize>,
+    max_bytes: Option<u64>,
+    store: tauri::State<'_, ResultStore>,
+) -> Result<ResultsHandle, String> {
+    let analysis = analyse_file(
+        path,
+        alphabet,
+        None,
+        None,
+        reference.clone(),
+        max_records,
+        max_bytes,
+    )?;
+    Ok(store.insert(
+        path.to_path_buf(),
+        reference,
+        analysis.results,
+        analysis.is_preview,
+    ))
+}
+
+#[tauri::command]
+pub fn get_results_page(
+    handle: u64,
+    offset: usize,
+    limit: usize,
+    sort: Option<ResultSort>,
+    filter: Option<ResultFilter>,
+    store: tauri::State<'_, ResultStore>,
+) -> Result<ResultsPage, String> {
+    store.page(handle, offset, limit, sort, filter)
+}
+
+/// Count the stored results matching a filter, and list their indices in the
+/// requested order.
+#[tauri::command]
+pub fn find_results(
+    handle: u64,
+    sort: Option<ResultSort>,
+    filter: Option<ResultFilter>,
+    store: tauri::State<'_, ResultStore>,
+) -> Result<ResultMatches, String> {
+    store.find(handle, sort, filter)
+}
+
+/// The sequence, qualities, ORFs and adapter position of one stored record,
+/// read back from its file.
+#[tauri::command]
+pub fn get_record(
+    handle: u64,
+    index: usize,
+    store: tauri::State<'_, ResultStore>,
+) -> Result<SeqRecordDetail, String> {
+    let (path, reference) = store.source(handle)?;
+    let no_record = || format!("{} has no record at index {}.", path.display(), index);
+
+    let lookup = record_lookup(&store, handle, &path, reference.as_deref())?;
+    let records = match lookup.as_deref() {
+        Some(RecordLookup::Indexed(seq_index)) => {
+            return read_indexed_record(&path, seq_index, None, Some(index))
+                .map_err(|err| err.to_string())
+        }
+        Some(RecordLookup::Offsets(offsets)) => {
+            let offset = *offsets.get(index).ok_or_else(no_record)?;
+            load_record_at_offset(&path, offset)
+        }
+        Some(RecordLookup::CramSlices(slices)) => {
+            // The record is in the last slice starting at or before it
+            let after = slices.partition_point(|slice| slice.first_record <= index);
+            let slice = after
+                .checked_sub(1)
+                .map(|i| &slices[i])
+                .ok_or_else(no_record)?;
+            load_cram_slice(&path, reference.as_deref(), slice).map(|records| {
+                SeqRecords::Fastq(
+                    records
+                        .into_iter()
+                        .skip(index - slice.first_record)
+                        .take(1)
+                        .collect(),
+                )
+            })
+        }
+        None => load_record_at(&path, index, reference.as_deref()),
+    };
+
+    match records.map_err(|err| err.to_string())? {
+        SeqRecords::Fastq(records) => {
+            let rec = records.first().ok_or_else(no_record)?;
+            Ok(record_detail(
+                index,
+                rec.id(),
+                rec.desc(),
+                rec.seq(),
+                Some(rec.qual()),
+            ))
+        }
+        SeqRecords::Fasta(records) => {
+            let rec = records.first().ok_or_else(no_record)?;
+            Ok(record_detail(index, rec.id(), rec.desc(), rec.seq(), None))
+        }
+    }
+}
+
+/// Free the results behind a handle once the frontend is done with them.
+#[tauri::command]
+pub fn close_results(handle: u64, store: tauri::State<'_, ResultStore>) -> bool {
+    store.remove(handle)
+}
+
+/// Build the `.fai` index of a FASTA or FASTQ file, and the `.gzi` block index
+/// of a BGZF file, or reuse them if they are up to date.
+#[tauri::command]
+pub fn index_sequence_file(path: &Path) -> Result<SeqIndexSummary, String> {
+    let index = load_or_build_index(path).map_err(|err| err.to_string())?;
+
+    Ok(SeqIndexSummary {
+        n_records: index.entries.len(),
+        fai_path: fai_path(path).to_string_lossy().into_owned(),
+        gzi_path: index
+            .blocks
+            .as_ref()
+            .map(|_| gzi_path(path).to_string_lossy().into_owned()),
+    })
+}
+
+/// Extract a region such as `chr1:100-200` from an indexed file.
+#[tauri::command]
+pub fn fetch_region(path: &Path, region: &str) -> Result<RegionSequence, String> {
+    let index = load_or_build_index(path).map_err(|err| err.to_string())?;
+    let region = parse_region(&index, region)?;
+    let mut reader = IndexedReader::open(path, &index).map_err(|err| err.to_string())?;
+    let (seq, qual) = reader.fetch(&region).map_err(|err| err.to_string())?;
+
+    Ok(RegionSequence {
+        name: region.entry.name.clone(),
+        start: region.start + 1,
+        end: region.end,
+        seq: String::from_utf8_lossy(&seq).into_owned(),
+        qual: qual.map(|qual| String::from_utf8_lossy(&qual).into_owned()),
+    })
+}
+
+/// Jump straight to a record of an indexed file by its name or position.
+#[tauri::command]
+pub fn fetch_indexed_record(
+    path: &Path,
+    name: Option<String>,
+    position: Option<usize>,
+) -> Result<SeqRecordDetail, String> {
+    let index = load_or_build_index(path).map_err(|err| err.to_string())?;
+    read_indexed_record(path, &index, name.as_deref(), position).map_err(|err| err.to_string())
+}
+
+/// Read a whole record through the index of its file.
+fn read_indexed_record(
+    path: &Path,
+    index: &SeqIndex,
+    name: Option<&str>,
+    position: Option<usize>,
+) -> Result<SeqRecordDetail, std::io::Error> {
+    let (position, entry) = find_entry(index, name, position)?;
+    let region = Region {
+        entry,
+        start: 0,
+        end: entry.length,
+    };
+    let mut reader = IndexedReader::open(path, index)?;
+    let (seq, qual) = reader.fetch(&region)?;
+    let desc = reader.description(entry)?;
+
+    Ok(record_detail(
+        position,
+        &entry.name,
+        desc.as_deref(),
+        &seq,
+        qual.as_deref(),
+    ))
+}
+
+/// How to find the records of a stored file, worked out once per handle. FASTA
+/// and FASTQ files are read through their `.fai` index when one has been built,
+/// CRAM files a slice at a time, and other files are scanned for where each
+/// record starts.
+fn record_lookup(
+    store: &ResultStore,
+    handle: u64,
+    path: &Path,
+    reference: Option<&Path>,
+) -> Result<Option<Arc<RecordLookup>>, String> {
+    if let Some(lookup) = store.record_lookup(handle)? {
+        return Ok(Some(lookup));
+    }
+    let lookup = find_record_lookup(path, reference)
+        .map_err(|err| err.to_string())?
+        .map(Arc::new);
+    if let Some(lookup) = &lookup {
+        store.cache_record_lookup(handle, Arc::clone(lookup));
+    }
+    Ok(lookup)
+}
+
+fn find_record_lookup(
+    path: &Path,
+    reference: Option<&Path>,
+) -> Result<Option<RecordLookup>, std::io::Error> {
+    match detect_format(path)?.format {
+        SeqFormat::Fastq | SeqFormat::Fasta => {
+            // Indexes are only written when asked for, not as a side effect of viewing
+            if let Some(index) = load_index(path)? {
+                return Ok(Some(RecordLookup::Indexed(index)));
+            }
+        }
+        SeqFormat::Cram => {
+            return Ok(Some(RecordLookup::CramSlices(cram_slices(
+                path, reference,
+            )?)))
+        }
+        _ => {}
+    }
+    Ok(load_record_offsets(path)?.map(RecordLookup::Offsets))
+}
+
+/// Read the records, keeping only a subsample of them when one was asked for.
+fn preview<T, I>(records: I, subsample: Option<&Subsample>) -> Result<Vec<T>, String>
+where
+    I: IntoIterator<Item = T>,
+{
+    match subsample {
+        Some(spec) => subsample_records(records, spec),
+        None => Ok(records.into_iter().collect()),
+    }
+}
+
+fn index_reference(reference: &Path, k: usize, scale: u64) -> Result<KmerIndex, std::io::Error> {
+    let records = load_fasta_records(reference)?;
+    let seqs: Vec<&[u8]> = records
+        .iter()
+        .filter(|rec| rec.check().is_ok())
+        .map(|rec| rec.seq())
+        .collect();
+
+    Ok(build_kmer_index(
+        &sample_name(reference),
+        &reference.canonicalize()?,
+        &seqs,
+        k,
+        scale,
+    ))
 }
 
 #[cfg(test)]
@@ -78,8 +881,16 @@ mod tests {
     use std::io::Write;
 
     use crate::analysis::commands::{
-        analyse_fasta_file, analyse_fasta_sequences, analyse_fastq_file, analyse_fastq_sequences,
+        analyse_batch, analyse_fasta_file, analyse_fasta_sequences, analyse_fastq_file,
+        analyse_fastq_sequences, analyse_file, analyse_flowcell_quality, analyse_kmers,
+        analyse_long_reads, analyse_n_content, analyse_protein_sequences, analyse_sequences,
+        analyse_umis, build_contamination_index, demultiplex, export_fastq_multiqc,
+        export_fastqc_data, fetch_indexed_record, fetch_region, find_record_lookup,
+        index_sequence_file, read_indexed_record, screen_contamination, subsample_reads,
     };
+    use crate::analysis::profiles::builtin_qc_profiles;
+    use crate::analysis::store::RecordLookup;
+    use crate::models::{self, SeqFormat, SeqResults, Subsample, SubsampleSize, UmiSource};
 
     fn create_test_fq_file<'a>(path: &'a std::path::Path) -> std::io::Result<()> {
         let mut fqs_str: String = "@id description\nATAT\n+\n!!!!\n".to_owned();
@@ -137,7 +948,7 @@ mod tests {
         let mut fqs_str = "@id description\nATAT\n+\n!!!!\n".to_owned();
         fqs_str.push_str("@id description\nGCGC\n+\n!!!!\n");
 
-        let results = analyse_fastq_sequences(fqs_str.as_str());
+        let results = analyse_fastq_sequences(fqs_str.as_str(), None);
         assert_eq!(results.len(), 2);
     }
 
@@ -145,7 +956,7 @@ mod tests {
     fn test_missing_fq_sequence() {
         let missing_sequence = "@id description\n\n+\n!!!!\n";
 
-        let results = analyse_fastq_sequences(missing_sequence);
+        let results = analyse_fastq_sequences(missing_sequence, None);
         assert_eq!(results.len(), 1);
         assert!(!results[0].is_valid);
     }
@@ -154,17 +965,93 @@ mod tests {
     fn test_missing_fq_quality() {
         let missing_quality = "@id description\nATAT\n+\n\n";
 
-        let results = analyse_fastq_sequences(missing_quality);
+        let results = analyse_fastq_sequences(missing_quality, None);
         assert_eq!(results.len(), 1);
         assert!(!results[0].is_valid);
     }
 
+    #[test]
+    fn test_analyse_sequences_detects_format() {
+        let fastq = analyse_sequences("@id description\nATAT\n+\n!!!!\n", None);
+        assert!(matches!(fastq, Ok(SeqResults::Fastq(results)) if results.len() == 1));
+
+        let fasta = analyse_sequences(">id description\nATAT\n", None);
+        assert!(matches!(fasta, Ok(SeqResults::Fasta(results)) if results.len() == 1));
+
+        assert!(analyse_sequences("not sequences", None).is_err());
+    }
+
+    #[test]
+    fn test_analyse_file_detects_zipped_fasta() {
+        let test_file_name = std::path::Path::new("test_detect.dat");
+        assert!(create_test_fagz_file(test_file_name).is_ok());
+        let analysis = analyse_file(test_file_name, None, None, None, None, None, None);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(analysis.is_ok());
+        let analysis = analysis.unwrap();
+        assert_eq!(analysis.detected.format, SeqFormat::Fasta);
+        assert_eq!(analysis.detected.compression, models::Compression::Gzip);
+        match analysis.results {
+            SeqResults::Fasta(results) => assert_eq!(results.len(), 20),
+            SeqResults::Fastq(_) => panic!("Expected FASTA results"),
+        }
+    }
+
+    #[test]
+    fn test_analyse_file_reads_sam_as_fastq() {
+        let test_file_name = std::path::Path::new("test_detect.sam");
+        let sam = "@HD\tVN:1.6\nread1\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n";
+        assert!(std::fs::write(test_file_name, sam).is_ok());
+        let analysis = analyse_file(test_file_name, None, None, None, None, None, None);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(analysis.is_ok());
+        let analysis = analysis.unwrap();
+        assert_eq!(analysis.detected.format, SeqFormat::Sam);
+        match analysis.results {
+            SeqResults::Fastq(results) => {
+                assert_eq!(results.len(), 1);
+                assert!(results[0].is_valid);
+                assert_eq!(results[0].phred_score, 160);
+                assert!((results[0].mean_quality - 40.0).abs() < 1e-4);
+                assert!((results[0].expected_errors - 0.0004).abs() < 1e-7);
+            }
+            SeqResults::Fasta(_) => panic!("Expected FASTQ results"),
+        }
+    }
+
+    #[test]
+    fn test_analyse_file_grades_against_profile() {
+        let test_file_name = std::path::Path::new("test_analyse_file_qc.fq");
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        let profile = builtin_qc_profiles().into_iter().next();
+        let graded = analyse_file(
+            test_file_name,
+            None,
+            None,
+            profile.clone(),
+            None,
+            None,
+            None,
+        );
+        let ungraded = analyse_file(test_file_name, None, None, None, None, None, None);
+        let preview = analyse_fastq_file(test_file_name, None, Some(5), None, None, None, profile);
+        assert!(remove_test_file(test_file_name).is_ok());
+        let report = graded.unwrap().qc.unwrap();
+        assert!(!report.verdicts.is_empty());
+        assert!(ungraded.unwrap().qc.is_none());
+        assert!(preview.unwrap().qc.is_some());
+    }
+
     #[test]
     fn test_analyse_fastq_file() {
         let test_file_name = std::path::Path::new("test_fastq.fq");
         assert!(create_test_fq_file(test_file_name).is_ok());
-        let results = analyse_fastq_file(test_file_name);
+        let results = analyse_fastq_file(test_file_name, None, None, None, None, None, None);
         assert!(remove_test_file(test_file_name).is_ok());
+        assert!(results.is_ok());
+        let results = results.unwrap();
+        assert!(!results.is_preview);
+        let results = results.results;
         assert_eq!(results.len(), 20);
         for result in results {
             assert!(result.is_valid)
@@ -176,8 +1063,12 @@ mod tests {
         let test_file_name = std::path::Path::new("test_fastq.fq.gz");
         let test_file_unpacked = std::path::Path::new("test_fastq.fq");
         assert!(create_test_fqgz_file(test_file_name).is_ok());
-        let results = analyse_fastq_file(test_file_name);
+        let results = analyse_fastq_file(test_file_name, None, None, None, None, None, None);
         assert!(remove_test_file(test_file_name).is_ok());
+        assert!(results.is_ok());
+        let results = results.unwrap();
+        assert!(!results.is_preview);
+        let results = results.results;
         assert!(!test_file_unpacked.exists());
         assert_eq!(results.len(), 20);
         for result in results {
@@ -185,12 +1076,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_analyse_fastq_file_preview_continues() {
+        let test_file_name = std::path::Path::new("test_fastq_preview.fq");
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        let preview = analyse_fastq_file(test_file_name, None, Some(5), None, None, None, None);
+        let rest = analyse_fastq_file(test_file_name, None, None, None, Some(5), None, None);
+        let seeked = preview.as_ref().ok().and_then(|preview| {
+            analyse_fastq_file(
+                test_file_name,
+                None,
+                None,
+                None,
+                preview.next_record,
+                preview.next_offset,
+                None,
+            )
+            .ok()
+        });
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(preview.is_ok());
+        let preview = preview.unwrap();
+        assert!(preview.is_preview);
+        assert_eq!(preview.results.len(), 5);
+        assert_eq!(preview.next_record, Some(5));
+        assert!(preview.next_offset.is_some());
+        assert!(rest.is_ok());
+        let rest = rest.unwrap();
+        assert!(!rest.is_preview);
+        assert_eq!(rest.start_record, 5);
+        assert_eq!(rest.results.len(), 15);
+        assert_eq!(rest.results[0].id, "id6");
+        assert_eq!(seeked, Some(rest));
+    }
+
     #[test]
     fn test_analyse_fasta_sequences() {
         let mut fas_str = ">id description\nATAT\n".to_owned();
         fas_str.push_str(">id description\nGCGC\n");
 
-        let results = analyse_fasta_sequences(fas_str.as_str());
+        let results = analyse_fasta_sequences(fas_str.as_str(), None);
         assert_eq!(results.len(), 2);
     }
 
@@ -198,7 +1123,7 @@ mod tests {
     fn test_missing_fa_sequence() {
         let missing_sequence = ">id description\n";
 
-        let results = analyse_fasta_sequences(missing_sequence);
+        let results = analyse_fasta_sequences(missing_sequence, None);
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].seq_len, 0);
         assert!(results[0].is_valid)
@@ -208,8 +1133,12 @@ mod tests {
     fn test_analyse_fasta_file() {
         let test_file_name = std::path::Path::new("test_fastq.fa");
         assert!(create_test_fa_file(test_file_name).is_ok());
-        let results = analyse_fasta_file(test_file_name);
+        let results = analyse_fasta_file(test_file_name, None, None, None, None, None, None);
         assert!(remove_test_file(test_file_name).is_ok());
+        assert!(results.is_ok());
+        let results = results.unwrap();
+        assert!(!results.is_preview);
+        let results = results.results;
         assert_eq!(results.len(), 20);
         for result in results {
             assert!(result.is_valid)
@@ -221,12 +1150,402 @@ mod tests {
         let test_file_name = std::path::Path::new("test_fasta.fa.gz");
         let test_file_unpacked = std::path::Path::new("test_fasta.fa");
         assert!(create_test_fagz_file(test_file_name).is_ok());
-        let results = analyse_fasta_file(test_file_name);
+        let results = analyse_fasta_file(test_file_name, None, None, None, None, None, None);
         assert!(remove_test_file(test_file_name).is_ok());
+        assert!(results.is_ok());
+        let results = results.unwrap();
+        assert!(!results.is_preview);
+        let results = results.results;
         assert!(!test_file_unpacked.exists());
         assert_eq!(results.len(), 20);
         for result in results {
             assert!(result.is_valid)
         }
     }
+
+    #[test]
+    fn test_export_fastq_multiqc() {
+        let test_file_name = std::path::Path::new("test_mqc.fq");
+        let dest_dir = std::env::temp_dir();
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        let written = export_fastq_multiqc(test_file_name, dest_dir.as_path());
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(written.is_ok());
+        let written = written.unwrap();
+        assert_eq!(written.len(), 4);
+        for path in written {
+            assert!(path
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("test_mqc_"));
+            assert!(remove_test_file(path.as_path()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_export_fastqc_data() {
+        let test_file_name = std::path::Path::new("test_fastqc.fq");
+        let dest_dir = std::env::temp_dir();
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        let written = export_fastqc_data(test_file_name, dest_dir.as_path(), None);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(written.is_ok());
+        let written = written.unwrap();
+        assert!(written.ends_with("test_fastqc_fastqc/fastqc_data.txt"));
+        let contents = std::fs::read_to_string(&written).unwrap();
+        assert!(contents.starts_with("##FastQC"));
+        assert!(contents.contains(">>Per base sequence quality\tfail"));
+        assert!(std::fs::remove_dir_all(written.parent().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_analyse_batch_isolates_file_errors() {
+        let test_file_name = std::path::PathBuf::from("test_batch.fq");
+        let fasta_file_name = std::path::PathBuf::from("test_batch.fa");
+        let missing_file_name = std::path::PathBuf::from("test_batch_missing.fq");
+        assert!(create_test_fq_file(test_file_name.as_path()).is_ok());
+        assert!(std::fs::write(&fasta_file_name, ">chr1\nACGT\n>chr2\nACGT\n").is_ok());
+        let results = analyse_batch(
+            vec![
+                test_file_name.clone(),
+                fasta_file_name.clone(),
+                missing_file_name,
+            ],
+            None,
+            None,
+            builtin_qc_profiles().into_iter().next(),
+        );
+        assert!(remove_test_file(test_file_name.as_path()).is_ok());
+        assert!(remove_test_file(fasta_file_name.as_path()).is_ok());
+        assert!(results.is_ok());
+        let results = results.unwrap();
+        assert_eq!(results.len(), 3);
+        // The batch is sorted by path
+        let fasta = results[0].summary.as_ref().unwrap();
+        assert_eq!(fasta.result_type, "fasta");
+        assert_eq!(fasta.dup_rate, 0.5);
+        let fastq = results[1].summary.as_ref().unwrap();
+        assert_eq!(fastq.n_records, 20);
+        assert_eq!(fastq.result_type, "fastq");
+        assert!(results[1].error.is_none());
+        assert!(results[1].qc.is_some());
+        assert!(results[2].summary.is_none());
+        assert!(results[2].error.is_some());
+    }
+
+    #[test]
+    fn test_analyse_long_reads() {
+        let test_file_name = std::path::Path::new("test_long_reads.fq");
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        let summary = analyse_long_reads(test_file_name, None);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(summary.is_ok());
+        let summary = summary.unwrap();
+        assert_eq!(summary.n_reads, 20);
+        assert_eq!(summary.n50, 4);
+        assert!(summary.channels.is_empty());
+    }
+
+    #[test]
+    fn test_analyse_flowcell_quality_skips_plain_ids() {
+        let test_file_name = std::path::Path::new("test_flowcell.fq");
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        let quality = analyse_flowcell_quality(test_file_name);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(quality.is_ok());
+        assert!(quality.unwrap().lanes.is_empty());
+    }
+
+    #[test]
+    fn test_analyse_n_content_of_zipped_fasta() {
+        let test_file_name = std::path::Path::new("test_n_content.fa.gz");
+        assert!(create_test_fagz_file(test_file_name).is_ok());
+        let n_content = analyse_n_content(test_file_name);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert_eq!(n_content, Ok(vec![0.0; 4]));
+    }
+
+    #[test]
+    fn test_analyse_protein_sequences() {
+        let results = analyse_protein_sequences(">p1 kinase\nMKWVTFISLL\n>p2\nGG\n");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].desc, "kinase");
+        assert!(results.iter().all(|res| res.is_valid));
+        assert_eq!(results[1].result_type, "protein");
+    }
+
+    #[test]
+    fn test_analyse_kmers() {
+        let test_file_name = std::path::Path::new("test_kmers.fq");
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        let spectrum = analyse_kmers(test_file_name, Some(3));
+        let too_long = analyse_kmers(test_file_name, Some(32));
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(spectrum.is_ok());
+        let spectrum = spectrum.unwrap();
+        // ATA/TAT share a canonical k-mer, as do GCG/CGC
+        assert_eq!(spectrum.distinct_kmers, 2);
+        assert_eq!(spectrum.total_kmers, 40);
+        assert!(too_long.is_err());
+    }
+
+    #[test]
+    fn test_screen_contamination_caches_indexes() {
+        let test_file_name = std::path::Path::new("test_screen.fq");
+        let reference = std::path::Path::new("test_screen_ref.fa");
+        let index_dir = std::env::temp_dir();
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        assert!(std::fs::write(reference, ">ref\nGCGCGCGCGCGCGCGCGCGCGCGC\n").is_ok());
+
+        let report = screen_contamination(
+            test_file_name,
+            vec![reference.to_path_buf()],
+            &index_dir,
+            None,
+            Some(3),
+            None,
+        );
+        let cached = build_contamination_index(reference, &index_dir, Some(3), None);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(remove_test_file(reference).is_ok());
+        assert!(report.is_ok());
+        let report = report.unwrap();
+        assert_eq!(report.hits[0].reference, "test_screen_ref");
+        assert_eq!(report.hits[0].percentage, 95.0);
+        assert!(cached.is_ok());
+        assert!(remove_test_file(&cached.unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_demultiplex_header_barcodes() {
+        let test_file_name = std::path::Path::new("test_demux.fq");
+        let sample_sheet = std::path::Path::new("test_demux.csv");
+        let dest_dir = std::env::temp_dir().join("test_demux");
+        let fastq = "@r1 1:N:0:ACGTAC+GGTTAA\nACGT\n+\nIIII\n\
+                     @r2 1:N:0:TTTTTT+GGTTAA\nACGT\n+\nIIII\n";
+        assert!(std::fs::write(test_file_name, fastq).is_ok());
+        assert!(std::fs::write(sample_sheet, "Sample_ID,index,index2\nS1,ACGTAA,GGTTAA\n").is_ok());
+        assert!(std::fs::create_dir_all(&dest_dir).is_ok());
+
+        let report = demultiplex(test_file_name, sample_sheet, &dest_dir, None, None, None);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(remove_test_file(sample_sheet).is_ok());
+        assert!(std::fs::remove_dir_all(&dest_dir).is_ok());
+        assert!(report.is_ok());
+        let report = report.unwrap();
+        assert_eq!(report.samples[0].n_reads, 1);
+        assert!(report.samples[0].path.ends_with("S1.fastq.gz"));
+        assert_eq!(report.undetermined_barcodes[0].barcode, "TTTTTTGGTTAA");
+    }
+
+    #[test]
+    fn test_analyse_umis_from_sequence() {
+        let test_file_name = std::path::Path::new("test_umis.fq");
+        let dest = std::path::Path::new("test_umis_extracted.fq");
+        let fastq = "@r1\nAAAAGATTACA\n+\nIIIIIIIIIII\n\
+                     @r2\nCCCCGATTACA\n+\nIIIIIIIIIII\n\
+                     @r3\nAAAAGATTACA\n+\nIIIIIIIIIII\n";
+        assert!(std::fs::write(test_file_name, fastq).is_ok());
+
+        let analysis = analyse_umis(
+            test_file_name,
+            UmiSource::Sequence,
+            Some("NNNN".to_owned()),
+            Some(dest.to_path_buf()),
+            None,
+        );
+        let extracted = std::fs::read_to_string(dest);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(remove_test_file(dest).is_ok());
+        assert!(analysis.is_ok());
+        let analysis = analysis.unwrap();
+        assert_eq!(analysis.results[1].id, "r2_CCCC");
+        assert_eq!(analysis.results[1].seq_len, 7);
+        assert_eq!(analysis.summary.distinct_umis, 2);
+        assert!((analysis.summary.umi_dup_rate - 1.0 / 3.0).abs() < 1e-6);
+        assert!(extracted.unwrap().starts_with("@r1_AAAA\nGATTACA\n"));
+
+        let missing_pattern = analyse_umis(test_file_name, UmiSource::Sequence, None, None, None);
+        assert!(missing_pattern.is_err());
+    }
+
+    #[test]
+    fn test_subsample_reads_keeps_mates_in_sync() {
+        let r1 = std::path::Path::new("test_subsample_R1.fq");
+        let r2 = std::path::Path::new("test_subsample_R2.fq");
+        let dest_dir = std::env::temp_dir().join("test_subsample");
+        assert!(create_test_fq_file(r1).is_ok());
+        assert!(create_test_fq_file(r2).is_ok());
+        assert!(std::fs::create_dir_all(&dest_dir).is_ok());
+        let spec = Subsample {
+            size: SubsampleSize::Count(5),
+            seed: 42,
+        };
+
+        let report = subsample_reads(
+            vec![r1.to_path_buf(), r2.to_path_buf()],
+            &dest_dir,
+            spec,
+            Some(false),
+        );
+        let subsampled: Vec<String> = dest_dir
+            .read_dir()
+            .map(|entries| {
+                let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+                paths.sort();
+                paths
+            })
+            .unwrap_or_default()
+            .iter()
+            .map(|path| std::fs::read_to_string(path).unwrap_or_default())
+            .collect();
+        assert!(remove_test_file(r1).is_ok());
+        assert!(remove_test_file(r2).is_ok());
+        assert!(std::fs::remove_dir_all(&dest_dir).is_ok());
+        assert!(report.is_ok());
+        let report = report.unwrap();
+        assert_eq!(report.n_reads, 20);
+        assert_eq!(report.n_sampled, 5);
+        assert!(report.paths[0].ends_with("test_subsample_R1.subsampled.fastq"));
+        assert_eq!(subsampled.len(), 2);
+        assert_eq!(subsampled[0].lines().count(), 20);
+        assert_eq!(subsampled[0], subsampled[1]);
+    }
+
+    #[test]
+    fn test_subsample_reads_rejects_mismatched_mates() {
+        let r1 = std::path::Path::new("test_subsample_mismatch_R1.fq");
+        let r2 = std::path::Path::new("test_subsample_mismatch_R2.fq");
+        let dest_dir = std::env::temp_dir().join("test_subsample_mismatch");
+        assert!(create_test_fq_file(r1).is_ok());
+        assert!(std::fs::write(r2, "@other/2\nATAT\n+\n!!!!\n".repeat(20)).is_ok());
+        assert!(std::fs::create_dir_all(&dest_dir).is_ok());
+        let spec = Subsample {
+            size: SubsampleSize::Count(5),
+            seed: 42,
+        };
+
+        let mismatched = subsample_reads(
+            vec![r1.to_path_buf(), r2.to_path_buf()],
+            &dest_dir,
+            spec,
+            Some(false),
+        );
+        let same_name = subsample_reads(
+            vec![r1.to_path_buf(), dest_dir.join(r1)],
+            &dest_dir,
+            spec,
+            Some(false),
+        );
+        assert!(remove_test_file(r1).is_ok());
+        assert!(remove_test_file(r2).is_ok());
+        assert!(std::fs::remove_dir_all(&dest_dir).is_ok());
+        assert!(mismatched.is_err());
+        assert!(same_name.is_err());
+    }
+
+    #[test]
+    fn test_analyse_file_subsample_preview() {
+        let test_file_name = std::path::Path::new("test_analyse_file_subsample.fq");
+        assert!(create_test_fq_file(test_file_name).is_ok());
+        let spec = Subsample {
+            size: SubsampleSize::Count(3),
+            seed: 1,
+        };
+
+        let analysis = analyse_file(test_file_name, None, Some(spec), None, None, None, None);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(analysis.is_ok());
+        let analysis = analysis.unwrap();
+        assert_eq!(analysis.subsample, Some(spec));
+        match analysis.results {
+            SeqResults::Fastq(results) => assert_eq!(results.len(), 3),
+            SeqResults::Fasta(_) => panic!("expected FASTQ results"),
+        }
+    }
+
+    #[test]
+    fn test_analyse_file_previews_first_records() {
+        let test_file_name = std::path::Path::new("test_analyse_file_preview.fq");
+        assert!(create_test_fq_file(test_file_name).is_ok());
+
+        let preview = analyse_file(test_file_name, None, None, None, None, Some(5), None);
+        let whole = analyse_file(test_file_name, None, None, None, None, Some(20), None);
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(preview.is_ok());
+        let preview = preview.unwrap();
+        assert!(preview.is_preview);
+        assert_eq!(preview.next_record, Some(5));
+        match preview.results {
+            SeqResults::Fastq(results) => assert_eq!(results.len(), 5),
+            SeqResults::Fasta(_) => panic!("expected FASTQ results"),
+        }
+        assert!(whole.is_ok());
+        assert!(!whole.unwrap().is_preview);
+    }
+
+    #[test]
+    fn test_fetch_region_and_record_by_index() {
+        let test_file_name = std::path::Path::new("test_faidx.fq");
+        let fai = std::path::Path::new("test_faidx.fq.fai");
+        assert!(create_test_fq_file(test_file_name).is_ok());
+
+        let summary = index_sequence_file(test_file_name);
+        let region = fetch_region(test_file_name, "id3:2-3");
+        let by_name = fetch_indexed_record(test_file_name, Some("id".to_owned()), None);
+        let by_position = fetch_indexed_record(test_file_name, None, Some(19));
+        let missing = fetch_region(test_file_name, "id21:1-2");
+        assert!(remove_test_file(test_file_name).is_ok());
+        assert!(remove_test_file(fai).is_ok());
+        let summary = summary.unwrap();
+        assert_eq!(summary.n_records, 20);
+        assert!(summary.gzi_path.is_none());
+        let region = region.unwrap();
+        assert_eq!((region.start, region.end), (2, 3));
+        assert_eq!(region.seq, "CG");
+        assert_eq!(region.qual.unwrap(), "!!");
+        let by_name = by_name.unwrap();
+        assert_eq!(by_name.seq, "ATAT");
+        assert_eq!(by_name.desc, "description");
+        let by_position = by_position.unwrap();
+        assert_eq!(by_position.id, "id20");
+        assert_eq!(by_position.phred.unwrap(), vec![0, 0, 0, 0]);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_find_record_lookup_indexes_or_scans() {
+        let plain_name = std::path::Path::new("test_lookup.fq");
+        let fai = std::path::Path::new("test_lookup.fq.fai");
+        let gz_name = std::path::Path::new("test_lookup.fq.gz");
+        assert!(create_test_fq_file(plain_name).is_ok());
+        assert!(create_test_fqgz_file(gz_name).is_ok());
+
+        let unindexed = find_record_lookup(plain_name, None);
+        let indexed = fai.exists();
+        assert!(index_sequence_file(plain_name).is_ok());
+        let plain = find_record_lookup(plain_name, None);
+        let gz = find_record_lookup(gz_name, None);
+        let record = match plain.as_ref() {
+            Ok(Some(RecordLookup::Indexed(index))) => {
+                read_indexed_record(plain_name, index, None, Some(1)).ok()
+            }
+            _ => None,
+        };
+        assert!(remove_test_file(plain_name).is_ok());
+        assert!(remove_test_file(fai).is_ok());
+        assert!(remove_test_file(gz_name).is_ok());
+        assert!(!indexed);
+        assert!(matches!(unindexed, Ok(Some(RecordLookup::Offsets(_)))));
+        let record = record.unwrap();
+        assert_eq!(record.id, "id2");
+        assert_eq!(record.desc, "description");
+        assert_eq!(record.seq, "GCGC");
+        match gz.unwrap() {
+            Some(RecordLookup::Offsets(offsets)) => assert_eq!(offsets.len(), 20),
+            _ => panic!("a gzip file should be scanned for its record offsets"),
+        }
+    }
 }
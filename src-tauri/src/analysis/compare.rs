@@ -0,0 +1,146 @@
+use crate::models::{BatchFileSummary, SampleComparison, SampleOutlier, SeqSummary};
+use std::cmp::Ordering;
+
+/// Modified z-scores beyond this are conventionally treated as outliers.
+pub const DEFAULT_OUTLIER_THRESHOLD: f32 = 3.5;
+
+const METRICS: [&str; 5] = ["reads", "mean_quality", "gc", "dup_rate", "adapter_rate"];
+
+/// Align the summaries of successfully analysed files into a sample-by-metric
+/// matrix and flag samples whose robust z-score exceeds the threshold.
+pub fn compare_summaries(files: &[BatchFileSummary], threshold: f32) -> SampleComparison {
+    let analysed: Vec<(&String, &SeqSummary)> = files
+        .iter()
+        .filter_map(|file| file.summary.as_ref().map(|summary| (&file.sample, summary)))
+        .collect();
+
+    let values: Vec<Vec<f32>> = analysed
+        .iter()
+        .map(|(_, summary)| metric_values(summary))
+        .collect();
+
+    let mut z_scores = vec![vec![0.0; METRICS.len()]; values.len()];
+    for col in 0..METRICS.len() {
+        let column: Vec<f32> = values.iter().map(|row| row[col]).collect();
+        for (row, z_score) in robust_z_scores(&column).into_iter().enumerate() {
+            z_scores[row][col] = z_score;
+        }
+    }
+
+    let mut outliers = Vec::new();
+    for (row, (sample, _)) in analysed.iter().enumerate() {
+        for (col, metric) in METRICS.iter().enumerate() {
+            if z_scores[row][col].abs() > threshold {
+                outliers.push(SampleOutlier {
+                    sample: sample.to_string(),
+                    metric: metric.to_string(),
+                    value: values[row][col],
+                    z_score: z_scores[row][col],
+                });
+            }
+        }
+    }
+
+    SampleComparison {
+        samples: analysed
+            .iter()
+            .map(|(sample, _)| sample.to_string())
+            .collect(),
+        metrics: METRICS.iter().map(|metric| metric.to_string()).collect(),
+        values,
+        z_scores,
+        outliers,
+    }
+}
+
+fn metric_values(summary: &SeqSummary) -> Vec<f32> {
+    vec![
+        summary.n_records as f32,
+        summary.mean_quality,
+        summary.mean_gc * 100.0,
+        summary.dup_rate * 100.0,
+        summary.adapter_rate * 100.0,
+    ]
+}
+
+/// Modified z-scores (Iglewicz & Hoaglin) based on the median absolute
+/// deviation, falling back to the mean absolute deviation when the MAD is zero.
+fn robust_z_scores(values: &[f32]) -> Vec<f32> {
+    let centre = median(values);
+    let deviations: Vec<f32> = values.iter().map(|v| (v - centre).abs()).collect();
+
+    let mad = median(&deviations);
+    let scale = if mad > 0.0 {
+        1.4826 * mad
+    } else {
+        let mean_ad = deviations.iter().sum::<f32>() / deviations.len().max(1) as f32;
+        1.2533 * mean_ad
+    };
+
+    if scale == 0.0 {
+        return vec![0.0; values.len()];
+    }
+    values.iter().map(|v| (v - centre) / scale).collect()
+}
+
+fn median(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_summaries, robust_z_scores, DEFAULT_OUTLIER_THRESHOLD};
+    use crate::models::{BatchFileSummary, SeqSummary};
+
+    fn batch_file(sample: &str, mean_gc: f32) -> BatchFileSummary {
+        BatchFileSummary {
+            path: format!("{}.fq", sample),
+            sample: sample.to_owned(),
+            summary: Some(SeqSummary {
+                n_records: 100,
+                mean_gc,
+                ..Default::default()
+            }),
+            qc: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_robust_z_scores_constant_values() {
+        assert_eq!(robust_z_scores(&[2.0, 2.0, 2.0]), vec![0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_compare_summaries_flags_outlier() {
+        let mut files: Vec<BatchFileSummary> = [0.40, 0.41, 0.42, 0.41, 0.40, 0.70]
+            .iter()
+            .enumerate()
+            .map(|(i, gc)| batch_file(&format!("s{}", i), *gc))
+            .collect();
+        files.push(BatchFileSummary {
+            path: "broken.fq".to_owned(),
+            sample: "broken".to_owned(),
+            summary: None,
+            qc: None,
+            error: Some("No such file".to_owned()),
+        });
+
+        let comparison = compare_summaries(&files, DEFAULT_OUTLIER_THRESHOLD);
+        assert_eq!(comparison.samples.len(), 6);
+        assert_eq!(comparison.values[0].len(), comparison.metrics.len());
+        assert_eq!(comparison.outliers.len(), 1);
+        assert_eq!(comparison.outliers[0].sample, "s5");
+        assert_eq!(comparison.outliers[0].metric, "gc");
+    }
+}
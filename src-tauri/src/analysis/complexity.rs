@@ -0,0 +1,160 @@
+use crate::models::SeqComplexity;
+use bio::utils::TextSlice;
+use std::collections::HashMap;
+
+/// Window size and step used for the DUST score, as in prinseq.
+const DUST_WINDOW: usize = 64;
+const DUST_STEP: usize = 32;
+
+/// Records whose DUST score is above this are reported as low complexity.
+pub const LOW_COMPLEXITY_DUST: f32 = 7.0;
+
+/// Records ending in at least this many Gs are reported as poly-G artefacts.
+pub const POLY_G_MIN_LEN: usize = 10;
+
+pub fn sequence_complexity(seq: TextSlice) -> SeqComplexity {
+    let seq = seq.to_ascii_uppercase();
+
+    SeqComplexity {
+        dust_score: dust_score(&seq),
+        entropy: shannon_entropy(&seq),
+        longest_homopolymer: longest_homopolymer(&seq),
+        longest_dinucleotide_repeat: longest_dinucleotide_repeat(&seq),
+        poly_g_tail: seq.iter().rev().take_while(|base| **base == b'G').count(),
+    }
+}
+
+pub fn is_low_complexity(complexity: &SeqComplexity) -> bool {
+    complexity.dust_score > LOW_COMPLEXITY_DUST
+}
+
+pub fn has_poly_g_tail(complexity: &SeqComplexity) -> bool {
+    complexity.poly_g_tail >= POLY_G_MIN_LEN
+}
+
+/// The highest DUST score of any window in the sequence, scaled from 0 for
+/// diverse sequence to 100 for a homopolymer.
+fn dust_score(seq: &[u8]) -> f32 {
+    if seq.len() < 3 {
+        return 0.0;
+    }
+    if seq.len() <= DUST_WINDOW {
+        return window_dust_score(seq);
+    }
+
+    let mut start = 0;
+    let mut max_score: f32 = 0.0;
+    loop {
+        let end = (start + DUST_WINDOW).min(seq.len());
+        max_score = max_score.max(window_dust_score(&seq[start..end]));
+        if end == seq.len() {
+            break;
+        }
+        start += DUST_STEP;
+    }
+    max_score
+}
+
+fn window_dust_score(window: &[u8]) -> f32 {
+    let n_triplets = window.len().saturating_sub(2);
+    if n_triplets < 2 {
+        return 0.0;
+    }
+
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for triplet in window.windows(3) {
+        *counts.entry(triplet).or_insert(0) += 1;
+    }
+    let score: usize = counts.values().map(|c| c * (c - 1) / 2).sum();
+
+    // A homopolymer scores (n_triplets / 2), so scale that to 100
+    score as f32 / (n_triplets - 1) as f32 * 200.0 / n_triplets as f32
+}
+
+/// Shannon entropy of the base composition in bits, from 0 to 2 for DNA.
+fn shannon_entropy(seq: &[u8]) -> f32 {
+    if seq.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0usize; 256];
+    for base in seq {
+        counts[usize::from(*base)] += 1;
+    }
+    let len = seq.len() as f32;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let freq = *count as f32 / len;
+            -freq * freq.log2()
+        })
+        .sum()
+}
+
+fn longest_homopolymer(seq: &[u8]) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    for (pos, base) in seq.iter().enumerate() {
+        if pos > 0 && seq[pos - 1] == *base {
+            run += 1;
+        } else {
+            run = 1;
+        }
+        longest = longest.max(run);
+    }
+    longest
+}
+
+/// The most tandem copies of a dinucleotide such as `AC`, ignoring
+/// homopolymers which are counted separately.
+fn longest_dinucleotide_repeat(seq: &[u8]) -> usize {
+    let mut longest = 0;
+    for offset in 0..2 {
+        let mut copies = 0;
+        let mut prev: Option<&[u8]> = None;
+        for unit in seq[offset.min(seq.len())..].chunks_exact(2) {
+            if unit[0] == unit[1] {
+                copies = 0;
+                prev = None;
+                continue;
+            }
+            copies = if prev == Some(unit) { copies + 1 } else { 1 };
+            prev = Some(unit);
+            longest = longest.max(copies);
+        }
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{has_poly_g_tail, is_low_complexity, sequence_complexity};
+
+    #[test]
+    fn test_sequence_complexity_of_repeats() {
+        let homopolymer = sequence_complexity(&[b'A'; 40]);
+        assert_eq!(homopolymer.dust_score, 100.0);
+        assert_eq!(homopolymer.entropy, 0.0);
+        assert_eq!(homopolymer.longest_homopolymer, 40);
+        assert_eq!(homopolymer.longest_dinucleotide_repeat, 0);
+        assert!(is_low_complexity(&homopolymer));
+
+        let repeat = sequence_complexity(b"TTacacacacacGA");
+        assert_eq!(repeat.longest_dinucleotide_repeat, 5);
+        assert_eq!(repeat.longest_homopolymer, 2);
+        assert!(is_low_complexity(&repeat));
+    }
+
+    #[test]
+    fn test_sequence_complexity_of_diverse_sequence() {
+        let complexity =
+            sequence_complexity(b"ATGCGTACGTTAGCCTAGGATCCAGTTGACAGGCTTACGATCAGTCGATGGGGGGGGGGGG");
+        assert!((complexity.entropy - 1.910).abs() < 1e-3);
+        assert_eq!(complexity.poly_g_tail, 12);
+        assert!(has_poly_g_tail(&complexity));
+        assert!(!is_low_complexity(&sequence_complexity(
+            b"ATGCGTACGTTAGCCTAGGATCCAGTTGACAGGCTTACGATCAGTC"
+        )));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::analysis::kmers::{canonical, kmers};
+use crate::models::{ContaminantHit, ContaminationReport};
+use crate::services::kmer_index::KmerIndex;
+use std::path::Path;
+
+/// A read hits a reference when at least this fraction of its sampled k-mers
+/// are in the reference's index.
+pub const MIN_CONTAINMENT: f32 = 0.5;
+
+/// Build a containment index from the sequences of a reference.
+pub fn build_kmer_index(
+    name: &str,
+    reference: &Path,
+    seqs: &[&[u8]],
+    k: usize,
+    scale: u64,
+) -> KmerIndex {
+    let scale = scale.max(1);
+    let mut index_kmers: Vec<u64> = seqs
+        .iter()
+        .flat_map(|seq| sampled_kmers(seq, k, scale))
+        .collect();
+    index_kmers.sort_unstable();
+    index_kmers.dedup();
+
+    KmerIndex {
+        name: name.to_owned(),
+        reference: reference.to_path_buf(),
+        k,
+        scale,
+        kmers: index_kmers,
+    }
+}
+
+/// Report the percentage of reads whose k-mers are contained in each reference.
+/// A read can hit several references, e.g. a conserved rRNA region.
+pub fn screen_reads(seqs: &[&[u8]], indexes: &[KmerIndex]) -> ContaminationReport {
+    let mut hits = vec![0; indexes.len()];
+    let mut n_unmatched = 0;
+    for seq in seqs {
+        let mut matched = false;
+        for (index, n_hits) in indexes.iter().zip(hits.iter_mut()) {
+            let read_kmers = sampled_kmers(seq, index.k, index.scale);
+            if read_kmers.is_empty() {
+                continue;
+            }
+            let n_contained = read_kmers
+                .iter()
+                .filter(|kmer| index.contains(**kmer))
+                .count();
+            if n_contained as f32 / read_kmers.len() as f32 >= MIN_CONTAINMENT {
+                *n_hits += 1;
+                matched = true;
+            }
+        }
+        if !matched {
+            n_unmatched += 1;
+        }
+    }
+
+    let percentage = |n_reads: usize| {
+        if seqs.is_empty() {
+            0.0
+        } else {
+            n_reads as f32 / seqs.len() as f32 * 100.0
+        }
+    };
+    ContaminationReport {
+        n_screened: seqs.len(),
+        hits: indexes
+            .iter()
+            .zip(hits)
+            .map(|(index, n_reads)| ContaminantHit {
+                reference: index.name.clone(),
+                n_reads,
+                percentage: percentage(n_reads),
+            })
+            .collect(),
+        unmatched_percentage: percentage(n_unmatched),
+    }
+}
+
+/// The canonical k-mers of a sequence whose hash falls in the 1/scale sample.
+fn sampled_kmers(seq: &[u8], k: usize, scale: u64) -> Vec<u64> {
+    kmers(seq, k)
+        .map(|(_, kmer)| canonical(kmer, k))
+        .filter(|kmer| scale <= 1 || mix_hash(*kmer) % scale == 0)
+        .collect()
+}
+
+/// The splitmix64 finaliser, so sampling does not favour any sequence content.
+pub fn mix_hash(kmer: u64) -> u64 {
+    let mut hash = kmer.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_kmer_index, screen_reads};
+    use bio::alphabets::dna;
+    use std::path::Path;
+
+    const REFERENCE: &[u8] = b"GAGTTTTATCGCTTCCATGACGCAGAAGTTAACACTTTCGGATATTTCTGATGAGTCGAAAAATTATCTTGATAAAGCAGGAATTACTAC\
+        TGCTTGTTTACGAATTAAATCGAAGTGGACTGCTGGCGGAAAATGAGAAAATTCGACCTATCCTTGCGCAGCTCGAGAAGCTCTTACTTT\
+        GCGACCTTTCGCCATCAACTAACGATTCTGTCAAAAACTGACGCGTTGGATGAGGAGAAGTGGCTTAATATGCTTGGCACGTTCGTCAAG\
+        GACTGGTTTAGATATGAGTCACATTTTGTTCATGGTAGAGATTCTCTTGTTGACATTTTAAAAGAGCGTGGATTACTATCTGAGTCCGAT\
+        GCTGTTCAACCACTAATAGGTAAGAAATCATGAGTCAAGTTACTGAACAATCCGTACGTT";
+
+    #[test]
+    fn test_screen_reads_finds_reference_reads_on_either_strand() {
+        let index = build_kmer_index("phix", Path::new("phix.fa"), &[REFERENCE], 21, 1);
+        let reverse_read = dna::revcomp(&REFERENCE[200..350]);
+        let reads: Vec<&[u8]> = vec![
+            &REFERENCE[0..150],
+            &reverse_read,
+            b"ATATATATATATATATATATATATATATATATATATATATATATATATATATATATAT",
+            b"ACGT",
+        ];
+
+        let report = screen_reads(&reads, &[index]);
+        assert_eq!(report.n_screened, 4);
+        assert_eq!(report.hits[0].reference, "phix");
+        assert_eq!(report.hits[0].n_reads, 2);
+        assert_eq!(report.hits[0].percentage, 50.0);
+        assert_eq!(report.unmatched_percentage, 50.0);
+    }
+
+    #[test]
+    fn test_build_kmer_index_samples_by_scale() {
+        let full = build_kmer_index("phix", Path::new("phix.fa"), &[REFERENCE], 21, 1);
+        let sampled = build_kmer_index("phix", Path::new("phix.fa"), &[REFERENCE], 21, 10);
+        assert!(sampled.kmers.len() < full.kmers.len() / 5);
+        assert!(sampled.kmers.iter().all(|kmer| full.contains(*kmer)));
+    }
+}
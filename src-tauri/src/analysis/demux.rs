@@ -0,0 +1,279 @@
+use crate::models::{BarcodeCount, BarcodeLocation, DemuxReport, DemuxSample, SampleSheetEntry};
+use crate::services::io::{create_fastq_writer, read_fastq};
+use bio::io::fastq;
+use std::collections::HashMap;
+use std::io::Error as IOError;
+use std::io::ErrorKind::InvalidInput;
+use std::path::Path;
+
+/// How many of the undetermined barcodes are reported.
+const MAX_UNDETERMINED_BARCODES: usize = 20;
+
+/// Assigns observed barcodes to samples, allowing a number of mismatches.
+pub struct BarcodeMatcher {
+    barcodes: Vec<Vec<u8>>,
+    max_mismatches: usize,
+}
+
+impl BarcodeMatcher {
+    /// Fails if two samples' barcodes are close enough for a read to match both.
+    pub fn new(barcodes: Vec<Vec<u8>>, max_mismatches: usize) -> Result<Self, String> {
+        for (i, first) in barcodes.iter().enumerate() {
+            for second in &barcodes[i + 1..] {
+                if mismatches(first, second) <= 2 * max_mismatches {
+                    return Err(format!(
+                        "Barcodes {} and {} are too similar to allow {} mismatches.",
+                        String::from_utf8_lossy(first),
+                        String::from_utf8_lossy(second),
+                        max_mismatches
+                    ));
+                }
+            }
+        }
+
+        Ok(BarcodeMatcher {
+            barcodes,
+            max_mismatches,
+        })
+    }
+
+    /// The index of the sample whose barcode is within the mismatch tolerance.
+    pub fn assign(&self, observed: &[u8]) -> Option<usize> {
+        self.barcodes
+            .iter()
+            .position(|barcode| mismatches(barcode, observed) <= self.max_mismatches)
+    }
+}
+
+/// Hamming distance, counting missing bases and Ns as mismatches.
+fn mismatches(expected: &[u8], observed: &[u8]) -> usize {
+    let differing = expected
+        .iter()
+        .zip(observed)
+        .filter(|(e, o)| !e.eq_ignore_ascii_case(o) || o.eq_ignore_ascii_case(&b'N'))
+        .count();
+    differing + expected.len().max(observed.len()) - expected.len().min(observed.len())
+}
+
+/// The index reads from a Casava 1.8+ description, e.g. `1:N:0:ACGT+TTGC`,
+/// keeping as many of the `+`-separated indexes as the sample sheet uses.
+pub fn header_barcode(rec: &fastq::Record, n_indexes: usize) -> Option<Vec<u8>> {
+    let barcodes = rec.desc()?.split_whitespace().next()?.rsplit(':').next()?;
+    let barcode: Vec<u8> = barcodes
+        .split('+')
+        .take(n_indexes)
+        .flat_map(|index| index.bytes())
+        .map(|base| base.to_ascii_uppercase())
+        .collect();
+    if barcode.is_empty() {
+        None
+    } else {
+        Some(barcode)
+    }
+}
+
+/// Split a FASTQ file into one file per sample, plus one for reads whose
+/// barcode matches no sample.
+pub fn demultiplex_file(
+    path: &Path,
+    samples: &[SampleSheetEntry],
+    dest_dir: &Path,
+    location: BarcodeLocation,
+    max_mismatches: usize,
+    compress: bool,
+) -> Result<DemuxReport, IOError> {
+    if samples.is_empty() {
+        return Err(IOError::new(
+            InvalidInput,
+            "The sample sheet has no samples.",
+        ));
+    }
+    let barcodes: Vec<Vec<u8>> = match location {
+        // Inline barcodes only ever have a single index
+        BarcodeLocation::Inline => samples
+            .iter()
+            .map(|sample| sample.index.as_bytes().to_vec())
+            .collect(),
+        BarcodeLocation::Header => samples
+            .iter()
+            .map(|sample| {
+                let mut barcode = sample.index.as_bytes().to_vec();
+                barcode.extend(sample.index2.iter().flat_map(|index2| index2.bytes()));
+                barcode
+            })
+            .collect(),
+    };
+    let inline_len = barcodes[0].len();
+    if location == BarcodeLocation::Inline && barcodes.iter().any(|bc| bc.len() != inline_len) {
+        return Err(IOError::new(
+            InvalidInput,
+            "Inline barcodes must all be the same length.",
+        ));
+    }
+    let n_indexes = if samples.iter().any(|sample| sample.index2.is_some()) {
+        2
+    } else {
+        1
+    };
+    let matcher = BarcodeMatcher::new(barcodes.clone(), max_mismatches)
+        .map_err(|err| IOError::new(InvalidInput, err))?;
+
+    let extension = if compress { "fastq.gz" } else { "fastq" };
+    let sample_paths: Vec<_> = samples
+        .iter()
+        .map(|sample| dest_dir.join(format!("{}.{}", sample.sample_id, extension)))
+        .collect();
+    let undetermined_path = dest_dir.join(format!("Undetermined.{}", extension));
+    let mut writers = Vec::new();
+    for sample_path in &sample_paths {
+        writers.push(create_fastq_writer(sample_path, compress)?);
+    }
+    let mut undetermined_writer = create_fastq_writer(&undetermined_path, compress)?;
+
+    let mut counts = vec![0; samples.len()];
+    let mut undetermined: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut n_undetermined = 0;
+    for rec in read_fastq(path)?.records() {
+        let rec = rec.map_err(|err| IOError::new(InvalidInput, err.to_string()))?;
+        let (observed, rec) = match location {
+            BarcodeLocation::Header => (header_barcode(&rec, n_indexes), rec),
+            BarcodeLocation::Inline if rec.seq().len() >= inline_len => {
+                let trimmed = fastq::Record::with_attrs(
+                    rec.id(),
+                    rec.desc(),
+                    &rec.seq()[inline_len..],
+                    &rec.qual()[inline_len.min(rec.qual().len())..],
+                );
+                (Some(rec.seq()[..inline_len].to_ascii_uppercase()), trimmed)
+            }
+            BarcodeLocation::Inline => (None, rec),
+        };
+
+        match observed
+            .as_deref()
+            .and_then(|barcode| matcher.assign(barcode))
+        {
+            Some(sample) => {
+                writers[sample].write_record(&rec)?;
+                counts[sample] += 1;
+            }
+            None => {
+                undetermined_writer.write_record(&rec)?;
+                n_undetermined += 1;
+                *undetermined
+                    .entry(observed.unwrap_or_default())
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+    for writer in writers {
+        writer.finish()?;
+    }
+    undetermined_writer.finish()?;
+
+    let mut undetermined_barcodes: Vec<BarcodeCount> = undetermined
+        .into_iter()
+        .map(|(barcode, n_reads)| BarcodeCount {
+            barcode: String::from_utf8_lossy(&barcode).into_owned(),
+            n_reads,
+        })
+        .collect();
+    undetermined_barcodes.sort_by(|a, b| b.n_reads.cmp(&a.n_reads).then(a.barcode.cmp(&b.barcode)));
+    undetermined_barcodes.truncate(MAX_UNDETERMINED_BARCODES);
+
+    Ok(DemuxReport {
+        samples: samples
+            .iter()
+            .zip(barcodes)
+            .zip(counts)
+            .zip(sample_paths)
+            .map(|(((sample, barcode), n_reads), sample_path)| DemuxSample {
+                sample_id: sample.sample_id.clone(),
+                barcode: String::from_utf8_lossy(&barcode).into_owned(),
+                n_reads,
+                path: sample_path.to_string_lossy().into_owned(),
+            })
+            .collect(),
+        n_undetermined,
+        undetermined_path: undetermined_path.to_string_lossy().into_owned(),
+        undetermined_barcodes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{demultiplex_file, header_barcode, BarcodeMatcher};
+    use crate::models::{BarcodeLocation, SampleSheetEntry};
+    use crate::services::io::load_fastq_records;
+    use bio::io::fastq;
+    use uuid::Uuid;
+
+    fn sample(sample_id: &str, index: &str) -> SampleSheetEntry {
+        SampleSheetEntry {
+            sample_id: sample_id.to_owned(),
+            sample_name: sample_id.to_owned(),
+            index: index.to_owned(),
+            index2: None,
+        }
+    }
+
+    #[test]
+    fn test_barcode_matcher_allows_mismatches() {
+        let matcher = BarcodeMatcher::new(vec![b"AAAAAA".to_vec(), b"CCCCCC".to_vec()], 1);
+        assert!(matcher.is_ok());
+        let matcher = matcher.unwrap();
+        assert_eq!(matcher.assign(b"AAAAAA"), Some(0));
+        assert_eq!(matcher.assign(b"CCACCC"), Some(1));
+        assert_eq!(matcher.assign(b"CCAACC"), None);
+        assert_eq!(matcher.assign(b"AAAAN"), None);
+
+        assert!(BarcodeMatcher::new(vec![b"AAAAAA".to_vec(), b"AAAACC".to_vec()], 1).is_err());
+    }
+
+    #[test]
+    fn test_header_barcode() {
+        let rec = fastq::Record::with_attrs("r1", Some("1:N:0:acgt+TTGC"), b"A", b"I");
+        assert_eq!(header_barcode(&rec, 2), Some(b"ACGTTTGC".to_vec()));
+        assert_eq!(header_barcode(&rec, 1), Some(b"ACGT".to_vec()));
+        let bare = fastq::Record::with_attrs("r1", None, b"A", b"I");
+        assert_eq!(header_barcode(&bare, 1), None);
+    }
+
+    #[test]
+    fn test_demultiplex_file_inline() {
+        // Arrange
+        let source = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq");
+        let fastq = "@r1\nAAAAGATTACA\n+\nIIIIIIIIIII\n\
+                     @r2\nCCCCGATTACA\n+\nIIIIIIIIIII\n\
+                     @r3\nACAAGATTACA\n+\nIIIIIIIIIII\n\
+                     @r4\nGGGGGATTACA\n+\nIIIIIIIIIII\n";
+        std::fs::write(&source, fastq).unwrap();
+        let dest_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir(&dest_dir).unwrap();
+        let samples = vec![sample("S1", "AAAA"), sample("S2", "CCCC")];
+
+        // Act
+        let report = demultiplex_file(
+            &source,
+            &samples,
+            &dest_dir,
+            BarcodeLocation::Inline,
+            1,
+            false,
+        );
+
+        // Assert
+        assert!(report.is_ok());
+        let report = report.unwrap();
+        assert_eq!(report.samples[0].n_reads, 2);
+        assert_eq!(report.samples[1].n_reads, 1);
+        assert_eq!(report.n_undetermined, 1);
+        assert_eq!(report.undetermined_barcodes[0].barcode, "GGGG");
+        let s1 = load_fastq_records(std::path::Path::new(&report.samples[0].path)).unwrap();
+        assert_eq!(s1[0].seq(), b"GATTACA");
+
+        // Clean up
+        assert!(std::fs::remove_file(source).is_ok());
+        assert!(std::fs::remove_dir_all(dest_dir).is_ok());
+    }
+}
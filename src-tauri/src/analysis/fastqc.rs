@@ -0,0 +1,698 @@
+use crate::analysis::analysers::{find_adapter_sequence, ADAPTERS};
+use crate::analysis::illumina::parse_illumina_header;
+use crate::models::{FastqcModule, FastqcThresholds, QcStatus};
+use bio::io::fastq;
+use std::collections::{BTreeMap, HashMap};
+
+const PHRED_OFFSET: u8 = 33;
+const MAX_PHRED: usize = 93;
+// FastQC only tracks duplication for the first 100,000 distinct sequences,
+// and truncates reads longer than 75bp to 50bp before comparing them
+const MAX_TRACKED_SEQUENCES: usize = 100_000;
+const MAX_UNTRUNCATED_LENGTH: usize = 75;
+const TRUNCATED_LENGTH: usize = 50;
+const DUPLICATION_LEVELS: [&str; 16] = [
+    "1", "2", "3", "4", "5", "6", "7", "8", "9", ">10", ">50", ">100", ">500", ">1k", ">5k",
+    ">10k+",
+];
+
+/// Per-file accumulator for the statistics reported by FastQC's modules.
+struct FastqcStats {
+    n_records: usize,
+    n_invalid: usize,
+    gc_bases: usize,
+    total_bases: usize,
+    // Histogram of Phred scores at each position
+    base_quals: Vec<Vec<usize>>,
+    // Counts of G, A, T, C and N at each position
+    base_counts: Vec<[usize; 5]>,
+    seq_quals: BTreeMap<usize, usize>,
+    seq_gcs: Vec<usize>,
+    lengths: BTreeMap<usize, usize>,
+    // Quality sum and base count at each position of each Illumina tile
+    tile_quals: BTreeMap<u32, Vec<(usize, usize)>>,
+    // Occurrences of each tracked sequence, and the reads that were tracked
+    seq_counts: HashMap<Vec<u8>, usize>,
+    n_tracked: usize,
+    // Reads in which each adapter starts at each position
+    adapter_starts: Vec<[usize; 4]>,
+}
+
+impl FastqcStats {
+    fn from_records(records: &[fastq::Record]) -> Self {
+        let mut stats = FastqcStats {
+            n_records: records.len(),
+            n_invalid: 0,
+            gc_bases: 0,
+            total_bases: 0,
+            base_quals: Vec::new(),
+            base_counts: Vec::new(),
+            seq_quals: BTreeMap::new(),
+            seq_gcs: vec![0; 101],
+            lengths: BTreeMap::new(),
+            tile_quals: BTreeMap::new(),
+            seq_counts: HashMap::new(),
+            n_tracked: 0,
+            adapter_starts: Vec::new(),
+        };
+
+        for rec in records {
+            if rec.check().is_err() {
+                stats.n_invalid += 1;
+                continue;
+            }
+            stats.add_record(rec);
+        }
+
+        stats
+    }
+
+    fn add_record(&mut self, rec: &fastq::Record) {
+        let seq = rec.seq();
+        let qual = rec.qual();
+        if self.base_quals.len() < seq.len() {
+            self.base_quals.resize(seq.len(), vec![0; MAX_PHRED + 1]);
+            self.base_counts.resize(seq.len(), [0; 5]);
+            self.adapter_starts.resize(seq.len(), [0; 4]);
+        }
+
+        let mut gc_bases = 0;
+        for (pos, base) in seq.iter().enumerate() {
+            let base_idx = match base.to_ascii_uppercase() {
+                b'G' => 0,
+                b'A' => 1,
+                b'T' => 2,
+                b'C' => 3,
+                _ => 4,
+            };
+            if base_idx == 0 || base_idx == 3 {
+                gc_bases += 1;
+            }
+            self.base_counts[pos][base_idx] += 1;
+        }
+
+        let mut tile_quals = parse_illumina_header(rec.id())
+            .map(|header| self.tile_quals.entry(header.tile).or_default());
+        if let Some(tile_quals) = tile_quals.as_mut() {
+            if tile_quals.len() < qual.len() {
+                tile_quals.resize(qual.len(), (0, 0));
+            }
+        }
+
+        let mut qual_sum = 0;
+        for (pos, q) in qual.iter().enumerate() {
+            let phred = usize::from(q.saturating_sub(PHRED_OFFSET)).min(MAX_PHRED);
+            self.base_quals[pos][phred] += 1;
+            if let Some(tile_quals) = tile_quals.as_mut() {
+                tile_quals[pos].0 += phred;
+                tile_quals[pos].1 += 1;
+            }
+            qual_sum += phred;
+        }
+
+        for (idx, (_, adapter)) in ADAPTERS.iter().enumerate() {
+            if let Some(start) = find_adapter_sequence(seq, adapter) {
+                self.adapter_starts[start][idx] += 1;
+            }
+        }
+        self.track_sequence(seq);
+
+        *self.lengths.entry(seq.len()).or_insert(0) += 1;
+        self.gc_bases += gc_bases;
+        self.total_bases += seq.len();
+        if !seq.is_empty() {
+            self.seq_gcs[(gc_bases * 100 + seq.len() / 2) / seq.len()] += 1;
+            *self.seq_quals.entry(qual_sum / seq.len()).or_insert(0) += 1;
+        }
+    }
+
+    fn track_sequence(&mut self, seq: &[u8]) {
+        let seq = if seq.len() > MAX_UNTRUNCATED_LENGTH {
+            &seq[..TRUNCATED_LENGTH]
+        } else {
+            seq
+        };
+        if let Some(count) = self.seq_counts.get_mut(seq) {
+            *count += 1;
+        } else if self.seq_counts.len() < MAX_TRACKED_SEQUENCES {
+            self.seq_counts.insert(seq.to_vec(), 1);
+        } else {
+            return;
+        }
+        self.n_tracked += 1;
+    }
+
+    fn n_valid(&self) -> usize {
+        self.n_records - self.n_invalid
+    }
+}
+
+/// Compute the FastQC report modules for a set of FASTQ records.
+pub fn fastqc_modules(
+    filename: &str,
+    records: &[fastq::Record],
+    thresholds: &FastqcThresholds,
+) -> Vec<FastqcModule> {
+    let stats = FastqcStats::from_records(records);
+
+    let mut modules = vec![
+        basic_statistics(filename, &stats),
+        per_base_quality(&stats, thresholds),
+    ];
+    // Like FastQC, leave the tile module out when the IDs carry no tiles
+    if !stats.tile_quals.is_empty() {
+        modules.push(per_tile_quality(&stats, thresholds));
+    }
+    modules.extend(vec![
+        per_sequence_quality(&stats, thresholds),
+        per_base_content(&stats, thresholds),
+        per_sequence_gc(&stats, thresholds),
+        per_base_n_content(&stats, thresholds),
+        length_distribution(&stats),
+        duplication_levels(&stats, thresholds),
+        overrepresented_sequences(&stats, thresholds),
+        adapter_content(&stats, thresholds),
+    ]);
+    modules
+}
+
+fn basic_statistics(filename: &str, stats: &FastqcStats) -> FastqcModule {
+    let min_len = stats.lengths.keys().next().copied().unwrap_or(0);
+    let max_len = stats.lengths.keys().last().copied().unwrap_or(0);
+    let seq_length = if min_len == max_len {
+        min_len.to_string()
+    } else {
+        format!("{}-{}", min_len, max_len)
+    };
+    let gc_percent = (stats.gc_bases * 100 + stats.total_bases / 2)
+        .checked_div(stats.total_bases)
+        .unwrap_or(0);
+
+    let rows = vec![
+        ("Filename", filename.to_owned()),
+        ("File type", "Conventional base calls".to_owned()),
+        ("Encoding", "Sanger / Illumina 1.9".to_owned()),
+        ("Total Sequences", stats.n_records.to_string()),
+        (
+            "Sequences flagged as poor quality",
+            stats.n_invalid.to_string(),
+        ),
+        ("Sequence length", seq_length),
+        ("%GC", gc_percent.to_string()),
+    ];
+
+    FastqcModule {
+        name: "Basic Statistics".to_owned(),
+        status: QcStatus::Pass,
+        columns: vec!["Measure".to_owned(), "Value".to_owned()],
+        rows: rows
+            .into_iter()
+            .map(|(measure, value)| vec![measure.to_owned(), value])
+            .collect(),
+    }
+}
+
+fn per_base_quality(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    let mut status = QcStatus::Pass;
+    let mut rows = Vec::new();
+    for (pos, hist) in stats.base_quals.iter().enumerate() {
+        let total: usize = hist.iter().sum();
+        let weighted: usize = hist.iter().enumerate().map(|(q, n)| q * n).sum();
+        let mean = weighted as f32 / total as f32;
+        let median = percentile(hist, total, 0.5);
+        let lower_quartile = percentile(hist, total, 0.25);
+
+        status = status
+            .max(QcStatus::from_lower_limits(
+                lower_quartile as f32,
+                thresholds.base_lower_quartile_warn,
+                thresholds.base_lower_quartile_fail,
+            ))
+            .max(QcStatus::from_lower_limits(
+                median as f32,
+                thresholds.base_median_warn,
+                thresholds.base_median_fail,
+            ));
+
+        rows.push(vec![
+            (pos + 1).to_string(),
+            format!("{:.1}", mean),
+            format!("{:.1}", median as f32),
+            format!("{:.1}", lower_quartile as f32),
+            format!("{:.1}", percentile(hist, total, 0.75) as f32),
+            format!("{:.1}", percentile(hist, total, 0.1) as f32),
+            format!("{:.1}", percentile(hist, total, 0.9) as f32),
+        ]);
+    }
+
+    FastqcModule {
+        name: "Per base sequence quality".to_owned(),
+        status,
+        columns: to_columns(&[
+            "Base",
+            "Mean",
+            "Median",
+            "Lower Quartile",
+            "Upper Quartile",
+            "10th Percentile",
+            "90th Percentile",
+        ]),
+        rows,
+    }
+}
+
+fn per_tile_quality(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    // Each tile's mean quality is reported relative to the mean of all tiles
+    let mut totals: Vec<(usize, usize)> = Vec::new();
+    for tile_quals in stats.tile_quals.values() {
+        if totals.len() < tile_quals.len() {
+            totals.resize(tile_quals.len(), (0, 0));
+        }
+        for (pos, (qual_sum, n_bases)) in tile_quals.iter().enumerate() {
+            totals[pos].0 += qual_sum;
+            totals[pos].1 += n_bases;
+        }
+    }
+
+    let mut max_deviation: f32 = 0.0;
+    let mut rows = Vec::new();
+    for (tile, tile_quals) in &stats.tile_quals {
+        for (pos, (qual_sum, n_bases)) in tile_quals.iter().enumerate() {
+            if *n_bases == 0 {
+                continue;
+            }
+            let mean = *qual_sum as f32 / *n_bases as f32;
+            let difference = mean - totals[pos].0 as f32 / totals[pos].1 as f32;
+            max_deviation = max_deviation.max(-difference);
+            rows.push(vec![
+                tile.to_string(),
+                (pos + 1).to_string(),
+                difference.to_string(),
+            ]);
+        }
+    }
+
+    FastqcModule {
+        name: "Per tile sequence quality".to_owned(),
+        status: QcStatus::from_upper_limits(
+            max_deviation,
+            thresholds.tile_deviation_warn,
+            thresholds.tile_deviation_fail,
+        ),
+        columns: to_columns(&["Tile", "Base", "Mean"]),
+        rows,
+    }
+}
+
+fn per_sequence_quality(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    // FastQC grades on the most frequently observed mean quality
+    let mode = stats
+        .seq_quals
+        .iter()
+        .max_by_key(|(_, count)| **count)
+        .map(|(qual, _)| *qual)
+        .unwrap_or(0);
+
+    FastqcModule {
+        name: "Per sequence quality scores".to_owned(),
+        status: QcStatus::from_lower_limits(
+            mode as f32,
+            thresholds.sequence_quality_warn,
+            thresholds.sequence_quality_fail,
+        ),
+        columns: to_columns(&["Quality", "Count"]),
+        rows: stats
+            .seq_quals
+            .iter()
+            .map(|(qual, count)| vec![qual.to_string(), format!("{:.1}", *count as f32)])
+            .collect(),
+    }
+}
+
+fn per_base_content(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    let mut max_difference: f32 = 0.0;
+    let mut rows = Vec::new();
+    for (pos, counts) in stats.base_counts.iter().enumerate() {
+        let called: usize = counts[..4].iter().sum();
+        let percents: Vec<f32> = counts[..4].iter().map(|n| percent(*n, called)).collect();
+        max_difference = max_difference
+            .max((percents[0] - percents[3]).abs())
+            .max((percents[1] - percents[2]).abs());
+
+        let mut row = vec![(pos + 1).to_string()];
+        row.extend(percents.iter().map(|p| p.to_string()));
+        rows.push(row);
+    }
+
+    FastqcModule {
+        name: "Per base sequence content".to_owned(),
+        status: QcStatus::from_upper_limits(
+            max_difference,
+            thresholds.base_content_warn,
+            thresholds.base_content_fail,
+        ),
+        columns: to_columns(&["Base", "G", "A", "T", "C"]),
+        rows,
+    }
+}
+
+fn per_sequence_gc(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    // Compare the observed distribution to a normal distribution with the same mean and sd
+    let total: usize = stats.seq_gcs.iter().sum();
+    let mut deviation = 0.0;
+    if total > 0 {
+        let n = total as f64;
+        let mean = stats
+            .seq_gcs
+            .iter()
+            .enumerate()
+            .map(|(gc, count)| (gc * count) as f64)
+            .sum::<f64>()
+            / n;
+        let variance = stats
+            .seq_gcs
+            .iter()
+            .enumerate()
+            .map(|(gc, count)| (gc as f64 - mean).powi(2) * *count as f64)
+            .sum::<f64>()
+            / n;
+        let sd = variance.sqrt().max(1.0);
+
+        let theoretical: Vec<f64> = (0..stats.seq_gcs.len())
+            .map(|gc| (-(gc as f64 - mean).powi(2) / (2.0 * sd * sd)).exp())
+            .collect();
+        let scale = n / theoretical.iter().sum::<f64>();
+        deviation = stats
+            .seq_gcs
+            .iter()
+            .zip(theoretical.iter())
+            .map(|(observed, expected)| (*observed as f64 - expected * scale).abs())
+            .sum::<f64>()
+            / n
+            * 100.0;
+    }
+
+    FastqcModule {
+        name: "Per sequence GC content".to_owned(),
+        status: QcStatus::from_upper_limits(
+            deviation as f32,
+            thresholds.gc_deviation_warn,
+            thresholds.gc_deviation_fail,
+        ),
+        columns: to_columns(&["GC Content", "Count"]),
+        rows: stats
+            .seq_gcs
+            .iter()
+            .enumerate()
+            .map(|(gc, count)| vec![gc.to_string(), format!("{:.1}", *count as f32)])
+            .collect(),
+    }
+}
+
+fn per_base_n_content(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    let mut max_n: f32 = 0.0;
+    let mut rows = Vec::new();
+    for (pos, counts) in stats.base_counts.iter().enumerate() {
+        let n_percent = percent(counts[4], counts.iter().sum());
+        max_n = max_n.max(n_percent);
+        rows.push(vec![(pos + 1).to_string(), n_percent.to_string()]);
+    }
+
+    FastqcModule {
+        name: "Per base N content".to_owned(),
+        status: QcStatus::from_upper_limits(
+            max_n,
+            thresholds.n_content_warn,
+            thresholds.n_content_fail,
+        ),
+        columns: to_columns(&["Base", "N-Count"]),
+        rows,
+    }
+}
+
+fn length_distribution(stats: &FastqcStats) -> FastqcModule {
+    let status = if stats.n_valid() > 0 && stats.lengths.contains_key(&0) {
+        QcStatus::Fail
+    } else if stats.lengths.len() > 1 {
+        QcStatus::Warn
+    } else {
+        QcStatus::Pass
+    };
+
+    FastqcModule {
+        name: "Sequence Length Distribution".to_owned(),
+        status,
+        columns: to_columns(&["Length", "Count"]),
+        rows: stats
+            .lengths
+            .iter()
+            .map(|(len, count)| vec![len.to_string(), format!("{:.1}", *count as f32)])
+            .collect(),
+    }
+}
+
+fn duplication_levels(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    let mut distinct = [0; 16];
+    let mut total = [0; 16];
+    for count in stats.seq_counts.values() {
+        let level = match count {
+            1..=9 => count - 1,
+            10..=49 => 9,
+            50..=99 => 10,
+            100..=499 => 11,
+            500..=999 => 12,
+            1000..=4999 => 13,
+            5000..=9999 => 14,
+            _ => 15,
+        };
+        distinct[level] += 1;
+        total[level] += count;
+    }
+
+    let deduplicated = if stats.n_tracked == 0 {
+        100.0
+    } else {
+        percent(stats.seq_counts.len(), stats.n_tracked)
+    };
+
+    FastqcModule {
+        name: "Sequence Duplication Levels".to_owned(),
+        status: QcStatus::from_lower_limits(
+            deduplicated,
+            thresholds.duplication_warn,
+            thresholds.duplication_fail,
+        ),
+        columns: to_columns(&[
+            "Duplication Level",
+            "Percentage of deduplicated",
+            "Percentage of total",
+        ]),
+        rows: DUPLICATION_LEVELS
+            .iter()
+            .enumerate()
+            .map(|(level, label)| {
+                vec![
+                    label.to_string(),
+                    percent(distinct[level], stats.seq_counts.len()).to_string(),
+                    percent(total[level], stats.n_tracked).to_string(),
+                ]
+            })
+            .collect(),
+    }
+}
+
+fn overrepresented_sequences(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    let mut overrepresented: Vec<(&Vec<u8>, usize)> = stats
+        .seq_counts
+        .iter()
+        .map(|(seq, count)| (seq, *count))
+        .filter(|(_, count)| percent(*count, stats.n_valid()) > thresholds.overrepresented_warn)
+        .collect();
+    overrepresented.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let max_percent = overrepresented
+        .first()
+        .map(|(_, count)| percent(*count, stats.n_valid()))
+        .unwrap_or(0.0);
+
+    FastqcModule {
+        name: "Overrepresented sequences".to_owned(),
+        status: QcStatus::from_upper_limits(
+            max_percent,
+            thresholds.overrepresented_warn,
+            thresholds.overrepresented_fail,
+        ),
+        columns: to_columns(&["Sequence", "Count", "Percentage", "Possible Source"]),
+        rows: overrepresented
+            .into_iter()
+            .map(|(seq, count)| {
+                let source = ADAPTERS
+                    .iter()
+                    .find(|(_, adapter)| find_adapter_sequence(seq, adapter).is_some())
+                    .map(|(name, _)| name.to_string())
+                    .unwrap_or_else(|| "No Hit".to_owned());
+                vec![
+                    String::from_utf8_lossy(seq).into_owned(),
+                    count.to_string(),
+                    percent(count, stats.n_valid()).to_string(),
+                    source,
+                ]
+            })
+            .collect(),
+    }
+}
+
+fn adapter_content(stats: &FastqcStats, thresholds: &FastqcThresholds) -> FastqcModule {
+    // A read counts towards every position from where the adapter starts
+    let mut cumulative = [0; 4];
+    let mut max_percent: f32 = 0.0;
+    let mut rows = Vec::new();
+    for (pos, starts) in stats.adapter_starts.iter().enumerate() {
+        let mut row = vec![(pos + 1).to_string()];
+        for (idx, count) in starts.iter().enumerate() {
+            cumulative[idx] += count;
+            let adapter_percent = percent(cumulative[idx], stats.n_valid());
+            max_percent = max_percent.max(adapter_percent);
+            row.push(adapter_percent.to_string());
+        }
+        rows.push(row);
+    }
+
+    let mut columns = vec!["Position".to_owned()];
+    columns.extend(ADAPTERS.iter().map(|(name, _)| name.to_string()));
+
+    FastqcModule {
+        name: "Adapter Content".to_owned(),
+        status: QcStatus::from_upper_limits(
+            max_percent,
+            thresholds.adapter_warn,
+            thresholds.adapter_fail,
+        ),
+        columns,
+        rows,
+    }
+}
+
+/// Find the value at the given fraction of a histogram's observations.
+fn percentile(hist: &[usize], total: usize, fraction: f32) -> usize {
+    let target = ((total as f32 * fraction).ceil() as usize).max(1);
+    let mut cumulative = 0;
+    for (value, count) in hist.iter().enumerate() {
+        cumulative += count;
+        if cumulative >= target {
+            return value;
+        }
+    }
+    0
+}
+
+fn percent(count: usize, total: usize) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f32 / total as f32 * 100.0
+}
+
+fn to_columns(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fastqc_modules, percent, percentile};
+    use crate::models::{FastqcThresholds, QcStatus};
+    use bio::io::fastq;
+
+    #[test]
+    fn test_percentile() {
+        let hist = [0, 2, 0, 2];
+        assert_eq!(percentile(&hist, 4, 0.25), 1);
+        assert_eq!(percentile(&hist, 4, 0.5), 1);
+        assert_eq!(percentile(&hist, 4, 0.75), 3);
+    }
+
+    #[test]
+    fn test_fastqc_modules_grade_low_quality_as_fail() {
+        let records = vec![
+            fastq::Record::with_attrs("M1:1:FC:1:1101:10:10", None, b"ACGT", b"!!!!"),
+            fastq::Record::with_attrs("M1:1:FC:1:1102:10:10", None, b"ACGT", b"!!!!"),
+        ];
+
+        let modules = fastqc_modules("test.fq", &records, &FastqcThresholds::default());
+        let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Basic Statistics",
+                "Per base sequence quality",
+                "Per tile sequence quality",
+                "Per sequence quality scores",
+                "Per base sequence content",
+                "Per sequence GC content",
+                "Per base N content",
+                "Sequence Length Distribution",
+                "Sequence Duplication Levels",
+                "Overrepresented sequences",
+                "Adapter Content",
+            ]
+        );
+        assert_eq!(modules[0].rows[3], vec!["Total Sequences", "2"]);
+        assert_eq!(modules[1].status, QcStatus::Fail);
+        assert_eq!(modules[1].rows.len(), 4);
+        assert_eq!(modules[2].status, QcStatus::Pass);
+        assert_eq!(modules[2].rows.len(), 8);
+        assert_eq!(modules[7].status, QcStatus::Pass);
+        // Both reads are the same sequence, so only half remain after deduplication
+        assert_eq!(modules[8].status, QcStatus::Warn);
+        assert_eq!(modules[8].rows[1], vec!["2", "100", "100"]);
+        assert_eq!(modules[9].status, QcStatus::Fail);
+        assert_eq!(modules[9].rows, vec![vec!["ACGT", "2", "100", "No Hit"]]);
+        assert_eq!(modules[10].status, QcStatus::Pass);
+    }
+
+    #[test]
+    fn test_fastqc_modules_grade_adapters_and_bad_tiles() {
+        let adapter_read = b"ACGTAGATCGGAAGAGC";
+        let records = vec![
+            fastq::Record::with_attrs("M1:1:FC:1:1101:10:10", None, adapter_read, &[b'I'; 17]),
+            fastq::Record::with_attrs("M1:1:FC:1:1102:10:10", None, b"ACGT", b"!!!!"),
+            fastq::Record::with_attrs("id3", None, b"TTTT", b"IIII"),
+        ];
+
+        let modules = fastqc_modules("test.fq", &records, &FastqcThresholds::default());
+        assert_eq!(modules.len(), 11);
+        assert_eq!(modules[2].name, "Per tile sequence quality");
+        assert_eq!(modules[2].status, QcStatus::Fail);
+
+        let adapters = &modules[10];
+        assert_eq!(adapters.status, QcStatus::Fail);
+        assert_eq!(adapters.columns[1], "Illumina Universal Adapter");
+        assert_eq!(adapters.rows[3][1], "0");
+        assert_eq!(adapters.rows[4][1], percent(1, 3).to_string());
+        assert_eq!(adapters.rows[16][1], percent(1, 3).to_string());
+    }
+
+    #[test]
+    fn test_fastqc_modules_leave_out_tiles_without_illumina_ids() {
+        let records = vec![fastq::Record::with_attrs("id1", None, b"ACGT", b"IIII")];
+
+        let modules = fastqc_modules("test.fq", &records, &FastqcThresholds::default());
+        assert_eq!(modules.len(), 10);
+        assert!(modules
+            .iter()
+            .all(|module| module.name != "Per tile sequence quality"));
+    }
+
+    #[test]
+    fn test_fastqc_modules_use_custom_thresholds() {
+        let records = vec![fastq::Record::with_attrs("id1", None, b"ACGT", b"5555")];
+        let thresholds = FastqcThresholds {
+            base_median_warn: 10.0,
+            base_median_fail: 5.0,
+            ..Default::default()
+        };
+
+        let modules = fastqc_modules("test.fq", &records, &thresholds);
+        assert_eq!(modules[1].status, QcStatus::Pass);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::analysis::analysers::phred_from_error_probability;
+use crate::models::{FastqSeqResult, FlowcellQuality, IlluminaHeader, LaneQuality, TileQuality};
+use std::collections::BTreeMap;
+
+/// Parse the flowcell position out of an Illumina read ID, in either the
+/// Casava 1.8+ form `instrument:run:flowcell:lane:tile:x:y` or the older
+/// `instrument:lane:tile:x:y#index/read` form.
+pub fn parse_illumina_header(id: &str) -> Option<IlluminaHeader> {
+    // Older IDs carry the index and read number after the y coordinate
+    let id = id.split(|c| c == '#' || c == '/').next().unwrap_or(id);
+    let fields: Vec<&str> = id.split(':').collect();
+
+    let (instrument, run_number, flowcell, position) = match fields.len() {
+        7 => (
+            fields[0],
+            Some(fields[1].parse().ok()?),
+            Some(fields[2].to_owned()),
+            &fields[3..],
+        ),
+        5 => (fields[0], None, None, &fields[1..]),
+        _ => return None,
+    };
+    if instrument.is_empty() {
+        return None;
+    }
+
+    Some(IlluminaHeader {
+        instrument: instrument.to_owned(),
+        run_number,
+        flowcell,
+        lane: position[0].parse().ok()?,
+        tile: position[1].parse().ok()?,
+        x: position[2].parse().ok()?,
+        y: position[3].parse().ok()?,
+    })
+}
+
+/// Mean per-base quality of the valid records in each lane and each tile, to
+/// spot bad tiles or bubbles on the flowcell. Records without an Illumina
+/// header are left out.
+pub fn flowcell_quality(results: &[FastqSeqResult]) -> FlowcellQuality {
+    let mut lanes: BTreeMap<u32, (usize, f64, usize)> = BTreeMap::new();
+    let mut tiles: BTreeMap<(u32, u32), (usize, f64, usize)> = BTreeMap::new();
+    for res in results.iter().filter(|res| res.is_valid) {
+        let header = match &res.illumina {
+            Some(header) => header,
+            None => continue,
+        };
+        for stats in [
+            lanes.entry(header.lane).or_insert((0, 0.0, 0)),
+            tiles
+                .entry((header.lane, header.tile))
+                .or_insert((0, 0.0, 0)),
+        ] {
+            stats.0 += 1;
+            stats.1 += f64::from(res.expected_errors);
+            stats.2 += res.seq_len;
+        }
+    }
+
+    FlowcellQuality {
+        lanes: lanes
+            .into_iter()
+            .map(|(lane, (n_reads, errors, bases))| LaneQuality {
+                lane,
+                n_reads,
+                mean_quality: mean_quality(errors, bases),
+            })
+            .collect(),
+        tiles: tiles
+            .into_iter()
+            .map(|((lane, tile), (n_reads, errors, bases))| TileQuality {
+                lane,
+                tile,
+                n_reads,
+                mean_quality: mean_quality(errors, bases),
+            })
+            .collect(),
+    }
+}
+
+fn mean_quality(errors: f64, bases: usize) -> f32 {
+    if bases == 0 {
+        return 0.0;
+    }
+    phred_from_error_probability(errors / bases as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flowcell_quality, parse_illumina_header};
+    use crate::models::FastqSeqResult;
+
+    #[test]
+    fn test_parse_illumina_header() {
+        let header = parse_illumina_header("EAS139:136:FC706VJ:2:2104:15343:197393").unwrap();
+        assert_eq!(header.instrument, "EAS139");
+        assert_eq!(header.run_number, Some(136));
+        assert_eq!(header.flowcell.as_deref(), Some("FC706VJ"));
+        assert_eq!(header.lane, 2);
+        assert_eq!(header.tile, 2104);
+        assert_eq!(header.y, 197393);
+
+        let old = parse_illumina_header("HWUSI-EAS100R:6:73:941:1973#0/1").unwrap();
+        assert_eq!(old.run_number, None);
+        assert_eq!(old.lane, 6);
+        assert_eq!(old.tile, 73);
+        assert_eq!(old.x, 941);
+
+        assert!(parse_illumina_header("read1").is_none());
+        assert!(parse_illumina_header("a:b:c:d:e").is_none());
+    }
+
+    #[test]
+    fn test_flowcell_quality() {
+        let result = |id: &str, expected_errors: f32| FastqSeqResult {
+            is_valid: true,
+            expected_errors,
+            seq_len: 4,
+            illumina: parse_illumina_header(id),
+            ..Default::default()
+        };
+        let results = vec![
+            result("M1:1:FC1:1:1101:10:10", 0.004),
+            result("M1:1:FC1:1:1101:20:20", 0.004),
+            result("M1:1:FC1:1:1102:10:10", 0.4),
+            result("M1:1:FC1:2:1101:10:10", 0.04),
+            result("read1", 0.0),
+        ];
+
+        let quality = flowcell_quality(&results);
+        assert_eq!(quality.lanes.len(), 2);
+        assert_eq!(quality.lanes[0].n_reads, 3);
+        assert!((quality.lanes[0].mean_quality - 14.685).abs() < 1e-3);
+        assert_eq!(quality.tiles.len(), 3);
+        assert!((quality.tiles[0].mean_quality - 30.0).abs() < 1e-3);
+        assert_eq!(quality.tiles[1].tile, 1102);
+        assert_eq!(quality.tiles[2].lane, 2);
+    }
+}
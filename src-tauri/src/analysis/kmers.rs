@@ -0,0 +1,280 @@
+use crate::models::{EnrichedKmer, GenomeEstimate, KmerSpectrum};
+use std::collections::{BTreeMap, HashMap};
+
+/// The largest k that fits two bits per base in a `u64`.
+pub const MAX_K: usize = 31;
+
+pub const DEFAULT_K: usize = 21;
+
+/// How many of the most frequent k-mers are checked for positional enrichment.
+const ENRICHMENT_CANDIDATES: usize = 1000;
+
+/// K-mers at least this many times over-represented at one position are reported,
+/// as in FastQC's Kmer Content module.
+const MIN_ENRICHMENT: f32 = 5.0;
+
+const MAX_ENRICHED_KMERS: usize = 20;
+
+/// Count the canonical k-mers of the sequences, then summarise their frequency
+/// spectrum, positional enrichment and what the spectrum says about the genome.
+pub fn kmer_spectrum(seqs: &[&[u8]], k: usize) -> KmerSpectrum {
+    assert!(k > 0 && k <= MAX_K, "k must be between 1 and {}", MAX_K);
+
+    let mut canonical_counts: HashMap<u64, u32> = HashMap::new();
+    let mut forward_counts: HashMap<u64, u32> = HashMap::new();
+    for seq in seqs {
+        for (_, kmer) in kmers(seq, k) {
+            *canonical_counts.entry(canonical(kmer, k)).or_insert(0) += 1;
+            *forward_counts.entry(kmer).or_insert(0) += 1;
+        }
+    }
+
+    let mut spectrum = BTreeMap::new();
+    for count in canonical_counts.values() {
+        *spectrum.entry(*count as usize).or_insert(0) += 1;
+    }
+
+    KmerSpectrum {
+        k,
+        total_kmers: canonical_counts.values().map(|count| *count as usize).sum(),
+        distinct_kmers: canonical_counts.len(),
+        genome: estimate_genome(&spectrum, k),
+        enriched: enriched_kmers(seqs, k, &forward_counts),
+        spectrum,
+    }
+}
+
+/// Each k-mer of a sequence with its start position, as a 2-bit encoded integer.
+/// Windows containing anything other than A, C, G or T are skipped.
+pub fn kmers(seq: &[u8], k: usize) -> impl Iterator<Item = (usize, u64)> + '_ {
+    let mask = (1u64 << (2 * k)) - 1;
+    let mut kmer = 0u64;
+    let mut valid_len = 0;
+    seq.iter().enumerate().filter_map(move |(pos, base)| {
+        match encode_base(*base) {
+            Some(code) => {
+                kmer = (kmer << 2 | code) & mask;
+                valid_len += 1;
+            }
+            None => valid_len = 0,
+        }
+        if valid_len >= k {
+            Some((pos + 1 - k, kmer))
+        } else {
+            None
+        }
+    })
+}
+
+fn encode_base(base: u8) -> Option<u64> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+fn decode_kmer(kmer: u64, k: usize) -> String {
+    (0..k)
+        .rev()
+        .map(|i| char::from(b"ACGT"[((kmer >> (2 * i)) & 3) as usize]))
+        .collect()
+}
+
+/// The lesser of a k-mer and its reverse complement, so both strands count as one.
+pub fn canonical(kmer: u64, k: usize) -> u64 {
+    let mut revcomp = 0u64;
+    let mut fwd = kmer;
+    for _ in 0..k {
+        revcomp = revcomp << 2 | (3 - (fwd & 3));
+        fwd >>= 2;
+    }
+    kmer.min(revcomp)
+}
+
+/// Find the most frequent k-mers that pile up at particular positions in the
+/// reads, such as adapters or primers.
+fn enriched_kmers(seqs: &[&[u8]], k: usize, counts: &HashMap<u64, u32>) -> Vec<EnrichedKmer> {
+    let mut by_count: Vec<(&u64, &u32)> = counts.iter().collect();
+    by_count.sort_unstable_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let candidates: HashMap<u64, usize> = by_count
+        .iter()
+        .take(ENRICHMENT_CANDIDATES)
+        .enumerate()
+        .map(|(idx, (kmer, _))| (**kmer, idx))
+        .collect();
+
+    let mut kmers_at: Vec<usize> = Vec::new();
+    let mut candidate_positions: Vec<BTreeMap<usize, usize>> =
+        vec![BTreeMap::new(); candidates.len()];
+    for seq in seqs {
+        for (pos, kmer) in kmers(seq, k) {
+            if kmers_at.len() <= pos {
+                kmers_at.resize(pos + 1, 0);
+            }
+            kmers_at[pos] += 1;
+            if let Some(idx) = candidates.get(&kmer) {
+                *candidate_positions[*idx].entry(pos).or_insert(0) += 1;
+            }
+        }
+    }
+    let total: usize = kmers_at.iter().sum();
+
+    let mut enriched = Vec::new();
+    for (kmer, idx) in &candidates {
+        let count = counts[kmer] as usize;
+        let best = candidate_positions[*idx]
+            .iter()
+            .map(|(pos, observed)| {
+                let expected = count as f32 * kmers_at[*pos] as f32 / total as f32;
+                (*pos, *observed as f32 / expected)
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some((position, ratio)) = best {
+            if ratio >= MIN_ENRICHMENT {
+                enriched.push(EnrichedKmer {
+                    kmer: decode_kmer(*kmer, k),
+                    count,
+                    max_obs_exp: ratio,
+                    max_position: position,
+                });
+            }
+        }
+    }
+    enriched.sort_by(|a, b| {
+        b.max_obs_exp
+            .partial_cmp(&a.max_obs_exp)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.kmer.cmp(&b.kmer))
+    });
+    enriched.truncate(MAX_ENRICHED_KMERS);
+    enriched
+}
+
+/// A rough estimate of genome size and heterozygosity from the homozygous
+/// coverage peak, after the low-multiplicity error k-mers are discarded.
+fn estimate_genome(spectrum: &BTreeMap<usize, usize>, k: usize) -> Option<GenomeEstimate> {
+    // Error k-mers fall away steeply, so the first rise marks the end of them
+    let mut valley = None;
+    let mut prev: Option<(usize, usize)> = None;
+    for (multiplicity, n_kmers) in spectrum {
+        match prev {
+            Some((prev_multiplicity, prev_n_kmers)) if *n_kmers > prev_n_kmers => {
+                valley = Some(prev_multiplicity);
+                break;
+            }
+            _ => prev = Some((*multiplicity, *n_kmers)),
+        }
+    }
+    let valley = valley?;
+
+    let (peak, _) = spectrum
+        .range(valley..)
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))?;
+    let solid_kmers: usize = spectrum
+        .range(valley..)
+        .map(|(multiplicity, n_kmers)| multiplicity * n_kmers)
+        .sum();
+    let genome_size = solid_kmers / peak;
+    if genome_size == 0 {
+        return None;
+    }
+
+    // Each heterozygous site gives 2k distinct k-mers at half coverage
+    let het_kmers: usize = spectrum
+        .range(valley..(peak * 3 / 4).max(valley))
+        .map(|(_, n_kmers)| n_kmers)
+        .sum();
+    let heterozygosity = het_kmers as f32 / (2 * k * genome_size) as f32;
+
+    Some(GenomeEstimate {
+        coverage_peak: *peak,
+        genome_size,
+        heterozygosity: heterozygosity.min(1.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical, decode_kmer, estimate_genome, kmer_spectrum, kmers};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_kmers_skip_ambiguous_bases() {
+        let found: Vec<(usize, String)> = kmers(b"ACGNTGCA", 3)
+            .map(|(pos, kmer)| (pos, decode_kmer(kmer, 3)))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, "ACG".to_owned()),
+                (4, "TGC".to_owned()),
+                (5, "GCA".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_canonical_matches_reverse_complement() {
+        let (_, acg) = kmers(b"ACG", 3).next().unwrap();
+        let (_, cgt) = kmers(b"CGT", 3).next().unwrap();
+        assert_eq!(canonical(acg, 3), canonical(cgt, 3));
+        assert_eq!(canonical(acg, 3), acg);
+    }
+
+    #[test]
+    fn test_kmer_spectrum_counts_both_strands_together() {
+        let seqs: Vec<&[u8]> = vec![b"AAAAC", b"GTTTT"];
+
+        let spectrum = kmer_spectrum(&seqs, 4);
+        assert_eq!(spectrum.total_kmers, 4);
+        assert_eq!(spectrum.distinct_kmers, 2);
+        assert_eq!(spectrum.spectrum.get(&2), Some(&2));
+    }
+
+    #[test]
+    fn test_kmer_spectrum_finds_positional_enrichment() {
+        let mut seqs: Vec<Vec<u8>> = Vec::new();
+        for i in 0..50 {
+            let mut seq = b"ACGTTGCA".to_vec();
+            seq.extend((0..24).map(|j| b"ACGT"[(i * 7 + j * 3 + i / 4 * j) % 4]));
+            seqs.push(seq);
+        }
+        let seqs: Vec<&[u8]> = seqs.iter().map(|seq| seq.as_slice()).collect();
+
+        let spectrum = kmer_spectrum(&seqs, 8);
+        let primer = spectrum
+            .enriched
+            .iter()
+            .find(|enriched| enriched.kmer == "ACGTTGCA");
+        assert!(primer.is_some());
+        assert_eq!(primer.unwrap().max_position, 0);
+        assert_eq!(primer.unwrap().count, 50);
+    }
+
+    #[test]
+    fn test_estimate_genome() {
+        let spectrum = BTreeMap::from([
+            (1, 5000),
+            (2, 400),
+            (3, 50),
+            (4, 20),
+            (5, 60),
+            (10, 80),
+            (19, 200),
+            (20, 1000),
+            (21, 200),
+        ]);
+
+        let estimate = estimate_genome(&spectrum, 21).unwrap();
+        assert_eq!(estimate.coverage_peak, 20);
+        assert_eq!(
+            estimate.genome_size,
+            (4 * 20 + 5 * 60 + 10 * 80 + 19 * 200 + 20 * 1000 + 21 * 200) / 20
+        );
+        assert!(estimate.heterozygosity > 0.0);
+        assert!(estimate_genome(&BTreeMap::from([(1, 10), (2, 5)]), 21).is_none());
+    }
+}
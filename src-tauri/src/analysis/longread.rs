@@ -0,0 +1,186 @@
+use crate::analysis::analysers::{error_probability, phred_from_error_probability};
+use crate::models::{ChannelStats, LongReadSummary, YieldBin};
+use bio::io::fastq;
+use chrono::{DateTime, FixedOffset};
+use std::collections::BTreeMap;
+
+/// Summarise long reads with length-weighted metrics, yield over time from the
+/// Nanopore `start_time=` tag and per-channel statistics from the `ch=` tag.
+pub fn summarise_long_reads(records: &[fastq::Record], bin_minutes: u64) -> LongReadSummary {
+    let valid: Vec<&fastq::Record> = records.iter().filter(|rec| rec.check().is_ok()).collect();
+
+    let mut lengths: Vec<usize> = valid.iter().map(|rec| rec.seq().len()).collect();
+    lengths.sort_unstable();
+    let total_bases: usize = lengths.iter().sum();
+
+    let mut total_error = 0.0;
+    let mut gc_bases = 0;
+    let mut read_qualities = Vec::new();
+    let mut channels: BTreeMap<u32, (usize, usize, f64)> = BTreeMap::new();
+    let mut start_times = Vec::new();
+    for rec in &valid {
+        let read_error: f64 = rec.qual().iter().map(|q| error_probability(*q)).sum();
+        total_error += read_error;
+        gc_bases += rec
+            .seq()
+            .iter()
+            .filter(|b| matches!(b.to_ascii_uppercase(), b'G' | b'C'))
+            .count();
+        if !rec.seq().is_empty() {
+            read_qualities.push(phred_from_error_probability(
+                read_error / rec.seq().len() as f64,
+            ));
+        }
+
+        let tags = header_tags(rec.desc().unwrap_or(""));
+        if let Some(channel) = tags.get("ch").and_then(|ch| ch.parse().ok()) {
+            let stats = channels.entry(channel).or_insert((0, 0, 0.0));
+            stats.0 += 1;
+            stats.1 += rec.seq().len();
+            stats.2 += read_error;
+        }
+        if let Some(start) = tags
+            .get("start_time")
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        {
+            start_times.push((start, rec.seq().len()));
+        }
+    }
+    read_qualities.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    LongReadSummary {
+        n_reads: valid.len(),
+        total_bases,
+        mean_len: ratio(total_bases as f64, lengths.len()) as f32,
+        median_len: lengths.get(lengths.len() / 2).copied().unwrap_or(0),
+        max_len: lengths.last().copied().unwrap_or(0),
+        n50: n50(&lengths),
+        mean_quality: phred_from_error_probability(ratio(total_error, total_bases)),
+        median_read_quality: read_qualities
+            .get(read_qualities.len() / 2)
+            .copied()
+            .unwrap_or(0.0),
+        weighted_gc: ratio(gc_bases as f64, total_bases) as f32,
+        yield_over_time: yield_over_time(&start_times, bin_minutes),
+        channels: channels
+            .into_iter()
+            .map(|(channel, (n_reads, bases, error))| ChannelStats {
+                channel,
+                n_reads,
+                bases,
+                mean_quality: phred_from_error_probability(ratio(error, bases)),
+            })
+            .collect(),
+    }
+}
+
+/// The length such that reads at least this long contain half of all bases.
+/// Expects the lengths sorted in ascending order.
+fn n50(sorted_lengths: &[usize]) -> usize {
+    let total: usize = sorted_lengths.iter().sum();
+    let mut cumulative = 0;
+    for len in sorted_lengths.iter().rev() {
+        cumulative += len;
+        if cumulative * 2 >= total {
+            return *len;
+        }
+    }
+    0
+}
+
+fn yield_over_time(
+    start_times: &[(DateTime<FixedOffset>, usize)],
+    bin_minutes: u64,
+) -> Vec<YieldBin> {
+    let first = match start_times.iter().map(|(start, _)| start).min() {
+        Some(first) => *first,
+        None => return Vec::new(),
+    };
+    let bin_minutes = bin_minutes.max(1);
+
+    let mut bins: BTreeMap<u64, (usize, usize)> = BTreeMap::new();
+    for (start, len) in start_times {
+        let elapsed = (*start - first).num_minutes().max(0) as u64;
+        let bin = bins.entry(elapsed / bin_minutes).or_insert((0, 0));
+        bin.0 += 1;
+        bin.1 += len;
+    }
+
+    let mut cumulative_bases = 0;
+    bins.into_iter()
+        .map(|(bin, (n_reads, bases))| {
+            cumulative_bases += bases;
+            YieldBin {
+                minutes: bin * bin_minutes,
+                n_reads,
+                bases,
+                cumulative_bases,
+            }
+        })
+        .collect()
+}
+
+/// Parse `key=value` tags from a read description, as written by MinKNOW and Guppy.
+fn header_tags(desc: &str) -> BTreeMap<&str, &str> {
+    desc.split_whitespace()
+        .filter_map(|tag| tag.split_once('='))
+        .collect()
+}
+
+fn ratio(numerator: f64, denominator: usize) -> f64 {
+    if denominator == 0 {
+        return 0.0;
+    }
+    numerator / denominator as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{n50, summarise_long_reads};
+    use bio::io::fastq;
+
+    #[test]
+    fn test_n50() {
+        assert_eq!(n50(&[2, 3, 4, 5, 6, 8, 8]), 6);
+        assert_eq!(n50(&[]), 0);
+    }
+
+    #[test]
+    fn test_summarise_long_reads() {
+        let records = vec![
+            fastq::Record::with_attrs(
+                "r1",
+                Some("ch=12 start_time=2023-05-01T10:00:00Z"),
+                b"GGCCAATT",
+                b"++++++++",
+            ),
+            fastq::Record::with_attrs(
+                "r2",
+                Some("ch=12 start_time=2023-05-01T10:30:00Z"),
+                b"ACGT",
+                b"5555",
+            ),
+            fastq::Record::with_attrs(
+                "r3",
+                Some("ch=7 start_time=2023-05-01T11:15:00+00:00"),
+                b"AAAA",
+                b"5555",
+            ),
+        ];
+
+        let summary = summarise_long_reads(&records, 60);
+        assert_eq!(summary.n_reads, 3);
+        assert_eq!(summary.total_bases, 16);
+        assert_eq!(summary.n50, 8);
+        assert_eq!(summary.weighted_gc, 0.375);
+        // Eight Q10 bases and eight Q20 bases average to an error rate of 0.055
+        assert!((summary.mean_quality - 12.596).abs() < 1e-3);
+        assert_eq!(summary.channels.len(), 2);
+        assert_eq!(summary.channels[0].channel, 7);
+        assert_eq!(summary.channels[1].n_reads, 2);
+        assert_eq!(summary.yield_over_time.len(), 2);
+        assert_eq!(summary.yield_over_time[0].bases, 12);
+        assert_eq!(summary.yield_over_time[1].minutes, 60);
+        assert_eq!(summary.yield_over_time[1].cumulative_bases, 16);
+    }
+}
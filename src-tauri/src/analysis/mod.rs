@@ -1,2 +1,17 @@
 pub mod analysers;
+pub mod batch;
 pub mod commands;
+pub mod compare;
+pub mod complexity;
+pub mod contamination;
+pub mod demux;
+pub mod fastqc;
+pub mod illumina;
+pub mod kmers;
+pub mod longread;
+pub mod profiles;
+pub mod protein;
+pub mod store;
+pub mod subsample;
+pub mod summary;
+pub mod umi;
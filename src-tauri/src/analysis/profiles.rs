@@ -0,0 +1,172 @@
+use crate::models::{QcProfile, QcReport, QcStatus, QcVerdict, SeqSummary, ThresholdDirection};
+
+const BUILTIN_PROFILES: [&str; 3] = [
+    include_str!("../../profiles/illumina_short_read.toml"),
+    include_str!("../../profiles/nanopore.toml"),
+    include_str!("../../profiles/amplicon.toml"),
+];
+
+/// The QC profiles bundled with the application.
+pub fn builtin_qc_profiles() -> Vec<QcProfile> {
+    BUILTIN_PROFILES
+        .iter()
+        .map(|profile| toml::from_str(profile).expect("Bundled QC profiles should be valid TOML."))
+        .collect()
+}
+
+/// Grade each metric named in the profile; the overall status is the worst verdict.
+pub fn evaluate_qc_profile(summary: &SeqSummary, profile: &QcProfile) -> QcReport {
+    let mut verdicts = Vec::new();
+    for threshold in &profile.thresholds {
+        let metric = threshold.metric.as_str();
+        let verdict = match summary_metric(summary, metric) {
+            Some(Some(value)) => {
+                let (status, comparison) = match threshold.direction {
+                    ThresholdDirection::Min => (
+                        QcStatus::from_lower_limits(value, threshold.warn, threshold.fail),
+                        "below",
+                    ),
+                    ThresholdDirection::Max => (
+                        QcStatus::from_upper_limits(value, threshold.warn, threshold.fail),
+                        "above",
+                    ),
+                };
+                let reason = match status {
+                    QcStatus::Pass => format!("{} is {}", metric, value),
+                    QcStatus::Warn => format!(
+                        "{} is {}, {} the warning limit of {}",
+                        metric, value, comparison, threshold.warn
+                    ),
+                    QcStatus::Fail => format!(
+                        "{} is {}, {} the failure limit of {}",
+                        metric, value, comparison, threshold.fail
+                    ),
+                };
+                QcVerdict {
+                    metric: metric.to_owned(),
+                    value: Some(value),
+                    status,
+                    reason,
+                }
+            }
+            Some(None) => QcVerdict {
+                metric: metric.to_owned(),
+                value: None,
+                status: QcStatus::Pass,
+                reason: format!("{} does not apply to {} files", metric, summary.result_type),
+            },
+            None => QcVerdict {
+                metric: metric.to_owned(),
+                value: None,
+                status: QcStatus::Warn,
+                reason: format!("{} is not a known summary metric", metric),
+            },
+        };
+        verdicts.push(verdict);
+    }
+
+    QcReport {
+        profile: profile.name.clone(),
+        status: verdicts
+            .iter()
+            .map(|verdict| verdict.status)
+            .max()
+            .unwrap_or(QcStatus::Pass),
+        verdicts,
+    }
+}
+
+/// Look up a metric by name: `None` if it is unknown, `Some(None)` if it does
+/// not apply to this kind of file.
+fn summary_metric(summary: &SeqSummary, metric: &str) -> Option<Option<f32>> {
+    let value = match metric {
+        "n_records" => summary.n_records as f32,
+        "n_valid" => summary.n_valid as f32,
+        "valid_rate" if summary.n_records == 0 => 0.0,
+        "valid_rate" => summary.n_valid as f32 / summary.n_records as f32,
+        "total_bases" => summary.total_bases as f32,
+        "min_len" => summary.min_len as f32,
+        "max_len" => summary.max_len as f32,
+        "mean_len" => summary.mean_len,
+        "mean_gc" => summary.mean_gc,
+        "mean_quality" if summary.result_type != "fastq" => return Some(None),
+        "mean_quality" => summary.mean_quality,
+        "mean_expected_errors" if summary.result_type != "fastq" => return Some(None),
+        "mean_expected_errors" => summary.mean_expected_errors,
+        "dup_rate" => summary.dup_rate,
+        "adapter_rate" => summary.adapter_rate,
+        "mean_entropy" => summary.mean_entropy,
+        "low_complexity_rate" => summary.low_complexity_rate,
+        "poly_g_rate" => summary.poly_g_rate,
+        _ => return None,
+    };
+    Some(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{builtin_qc_profiles, evaluate_qc_profile};
+    use crate::models::{MetricThreshold, QcProfile, QcStatus, SeqSummary, ThresholdDirection};
+
+    #[test]
+    fn test_builtin_qc_profiles_parse() {
+        let profiles = builtin_qc_profiles();
+        assert_eq!(profiles.len(), 3);
+        assert_eq!(profiles[0].name, "Illumina short-read");
+    }
+
+    #[test]
+    fn test_evaluate_qc_profile_grades_metrics() {
+        let summary = SeqSummary {
+            n_records: 100,
+            n_valid: 100,
+            mean_quality: 25.0,
+            adapter_rate: 0.2,
+            ..Default::default()
+        };
+        let profile = QcProfile {
+            name: "test".to_owned(),
+            description: String::new(),
+            thresholds: vec![
+                MetricThreshold {
+                    metric: "valid_rate".to_owned(),
+                    direction: ThresholdDirection::Min,
+                    warn: 0.99,
+                    fail: 0.95,
+                },
+                MetricThreshold {
+                    metric: "mean_quality".to_owned(),
+                    direction: ThresholdDirection::Min,
+                    warn: 28.0,
+                    fail: 20.0,
+                },
+                MetricThreshold {
+                    metric: "adapter_rate".to_owned(),
+                    direction: ThresholdDirection::Max,
+                    warn: 0.05,
+                    fail: 0.1,
+                },
+                MetricThreshold {
+                    metric: "not_a_metric".to_owned(),
+                    direction: ThresholdDirection::Max,
+                    warn: 0.0,
+                    fail: 0.0,
+                },
+            ],
+        };
+
+        let report = evaluate_qc_profile(&summary, &profile);
+        assert_eq!(report.status, QcStatus::Fail);
+        let statuses: Vec<QcStatus> = report.verdicts.iter().map(|v| v.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                QcStatus::Pass,
+                QcStatus::Warn,
+                QcStatus::Fail,
+                QcStatus::Warn
+            ]
+        );
+        assert!(report.verdicts[2].reason.contains("failure limit"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::analysis::analysers::{alphabet_symbols, find_invalid_symbol};
+use crate::models::{ProteinSeqResult, SeqAlphabet};
+use bio::io::fasta;
+use bio::utils::TextSlice;
+use std::collections::BTreeMap;
+
+/// The 20 standard amino acids, in the order used by the tables below.
+const AMINO_ACIDS: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Average masses of the free amino acids in Daltons.
+const AMINO_ACID_MASSES: [f64; 20] = [
+    89.0932, 121.1582, 133.1027, 147.1293, 165.1891, 75.0666, 155.1546, 131.1729, 146.1876,
+    131.1729, 149.2113, 132.1179, 115.1305, 146.1445, 174.201, 105.0926, 119.1192, 117.1463,
+    204.2252, 181.1885,
+];
+const WATER_MASS: f64 = 18.01528;
+
+/// Kyte-Doolittle hydropathy values.
+const HYDROPATHY: [f64; 20] = [
+    1.8, 2.5, -3.5, -3.5, 2.8, -0.4, -3.2, 4.5, -3.9, 3.8, 1.9, -3.5, -1.6, -3.5, -4.5, -0.8, -0.7,
+    4.2, -0.9, -1.3,
+];
+
+/// pKa values of the terminal groups and charged side chains.
+const PK_N_TERM: f64 = 7.5;
+const PK_C_TERM: f64 = 3.55;
+const POSITIVE_PKS: [(u8, f64); 3] = [(b'K', 10.0), (b'R', 12.0), (b'H', 5.98)];
+const NEGATIVE_PKS: [(u8, f64); 4] = [(b'D', 4.05), (b'E', 4.45), (b'C', 9.0), (b'Y', 10.0)];
+
+/// Dipeptide instability weight values from Guruprasad et al. (1990), indexed
+/// by the first and then the second residue.
+#[rustfmt::skip]
+const DIWV: [[f64; 20]; 20] = [
+    // A
+    [1.00, 44.94, -7.49, 1.00, 1.00, 1.00, -7.49, 1.00, 1.00, 1.00, 1.00, 1.00, 20.26, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00],
+    // C
+    [1.00, 1.00, 20.26, 1.00, 1.00, 1.00, 33.60, 1.00, 1.00, 20.26, 33.60, 1.00, 20.26, -6.54, 1.00, 1.00, 33.60, -6.54, 24.68, 1.00],
+    // D
+    [1.00, 1.00, 1.00, 1.00, -6.54, 1.00, 1.00, 1.00, -7.49, 1.00, 1.00, 1.00, 1.00, 1.00, -6.54, 20.26, -14.03, 1.00, 1.00, 1.00],
+    // E
+    [1.00, 44.94, 20.26, 33.60, 1.00, 1.00, -6.54, 20.26, 1.00, 1.00, 1.00, 1.00, 20.26, 20.26, 1.00, 20.26, 1.00, 1.00, -14.03, 1.00],
+    // F
+    [1.00, 1.00, 13.34, 1.00, 1.00, 1.00, 1.00, 1.00, -14.03, 1.00, 1.00, 1.00, 20.26, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 33.60],
+    // G
+    [-7.49, 1.00, 1.00, -6.54, 1.00, 13.34, 1.00, -7.49, -7.49, 1.00, 1.00, -7.49, 1.00, 1.00, 1.00, 1.00, -7.49, 1.00, 13.34, -7.49],
+    // H
+    [1.00, 1.00, 1.00, 1.00, -9.37, -9.37, 1.00, 44.94, 24.68, 1.00, 1.00, 24.68, -1.88, 1.00, 1.00, 1.00, -6.54, 1.00, -1.88, 44.94],
+    // I
+    [1.00, 1.00, 1.00, 44.94, 1.00, 1.00, 13.34, 1.00, -7.49, 20.26, 1.00, 1.00, -1.88, 1.00, 1.00, 1.00, 1.00, -7.49, 1.00, 1.00],
+    // K
+    [1.00, 1.00, 1.00, 1.00, 1.00, -7.49, 1.00, -7.49, 1.00, -7.49, 33.60, 1.00, -6.54, 24.64, 33.60, 1.00, 1.00, -7.49, 1.00, 1.00],
+    // L
+    [1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, -7.49, 1.00, 1.00, 1.00, 20.26, 33.60, 20.26, 1.00, 1.00, 1.00, 24.68, 1.00],
+    // M
+    [13.34, 1.00, 1.00, 1.00, 1.00, 1.00, 58.28, 1.00, 1.00, 1.00, -1.88, 1.00, 44.94, -6.54, -6.54, 44.94, -1.88, 1.00, 1.00, 24.68],
+    // N
+    [1.00, -1.88, 1.00, 1.00, -14.03, -14.03, 1.00, 44.94, 24.68, 1.00, 1.00, 1.00, -1.88, -6.54, 1.00, 1.00, -7.49, 1.00, -9.37, 1.00],
+    // P
+    [20.26, -6.54, -6.54, 18.38, 20.26, 1.00, 1.00, 1.00, 1.00, 1.00, -6.54, 1.00, 20.26, 20.26, -6.54, 20.26, 1.00, 20.26, -1.88, 1.00],
+    // Q
+    [1.00, -6.54, 20.26, 20.26, -6.54, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 20.26, 20.26, 1.00, 44.94, 1.00, -6.54, 1.00, -6.54],
+    // R
+    [1.00, 1.00, 1.00, 1.00, 1.00, -7.49, 20.26, 1.00, 1.00, 1.00, 1.00, 13.34, 20.26, 20.26, 58.28, 44.94, 1.00, 1.00, 58.28, -6.54],
+    // S
+    [1.00, 33.60, 1.00, 20.26, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 1.00, 44.94, 20.26, 20.26, 20.26, 1.00, 1.00, 1.00, 1.00],
+    // T
+    [1.00, 1.00, 1.00, 20.26, 13.34, -7.49, 1.00, 1.00, 1.00, 1.00, 1.00, -14.03, 1.00, -6.54, 1.00, 1.00, 1.00, 1.00, -14.03, 1.00],
+    // V
+    [1.00, 1.00, -14.03, 1.00, 1.00, -7.49, 1.00, 1.00, -1.88, 1.00, 1.00, 1.00, 20.26, 1.00, 1.00, 1.00, -7.49, 1.00, 1.00, -6.54],
+    // W
+    [-14.03, 1.00, 1.00, 1.00, 1.00, -9.37, 24.68, 1.00, 1.00, 13.34, 24.68, 13.34, 1.00, 1.00, 1.00, 1.00, -14.03, -7.49, 1.00, 1.00],
+    // Y
+    [24.68, 1.00, 24.68, -6.54, 1.00, -7.49, 13.34, 1.00, 1.00, 1.00, 44.94, 1.00, 13.34, 1.00, -15.91, 1.00, -7.49, 1.00, -9.37, 13.34],
+];
+
+pub fn analyse_protein_records(records: &Vec<fasta::Record>) -> Vec<ProteinSeqResult> {
+    let symbols = alphabet_symbols(SeqAlphabet::Protein);
+    let mut results = Vec::new();
+
+    for rec in records {
+        if rec.check().is_ok() {
+            let alphabet_error = find_invalid_symbol(rec.seq(), &symbols);
+            let seq: Vec<u8> = rec
+                .seq()
+                .iter()
+                .map(|residue| residue.to_ascii_uppercase())
+                .filter(|residue| *residue != b'*')
+                .collect();
+            results.push(ProteinSeqResult {
+                id: rec.id().to_owned(),
+                desc: rec.desc().unwrap_or("").to_owned(),
+                is_valid: alphabet_error.is_none(),
+                seq_len: seq.len(),
+                molecular_weight: molecular_weight(&seq),
+                isoelectric_point: isoelectric_point(&seq),
+                gravy: gravy(&seq),
+                instability_index: instability_index(&seq),
+                composition: composition(&seq),
+                alphabet_error,
+                ..Default::default()
+            });
+        } else {
+            results.push(ProteinSeqResult {
+                id: "Invalid Record".to_owned(),
+                is_valid: rec.check().is_ok(),
+                ..Default::default()
+            });
+        }
+    }
+
+    results
+}
+
+fn residue_index(residue: u8) -> Option<usize> {
+    AMINO_ACIDS.iter().position(|aa| *aa == residue)
+}
+
+/// Average mass in Daltons, skipping ambiguous residues.
+fn molecular_weight(seq: TextSlice) -> f32 {
+    let masses: Vec<f64> = seq
+        .iter()
+        .filter_map(|residue| residue_index(*residue))
+        .map(|idx| AMINO_ACID_MASSES[idx])
+        .collect();
+    if masses.is_empty() {
+        return 0.0;
+    }
+    // Each peptide bond releases a water molecule
+    (masses.iter().sum::<f64>() - (masses.len() - 1) as f64 * WATER_MASS) as f32
+}
+
+/// The pH at which the net charge is zero, found by bisection.
+fn isoelectric_point(seq: TextSlice) -> f32 {
+    if seq.is_empty() {
+        return 0.0;
+    }
+
+    let (mut low, mut high) = (0.0, 14.0);
+    while high - low > 1e-4 {
+        let mid = (low + high) / 2.0;
+        if net_charge(seq, mid) > 0.0 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    ((low + high) / 2.0) as f32
+}
+
+fn net_charge(seq: TextSlice, ph: f64) -> f64 {
+    let count = |aa: u8| seq.iter().filter(|residue| **residue == aa).count() as f64;
+    let positive = |pk: f64| 1.0 / (1.0 + 10f64.powf(ph - pk));
+    let negative = |pk: f64| 1.0 / (1.0 + 10f64.powf(pk - ph));
+
+    let mut charge = positive(PK_N_TERM) - negative(PK_C_TERM);
+    for (aa, pk) in POSITIVE_PKS {
+        charge += count(aa) * positive(pk);
+    }
+    for (aa, pk) in NEGATIVE_PKS {
+        charge -= count(aa) * negative(pk);
+    }
+    charge
+}
+
+/// Grand average of hydropathy over the standard residues.
+fn gravy(seq: TextSlice) -> f32 {
+    let values: Vec<f64> = seq
+        .iter()
+        .filter_map(|residue| residue_index(*residue))
+        .map(|idx| HYDROPATHY[idx])
+        .collect();
+    if values.is_empty() {
+        return 0.0;
+    }
+    (values.iter().sum::<f64>() / values.len() as f64) as f32
+}
+
+/// Guruprasad instability index; proteins scoring above 40 are likely unstable.
+fn instability_index(seq: TextSlice) -> f32 {
+    if seq.is_empty() {
+        return 0.0;
+    }
+    let score: f64 = seq
+        .windows(2)
+        .filter_map(|pair| Some(DIWV[residue_index(pair[0])?][residue_index(pair[1])?]))
+        .sum();
+    (10.0 / seq.len() as f64 * score) as f32
+}
+
+/// Percentage of the sequence made up by each residue.
+fn composition(seq: TextSlice) -> BTreeMap<char, f32> {
+    let mut counts = BTreeMap::new();
+    for residue in seq {
+        *counts.entry(char::from(*residue)).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(residue, count)| (residue, count as f32 / seq.len() as f32 * 100.0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyse_protein_records, instability_index, isoelectric_point};
+    use bio::io::fasta;
+
+    #[test]
+    fn test_analyse_protein_records() {
+        let records = vec![
+            fasta::Record::with_attrs("pep", None, b"MKWVTFISLL*"),
+            fasta::Record::with_attrs("dna?", None, b"ACGT1"),
+        ];
+
+        let results = analyse_protein_records(&records);
+        assert_eq!(results[0].seq_len, 10);
+        assert!(results[0].is_valid);
+        assert!((results[0].molecular_weight - 1237.55).abs() < 0.01);
+        assert!((results[0].gravy - 1.47).abs() < 1e-4);
+        assert_eq!(results[0].composition.get(&'L'), Some(&20.0));
+        assert!(!results[1].is_valid);
+        assert_eq!(results[1].alphabet_error.as_ref().unwrap().position, 4);
+    }
+
+    #[test]
+    fn test_isoelectric_point() {
+        // Only the termini are charged, so the pI sits between their pKas
+        assert!((isoelectric_point(b"GG") - 5.525).abs() < 1e-3);
+        assert!(isoelectric_point(b"KKKK") > 10.0);
+        assert!(isoelectric_point(b"DDDD") < 4.0);
+    }
+
+    #[test]
+    fn test_instability_index() {
+        // AC scores 44.94 and CA scores 1.0
+        assert!((instability_index(b"ACA") - 153.133).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,612 @@
+use crate::models::{
+    FastaSeqResult, FastqSeqResult, FieldRange, ResultFilter, ResultMatches, ResultSort,
+    ResultsHandle, ResultsPage, SeqComplexity, SeqResults,
+};
+use crate::services::cram::CramSlice;
+use crate::services::faidx::SeqIndex;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+
+/// Result fields that can be sorted, searched and filtered on without knowing
+/// the record type. Nested fields are named by their path, e.g.
+/// `complexity.dust_score`.
+pub trait ResultFields {
+    const NUMERIC_FIELDS: &'static [&'static str];
+
+    fn text_field(&self, field: &str) -> Option<&str>;
+    /// The value of a numeric field, or None when the record does not have it.
+    fn numeric_field(&self, field: &str) -> Option<f64>;
+}
+
+const TEXT_FIELDS: [&str; 2] = ["id", "desc"];
+
+impl ResultFields for FastqSeqResult {
+    const NUMERIC_FIELDS: &'static [&'static str] = &[
+        "gc",
+        "n_orfs",
+        "is_valid",
+        "phred_score",
+        "mean_quality",
+        "expected_errors",
+        "seq_len",
+        "n_count",
+        "ambiguous_count",
+        "longest_n_run",
+        "complexity.dust_score",
+        "complexity.entropy",
+        "complexity.longest_homopolymer",
+        "complexity.longest_dinucleotide_repeat",
+        "complexity.poly_g_tail",
+        "illumina.lane",
+        "illumina.tile",
+        "illumina.x",
+        "illumina.y",
+    ];
+
+    fn text_field(&self, field: &str) -> Option<&str> {
+        match field {
+            "id" => Some(&self.id),
+            "desc" => Some(&self.desc),
+            _ => None,
+        }
+    }
+
+    fn numeric_field(&self, field: &str) -> Option<f64> {
+        if let Some(field) = field.strip_prefix("complexity.") {
+            return complexity_field(&self.complexity, field);
+        }
+        if let Some(field) = field.strip_prefix("illumina.") {
+            let illumina = self.illumina.as_ref()?;
+            return match field {
+                "lane" => Some(f64::from(illumina.lane)),
+                "tile" => Some(f64::from(illumina.tile)),
+                "x" => Some(f64::from(illumina.x)),
+                "y" => Some(f64::from(illumina.y)),
+                _ => None,
+            };
+        }
+        match field {
+            "gc" => Some(f64::from(self.gc)),
+            "n_orfs" => Some(self.n_orfs as f64),
+            "is_valid" => Some(f64::from(u8::from(self.is_valid))),
+            "phred_score" => Some(f64::from(self.phred_score)),
+            "mean_quality" => Some(f64::from(self.mean_quality)),
+            "expected_errors" => Some(f64::from(self.expected_errors)),
+            "seq_len" => Some(self.seq_len as f64),
+            "n_count" => Some(self.n_count as f64),
+            "ambiguous_count" => Some(self.ambiguous_count as f64),
+            "longest_n_run" => Some(self.longest_n_run as f64),
+            _ => None,
+        }
+    }
+}
+
+impl ResultFields for FastaSeqResult {
+    const NUMERIC_FIELDS: &'static [&'static str] = &[
+        "gc",
+        "n_orfs",
+        "is_valid",
+        "seq_len",
+        "n_count",
+        "ambiguous_count",
+        "longest_n_run",
+        "complexity.dust_score",
+        "complexity.entropy",
+        "complexity.longest_homopolymer",
+        "complexity.longest_dinucleotide_repeat",
+        "complexity.poly_g_tail",
+    ];
+
+    fn text_field(&self, field: &str) -> Option<&str> {
+        match field {
+            "id" => Some(&self.id),
+            "desc" => Some(&self.desc),
+            _ => None,
+        }
+    }
+
+    fn numeric_field(&self, field: &str) -> Option<f64> {
+        if let Some(field) = field.strip_prefix("complexity.") {
+            return complexity_field(&self.complexity, field);
+        }
+        match field {
+            "gc" => Some(f64::from(self.gc)),
+            "n_orfs" => Some(self.n_orfs as f64),
+            "is_valid" => Some(f64::from(u8::from(self.is_valid))),
+            "seq_len" => Some(self.seq_len as f64),
+            "n_count" => Some(self.n_count as f64),
+            "ambiguous_count" => Some(self.ambiguous_count as f64),
+            "longest_n_run" => Some(self.longest_n_run as f64),
+            _ => None,
+        }
+    }
+}
+
+fn complexity_field(complexity: &SeqComplexity, field: &str) -> Option<f64> {
+    match field {
+        "dust_score" => Some(f64::from(complexity.dust_score)),
+        "entropy" => Some(f64::from(complexity.entropy)),
+        "longest_homopolymer" => Some(complexity.longest_homopolymer as f64),
+        "longest_dinucleotide_repeat" => Some(complexity.longest_dinucleotide_repeat as f64),
+        "poly_g_tail" => Some(complexity.poly_g_tail as f64),
+        _ => None,
+    }
+}
+
+/// The results of one file, with the order of the last view asked for so that
+/// paging through it does not sort again.
+struct StoredResults {
+    path: PathBuf,
+    // The FASTA file aligned CRAM reads are rebuilt from
+    reference: Option<PathBuf>,
+    results: SeqResults,
+    view: Option<(Option<ResultSort>, Option<ResultFilter>, Vec<usize>)>,
+    // How to find each record in the file, worked out the first time one is read back
+    record_lookup: Option<Arc<RecordLookup>>,
+}
+
+/// How the records of a stored file are found when reading them back.
+pub enum RecordLookup {
+    /// The `.fai` index of a FASTA or FASTQ file, read one region at a time.
+    Indexed(SeqIndex),
+    /// Where each record starts, for files that cannot be indexed.
+    Offsets(Vec<u64>),
+    /// Where each slice of a CRAM file starts, as its records are decoded together.
+    CramSlices(Vec<CramSlice>),
+}
+
+/// Analysis results kept in the backend, so the frontend only fetches the
+/// records it is showing.
+#[derive(Default)]
+pub struct ResultStore {
+    next_handle: AtomicU64,
+    entries: Mutex<HashMap<u64, StoredResults>>,
+}
+
+impl ResultStore {
+    pub fn insert(
+        &self,
+        path: PathBuf,
+        reference: Option<PathBuf>,
+        results: SeqResults,
+        is_preview: bool,
+    ) -> ResultsHandle {
+        let handle = self.next_handle.fetch_add(1, AtomicOrdering::Relaxed);
+        let (n_records, result_type) = match &results {
+            SeqResults::Fastq(results) => (results.len(), "fastq"),
+            SeqResults::Fasta(results) => (results.len(), "fasta"),
+        };
+        let stored = StoredResults {
+            path,
+            reference,
+            results,
+            view: None,
+            record_lookup: None,
+        };
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(handle, stored);
+
+        ResultsHandle {
+            handle,
+            n_records,
+            result_type: result_type.to_owned(),
+            is_preview,
+        }
+    }
+
+    pub fn remove(&self, handle: u64) -> bool {
+        self.entries
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&handle)
+            .is_some()
+    }
+
+    /// The file the results behind a handle came from, and its reference if any.
+    pub fn source(&self, handle: u64) -> Result<(PathBuf, Option<PathBuf>), String> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        entries
+            .get(&handle)
+            .map(|stored| (stored.path.clone(), stored.reference.clone()))
+            .ok_or_else(|| unknown_handle(handle))
+    }
+
+    /// How to find the records in the file, if it has been worked out yet.
+    pub fn record_lookup(&self, handle: u64) -> Result<Option<Arc<RecordLookup>>, String> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        entries
+            .get(&handle)
+            .map(|stored| stored.record_lookup.clone())
+            .ok_or_else(|| unknown_handle(handle))
+    }
+
+    /// Keep how to find the records in the file, so later reads can seek to them.
+    pub fn cache_record_lookup(&self, handle: u64, lookup: Arc<RecordLookup>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(stored) = entries.get_mut(&handle) {
+            stored.record_lookup = Some(lookup);
+        }
+    }
+
+    /// One page of the results, after filtering and sorting them.
+    pub fn page(
+        &self,
+        handle: u64,
+        offset: usize,
+        limit: usize,
+        sort: Option<ResultSort>,
+        filter: Option<ResultFilter>,
+    ) -> Result<ResultsPage, String> {
+        self.with_view(handle, sort, filter, |results, indices| {
+            let page: Vec<usize> = indices.iter().skip(offset).take(limit).copied().collect();
+            let results = match results {
+                SeqResults::Fastq(results) => {
+                    SeqResults::Fastq(page.iter().map(|i| results[*i].clone()).collect())
+                }
+                SeqResults::Fasta(results) => {
+                    SeqResults::Fasta(page.iter().map(|i| results[*i].clone()).collect())
+                }
+            };
+            ResultsPage {
+                total: indices.len(),
+                offset,
+                indices: page,
+                results,
+            }
+        })
+    }
+
+    /// The indices of every result matching the filter, in sorted order.
+    pub fn find(
+        &self,
+        handle: u64,
+        sort: Option<ResultSort>,
+        filter: Option<ResultFilter>,
+    ) -> Result<ResultMatches, String> {
+        self.with_view(handle, sort, filter, |_, indices| ResultMatches {
+            total: indices.len(),
+            indices: indices.to_vec(),
+        })
+    }
+
+    fn with_view<F, R>(
+        &self,
+        handle: u64,
+        sort: Option<ResultSort>,
+        filter: Option<ResultFilter>,
+        f: F,
+    ) -> Result<R, String>
+    where
+        F: FnOnce(&SeqResults, &[usize]) -> R,
+    {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        let stored = entries
+            .get_mut(&handle)
+            .ok_or_else(|| unknown_handle(handle))?;
+
+        let indices = match stored.view.take() {
+            Some((cached_sort, cached_filter, indices))
+                if cached_sort == sort && cached_filter == filter =>
+            {
+                indices
+            }
+            _ => match &stored.results {
+                SeqResults::Fastq(results) => view(results, sort.as_ref(), filter.as_ref())?,
+                SeqResults::Fasta(results) => view(results, sort.as_ref(), filter.as_ref())?,
+            },
+        };
+
+        let output = f(&stored.results, &indices);
+        stored.view = Some((sort, filter, indices));
+        Ok(output)
+    }
+}
+
+/// The indices of the results passing the filter, in sorted order.
+fn view<T: ResultFields>(
+    results: &[T],
+    sort: Option<&ResultSort>,
+    filter: Option<&ResultFilter>,
+) -> Result<Vec<usize>, String> {
+    let mut indices: Vec<usize> = match filter {
+        Some(filter) => {
+            let matcher = RecordFilter::new::<T>(filter)?;
+            (0..results.len())
+                .filter(|i| matcher.is_match(&results[*i]))
+                .collect()
+        }
+        None => (0..results.len()).collect(),
+    };
+
+    if let Some(sort) = sort {
+        let field = sort.field.as_str();
+        let is_text = TEXT_FIELDS.contains(&field);
+        if !is_text && !T::NUMERIC_FIELDS.contains(&field) {
+            return Err(format!("Results cannot be sorted by '{}'.", field));
+        }
+        let directed = |order: Ordering| {
+            if sort.descending {
+                order.reverse()
+            } else {
+                order
+            }
+        };
+        // A stable sort keeps records that compare equal in file order. Records
+        // missing a numeric field, or whose value is NaN as for an empty read's
+        // GC content, sort last either way
+        indices.sort_by(|a, b| {
+            let (a, b) = (&results[*a], &results[*b]);
+            if is_text {
+                return directed(a.text_field(field).cmp(&b.text_field(field)));
+            }
+            let is_number = |value: &f64| !value.is_nan();
+            match (
+                a.numeric_field(field).filter(is_number),
+                b.numeric_field(field).filter(is_number),
+            ) {
+                (Some(a), Some(b)) => directed(a.partial_cmp(&b).unwrap_or(Ordering::Equal)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        });
+    }
+    Ok(indices)
+}
+
+/// How a text search is matched against the id and description.
+enum Search {
+    // Lowercased, to match case-insensitively
+    Substring(String),
+    Regex(Regex),
+}
+
+/// A `ResultFilter` checked and compiled once for a whole view.
+struct RecordFilter<'a> {
+    valid_only: bool,
+    search: Option<Search>,
+    ranges: &'a [FieldRange],
+}
+
+impl<'a> RecordFilter<'a> {
+    fn new<T: ResultFields>(filter: &'a ResultFilter) -> Result<Self, String> {
+        if let Some(range) = filter
+            .ranges
+            .iter()
+            .find(|range| !T::NUMERIC_FIELDS.contains(&range.field.as_str()))
+        {
+            return Err(format!("Results cannot be filtered by '{}'.", range.field));
+        }
+        let search = match &filter.search {
+            Some(pattern) if filter.regex => Some(Search::Regex(
+                Regex::new(pattern).map_err(|err| err.to_string())?,
+            )),
+            Some(text) => Some(Search::Substring(text.to_lowercase())),
+            None => None,
+        };
+
+        Ok(RecordFilter {
+            valid_only: filter.valid_only,
+            search,
+            ranges: &filter.ranges,
+        })
+    }
+
+    fn is_match<T: ResultFields>(&self, res: &T) -> bool {
+        if self.valid_only && res.numeric_field("is_valid") != Some(1.0) {
+            return false;
+        }
+        let in_ranges = self.ranges.iter().all(|range| {
+            res.numeric_field(&range.field).map_or(false, |value| {
+                range.min.map_or(true, |min| value >= min)
+                    && range.max.map_or(true, |max| value <= max)
+            })
+        });
+        if !in_ranges {
+            return false;
+        }
+
+        let texts = TEXT_FIELDS.iter().filter_map(|field| res.text_field(field));
+        match &self.search {
+            Some(Search::Substring(text)) => texts
+                .into_iter()
+                .any(|field| field.to_lowercase().contains(text)),
+            Some(Search::Regex(regex)) => texts.into_iter().any(|field| regex.is_match(field)),
+            None => true,
+        }
+    }
+}
+
+fn unknown_handle(handle: u64) -> String {
+    format!("No results are stored under handle {}.", handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResultStore;
+    use crate::models::{FastqSeqResult, FieldRange, ResultFilter, ResultSort, SeqResults};
+    use std::path::PathBuf;
+
+    fn result(id: &str, gc: f32, is_valid: bool) -> FastqSeqResult {
+        FastqSeqResult {
+            id: id.to_owned(),
+            gc,
+            is_valid,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_result_store_pages_sorted_results() {
+        let store = ResultStore::default();
+        let results = vec![
+            result("r0", 0.4, true),
+            result("r1", 0.9, true),
+            result("r2", 0.1, false),
+            result("r3", 0.6, true),
+        ];
+        let handle = store.insert(
+            PathBuf::from("reads.fq"),
+            None,
+            SeqResults::Fastq(results),
+            false,
+        );
+        assert_eq!(handle.n_records, 4);
+
+        let sort = ResultSort {
+            field: "gc".to_owned(),
+            descending: true,
+        };
+        let filter = ResultFilter {
+            valid_only: true,
+            ..Default::default()
+        };
+        let page = store
+            .page(handle.handle, 1, 5, Some(sort.clone()), Some(filter))
+            .unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.indices, vec![3, 0]);
+        match page.results {
+            SeqResults::Fastq(results) => assert_eq!(results[0].id, "r3"),
+            SeqResults::Fasta(_) => panic!("expected FASTQ results"),
+        }
+
+        let unsorted = store.page(handle.handle, 0, 2, None, None).unwrap();
+        assert_eq!(unsorted.total, 4);
+        assert_eq!(unsorted.indices, vec![0, 1]);
+
+        let unknown_field = ResultSort {
+            field: "colour".to_owned(),
+            ..sort
+        };
+        assert!(store
+            .page(handle.handle, 0, 2, Some(unknown_field), None)
+            .is_err());
+        assert!(store.remove(handle.handle));
+        assert!(store.page(handle.handle, 0, 2, None, None).is_err());
+    }
+
+    #[test]
+    fn test_result_store_searches_and_filters_by_range() {
+        let store = ResultStore::default();
+        let mut results = vec![
+            result("read_1", 0.75, true),
+            result("READ_2", 0.72, true),
+            result("other_3", 0.9, true),
+            result("read_4", 0.3, true),
+        ];
+        results[2].desc = "read from lane 2".to_owned();
+        let handle = store.insert(
+            PathBuf::from("reads.fq"),
+            None,
+            SeqResults::Fastq(results),
+            false,
+        );
+        let high_gc = vec![FieldRange {
+            field: "gc".to_owned(),
+            min: Some(0.7),
+            max: None,
+        }];
+
+        let substring = ResultFilter {
+            search: Some("read".to_owned()),
+            ranges: high_gc.clone(),
+            ..Default::default()
+        };
+        let matches = store.find(handle.handle, None, Some(substring)).unwrap();
+        assert_eq!(matches.total, 3);
+        assert_eq!(matches.indices, vec![0, 1, 2]);
+
+        let regex = ResultFilter {
+            search: Some("^read_\\d$".to_owned()),
+            regex: true,
+            ranges: high_gc,
+            ..Default::default()
+        };
+        let sort = ResultSort {
+            field: "id".to_owned(),
+            descending: true,
+        };
+        let matches = store.find(handle.handle, Some(sort), Some(regex)).unwrap();
+        assert_eq!(matches.indices, vec![0]);
+
+        let bad_regex = ResultFilter {
+            search: Some("(".to_owned()),
+            regex: true,
+            ..Default::default()
+        };
+        assert!(store.find(handle.handle, None, Some(bad_regex)).is_err());
+        let bad_field = ResultFilter {
+            ranges: vec![FieldRange {
+                field: "colour".to_owned(),
+                min: None,
+                max: Some(1.0),
+            }],
+            ..Default::default()
+        };
+        assert!(store.find(handle.handle, None, Some(bad_field)).is_err());
+    }
+
+    #[test]
+    fn test_result_store_sorts_by_nested_fields() {
+        let store = ResultStore::default();
+        let mut results = vec![result("r0", 0.5, true), result("r1", 0.5, true)];
+        results[0].complexity.entropy = 1.9;
+        results[1].complexity.entropy = 0.2;
+        let handle = store.insert(
+            PathBuf::from("reads.fq"),
+            None,
+            SeqResults::Fastq(results),
+            false,
+        );
+
+        let sort = ResultSort {
+            field: "complexity.entropy".to_owned(),
+            descending: false,
+        };
+        let matches = store.find(handle.handle, Some(sort), None).unwrap();
+        assert_eq!(matches.indices, vec![1, 0]);
+        let missing = ResultSort {
+            field: "illumina.lane".to_owned(),
+            descending: false,
+        };
+        let matches = store.find(handle.handle, Some(missing), None).unwrap();
+        assert_eq!(matches.indices, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_result_store_sorts_nan_last() {
+        let store = ResultStore::default();
+        let results = vec![
+            result("r0", 0.4, true),
+            result("empty", f32::NAN, true),
+            result("r2", 0.9, true),
+            result("r3", 0.1, true),
+        ];
+        let handle = store.insert(
+            PathBuf::from("reads.fq"),
+            None,
+            SeqResults::Fastq(results),
+            false,
+        );
+
+        let ascending = ResultSort {
+            field: "gc".to_owned(),
+            descending: false,
+        };
+        let descending = ResultSort {
+            descending: true,
+            ..ascending.clone()
+        };
+        let matches = store.find(handle.handle, Some(ascending), None).unwrap();
+        assert_eq!(matches.indices, vec![3, 0, 2, 1]);
+        let matches = store.find(handle.handle, Some(descending), None).unwrap();
+        assert_eq!(matches.indices, vec![2, 0, 3, 1]);
+    }
+}
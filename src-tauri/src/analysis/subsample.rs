@@ -0,0 +1,275 @@
+use crate::analysis::contamination::mix_hash;
+use crate::models::{Subsample, SubsampleReport, SubsampleSize};
+use crate::services::io::{create_fastq_writer, read_fastq, sample_name};
+use bio::io::fastq;
+use std::io::Error as IOError;
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+use std::path::{Path, PathBuf};
+
+/// Picks items as they stream past, either each with a fixed probability or a
+/// fixed number of them by reservoir sampling. The same seed always picks the
+/// same items. Only reservoir sampling holds on to items; a fraction's picks are
+/// final, so they are handed straight back.
+pub struct Sampler<T> {
+    size: SubsampleSize,
+    state: u64,
+    n_seen: usize,
+    kept: Vec<(usize, T)>,
+}
+
+impl<T> Sampler<T> {
+    pub fn new(subsample: &Subsample) -> Result<Self, String> {
+        match subsample.size {
+            SubsampleSize::Fraction(fraction) if !(fraction > 0.0 && fraction <= 1.0) => {
+                return Err(format!(
+                    "The fraction to subsample must be above 0 and at most 1, not {}.",
+                    fraction
+                ))
+            }
+            SubsampleSize::Count(0) => {
+                return Err("The number of reads to subsample must be above 0.".to_owned())
+            }
+            _ => (),
+        }
+        Ok(Sampler {
+            size: subsample.size,
+            state: subsample.seed,
+            n_seen: 0,
+            kept: Vec::new(),
+        })
+    }
+
+    /// Offer the next item, getting it back at once if a fraction picked it.
+    pub fn offer(&mut self, item: T) -> Option<T> {
+        let index = self.n_seen;
+        self.n_seen += 1;
+        match self.size {
+            SubsampleSize::Fraction(fraction) => {
+                if self.next_f64() < fraction {
+                    return Some(item);
+                }
+            }
+            SubsampleSize::Count(count) => {
+                if self.kept.len() < count {
+                    self.kept.push((index, item));
+                } else {
+                    let slot = (self.next_u64() % self.n_seen as u64) as usize;
+                    if slot < count {
+                        self.kept[slot] = (index, item);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn n_seen(&self) -> usize {
+        self.n_seen
+    }
+
+    /// The items reservoir sampling picked, in the order they were offered.
+    pub fn finish(mut self) -> Vec<T> {
+        self.kept.sort_by_key(|(index, _)| *index);
+        self.kept.into_iter().map(|(_, item)| item).collect()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let value = mix_hash(self.state);
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        value
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        // The top 53 bits fill the mantissa of a double in [0, 1)
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Subsample records as they are read, keeping their order.
+pub fn subsample<T, I>(records: I, subsample: &Subsample) -> Result<Vec<T>, String>
+where
+    I: IntoIterator<Item = T>,
+{
+    let mut sampler = Sampler::new(subsample)?;
+    let mut picked = Vec::new();
+    for rec in records {
+        picked.extend(sampler.offer(rec));
+    }
+    picked.extend(sampler.finish());
+    Ok(picked)
+}
+
+/// Subsample one FASTQ file, or the two files of a read pair so that the same
+/// mates are kept from each, writing the picked reads into `dest_dir`.
+pub fn subsample_fastq_files(
+    paths: &[PathBuf],
+    dest_dir: &Path,
+    subsample: &Subsample,
+    compress: bool,
+) -> Result<SubsampleReport, IOError> {
+    if paths.is_empty() || paths.len() > 2 {
+        return Err(IOError::new(
+            InvalidInput,
+            "Subsample either a single FASTQ file or the two files of a read pair.",
+        ));
+    }
+    let mut sampler = Sampler::new(subsample).map_err(|err| IOError::new(InvalidInput, err))?;
+
+    let extension = if compress { "fastq.gz" } else { "fastq" };
+    let dest_paths: Vec<PathBuf> = paths
+        .iter()
+        .map(|path| dest_dir.join(format!("{}.subsampled.{}", sample_name(path), extension)))
+        .collect();
+    if dest_paths.len() == 2 && dest_paths[0] == dest_paths[1] {
+        return Err(IOError::new(
+            InvalidInput,
+            format!(
+                "Both files of the pair would be written to {}; rename one of them.",
+                dest_paths[0].display()
+            ),
+        ));
+    }
+    let mut writers = Vec::new();
+    for dest in &dest_paths {
+        writers.push(create_fastq_writer(dest, compress)?);
+    }
+
+    let mut readers = Vec::new();
+    for path in paths {
+        readers.push(read_fastq(path)?.records());
+    }
+    let mut n_sampled = 0;
+    let mut write_mates = |mates: Vec<fastq::Record>| -> Result<(), IOError> {
+        for (writer, rec) in writers.iter_mut().zip(&mates) {
+            writer.write_record(rec)?;
+        }
+        n_sampled += 1;
+        Ok(())
+    };
+    loop {
+        let mut mates = Vec::new();
+        for reader in readers.iter_mut() {
+            if let Some(rec) = reader.next() {
+                mates.push(rec.map_err(|err| IOError::new(InvalidData, err.to_string()))?);
+            }
+        }
+        if mates.is_empty() {
+            break;
+        }
+        if mates.len() != paths.len() {
+            return Err(IOError::new(
+                InvalidData,
+                "The paired files have different numbers of reads.",
+            ));
+        }
+        if let [first, second] = mates.as_slice() {
+            if mate_id(first.id()) != mate_id(second.id()) {
+                return Err(IOError::new(
+                    InvalidData,
+                    format!(
+                        "Read {} of the pair does not match its mate {}.",
+                        first.id(),
+                        second.id()
+                    ),
+                ));
+            }
+        }
+        if let Some(mates) = sampler.offer(mates) {
+            write_mates(mates)?;
+        }
+    }
+
+    let n_reads = sampler.n_seen();
+    for mates in sampler.finish() {
+        write_mates(mates)?;
+    }
+    for writer in writers {
+        writer.finish()?;
+    }
+
+    Ok(SubsampleReport {
+        n_reads,
+        n_sampled,
+        paths: dest_paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect(),
+    })
+}
+
+/// The ID both mates of a pair share, without the `/1` or `/2` of older
+/// Illumina read names.
+fn mate_id(id: &str) -> &str {
+    id.strip_suffix("/1")
+        .or_else(|| id.strip_suffix("/2"))
+        .unwrap_or(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mate_id, subsample, Sampler};
+    use crate::models::{Subsample, SubsampleSize};
+
+    #[test]
+    fn test_subsample_count_is_reproducible() {
+        let records: Vec<usize> = (0..1000).collect();
+        let spec = Subsample {
+            size: SubsampleSize::Count(50),
+            seed: 7,
+        };
+
+        let picked = subsample(records.clone(), &spec).unwrap();
+        assert_eq!(picked.len(), 50);
+        assert!(picked.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(picked, subsample(records.clone(), &spec).unwrap());
+        let reseeded = Subsample { seed: 8, ..spec };
+        assert_ne!(picked, subsample(records, &reseeded).unwrap());
+    }
+
+    #[test]
+    fn test_subsample_fraction() {
+        let records: Vec<usize> = (0..10_000).collect();
+        let spec = Subsample {
+            size: SubsampleSize::Fraction(0.1),
+            seed: 0,
+        };
+
+        let picked = subsample(records.clone(), &spec).unwrap();
+        assert!(picked.len() > 900 && picked.len() < 1100);
+        let everything = Subsample {
+            size: SubsampleSize::Fraction(1.0),
+            seed: 0,
+        };
+        assert_eq!(
+            subsample(records.clone(), &everything).unwrap().len(),
+            10_000
+        );
+        let invalid = Subsample {
+            size: SubsampleSize::Fraction(1.5),
+            seed: 0,
+        };
+        assert!(subsample(records, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_sampler_hands_back_fraction_picks_at_once() {
+        let spec = Subsample {
+            size: SubsampleSize::Fraction(0.5),
+            seed: 3,
+        };
+        let mut sampler = Sampler::new(&spec).unwrap();
+
+        let streamed: Vec<usize> = (0..100).filter_map(|item| sampler.offer(item)).collect();
+        assert_eq!(sampler.n_seen(), 100);
+        assert!(sampler.finish().is_empty());
+        assert_eq!(streamed, subsample(0..100, &spec).unwrap());
+    }
+
+    #[test]
+    fn test_mate_id_strips_read_number() {
+        assert_eq!(mate_id("read1/1"), "read1");
+        assert_eq!(mate_id("read1/2"), "read1");
+        assert_eq!(mate_id("read1"), "read1");
+        assert_eq!(mate_id("read1/3"), "read1/3");
+    }
+}
@@ -0,0 +1,305 @@
+use crate::analysis::analysers::{find_adapter, phred_from_error_probability};
+use crate::analysis::complexity::{has_poly_g_tail, is_low_complexity};
+use crate::models::{FastaSeqResult, FastqSeqResult, SeqComplexity, SeqSummary};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+pub fn summarise_fastq_results(results: &[FastqSeqResult]) -> SeqSummary {
+    let mut summary = SummaryBuilder::default();
+    for res in results {
+        summary.add_fastq(res);
+    }
+    summary.finish("fastq")
+}
+
+pub fn summarise_fasta_results(results: &[FastaSeqResult]) -> SeqSummary {
+    let mut summary = SummaryBuilder::default();
+    for res in results {
+        summary.add_fasta(res);
+    }
+    summary.finish("fasta")
+}
+
+/// Fill in the summary metrics that need the raw sequences of the valid records.
+pub fn with_sequence_metrics<'a, I>(summary: SeqSummary, seqs: I) -> SeqSummary
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut metrics = SummaryBuilder::default();
+    for seq in seqs {
+        metrics.add_sequence(seq);
+    }
+    metrics.with_sequence_rates(summary)
+}
+
+/// Builds a summary a result at a time, so the records of a file need not all
+/// be held at once. Duplicate sequences are found by their hashes.
+#[derive(Default)]
+pub struct SummaryBuilder {
+    n_records: usize,
+    n_valid: usize,
+    total_bases: usize,
+    min_len: Option<usize>,
+    max_len: usize,
+    gc_sum: f32,
+    expected_errors_sum: f32,
+    // Summed in double precision, as it is divided by every base
+    total_errors: f64,
+    entropy_sum: f32,
+    n_low_complexity: usize,
+    n_poly_g: usize,
+    seen: HashSet<u64>,
+    n_seqs: usize,
+    n_duplicates: usize,
+    n_adapters: usize,
+}
+
+impl SummaryBuilder {
+    pub fn add_fastq(&mut self, res: &FastqSeqResult) {
+        self.n_records += 1;
+        if res.is_valid {
+            self.add_valid(res.seq_len, res.gc, &res.complexity);
+            self.expected_errors_sum += res.expected_errors;
+            self.total_errors += f64::from(res.expected_errors);
+        }
+    }
+
+    pub fn add_fasta(&mut self, res: &FastaSeqResult) {
+        self.n_records += 1;
+        if res.is_valid {
+            self.add_valid(res.seq_len, res.gc, &res.complexity);
+        }
+    }
+
+    fn add_valid(&mut self, seq_len: usize, gc: f32, complexity: &SeqComplexity) {
+        self.n_valid += 1;
+        self.total_bases += seq_len;
+        self.min_len = Some(self.min_len.map_or(seq_len, |min| min.min(seq_len)));
+        self.max_len = self.max_len.max(seq_len);
+        self.gc_sum += gc;
+        self.entropy_sum += complexity.entropy;
+        if is_low_complexity(complexity) {
+            self.n_low_complexity += 1;
+        }
+        if has_poly_g_tail(complexity) {
+            self.n_poly_g += 1;
+        }
+    }
+
+    /// Count a valid record's sequence towards the duplicate and adapter rates.
+    pub fn add_sequence(&mut self, seq: &[u8]) {
+        self.n_seqs += 1;
+        let mut hasher = DefaultHasher::new();
+        seq.hash(&mut hasher);
+        if !self.seen.insert(hasher.finish()) {
+            self.n_duplicates += 1;
+        }
+        if find_adapter(seq).is_some() {
+            self.n_adapters += 1;
+        }
+    }
+
+    pub fn finish(self, result_type: &str) -> SeqSummary {
+        let per_valid = |total: f32| {
+            if self.n_valid == 0 {
+                0.0
+            } else {
+                total / self.n_valid as f32
+            }
+        };
+        // Average the error probabilities over every base, not the Phred scores
+        let mean_quality = if self.total_bases == 0 {
+            0.0
+        } else {
+            phred_from_error_probability(self.total_errors / self.total_bases as f64)
+        };
+
+        let summary = SeqSummary {
+            n_records: self.n_records,
+            n_valid: self.n_valid,
+            total_bases: self.total_bases,
+            min_len: self.min_len.unwrap_or(0),
+            max_len: self.max_len,
+            mean_len: per_valid(self.total_bases as f32),
+            mean_gc: per_valid(self.gc_sum),
+            mean_quality,
+            mean_expected_errors: per_valid(self.expected_errors_sum),
+            dup_rate: 0.0,
+            adapter_rate: 0.0,
+            mean_entropy: per_valid(self.entropy_sum),
+            low_complexity_rate: per_valid(self.n_low_complexity as f32),
+            poly_g_rate: per_valid(self.n_poly_g as f32),
+            result_type: result_type.to_owned(),
+        };
+        self.with_sequence_rates(summary)
+    }
+
+    fn with_sequence_rates(&self, summary: SeqSummary) -> SeqSummary {
+        if self.n_seqs == 0 {
+            return summary;
+        }
+        SeqSummary {
+            dup_rate: self.n_duplicates as f32 / self.n_seqs as f32,
+            adapter_rate: self.n_adapters as f32 / self.n_seqs as f32,
+            ..summary
+        }
+    }
+}
+
+/// Count the valid records falling into each whole-number %GC bin.
+pub fn gc_distribution<'a, I>(gcs: I) -> BTreeMap<usize, usize>
+where
+    I: IntoIterator<Item = &'a f32>,
+{
+    let mut dist = BTreeMap::new();
+    for gc_ in gcs {
+        let bin = (gc_ * 100.0).round() as usize;
+        *dist.entry(bin).or_insert(0) += 1;
+    }
+    dist
+}
+
+/// Count the valid records of each sequence length.
+pub fn length_distribution<'a, I>(lengths: I) -> BTreeMap<usize, usize>
+where
+    I: IntoIterator<Item = &'a usize>,
+{
+    let mut dist = BTreeMap::new();
+    for len in lengths {
+        *dist.entry(*len).or_insert(0) += 1;
+    }
+    dist
+}
+
+/// Percentage of the sequences with an N at each position.
+pub fn n_content_by_position<'a, I>(seqs: I) -> Vec<f32>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut n_counts: Vec<usize> = Vec::new();
+    let mut seq_counts: Vec<usize> = Vec::new();
+    for seq in seqs {
+        if seq_counts.len() < seq.len() {
+            seq_counts.resize(seq.len(), 0);
+            n_counts.resize(seq.len(), 0);
+        }
+        for (pos, base) in seq.iter().enumerate() {
+            seq_counts[pos] += 1;
+            if base.eq_ignore_ascii_case(&b'N') {
+                n_counts[pos] += 1;
+            }
+        }
+    }
+
+    n_counts
+        .iter()
+        .zip(&seq_counts)
+        .map(|(n, total)| *n as f32 / *total as f32 * 100.0)
+        .collect()
+}
+
+/// Count the valid FASTQ records in each whole-number mean Phred score bin.
+pub fn quality_distribution(results: &[FastqSeqResult]) -> BTreeMap<usize, usize> {
+    let mut dist = BTreeMap::new();
+    for res in results.iter().filter(|res| res.is_valid && res.seq_len > 0) {
+        let bin = res.mean_quality.round() as usize;
+        *dist.entry(bin).or_insert(0) += 1;
+    }
+    dist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        gc_distribution, n_content_by_position, summarise_fasta_results, summarise_fastq_results,
+        with_sequence_metrics,
+    };
+    use crate::analysis::complexity::sequence_complexity;
+    use crate::models::{FastaSeqResult, FastqSeqResult, SeqSummary};
+
+    #[test]
+    fn test_summarise_fastq_results() {
+        let results = vec![
+            FastqSeqResult {
+                gc: 0.5,
+                is_valid: true,
+                expected_errors: 0.4,
+                seq_len: 4,
+                ..Default::default()
+            },
+            FastqSeqResult {
+                gc: 1.0,
+                is_valid: true,
+                expected_errors: 0.004,
+                seq_len: 4,
+                ..Default::default()
+            },
+            FastqSeqResult::default(),
+        ];
+
+        let summary = summarise_fastq_results(&results);
+        assert_eq!(summary.n_records, 3);
+        assert_eq!(summary.n_valid, 2);
+        assert_eq!(summary.total_bases, 8);
+        assert_eq!(summary.mean_gc, 0.75);
+        // Q10 and Q30 reads average to an error rate of 0.0505, not Q20
+        assert!((summary.mean_quality - 12.967).abs() < 1e-3);
+        assert!((summary.mean_expected_errors - 0.202).abs() < 1e-6);
+        assert_eq!(summary.result_type, "fastq");
+    }
+
+    #[test]
+    fn test_summarise_fasta_results_flags_low_complexity() {
+        let result = |seq: &[u8]| FastaSeqResult {
+            is_valid: true,
+            seq_len: seq.len(),
+            complexity: sequence_complexity(seq),
+            ..Default::default()
+        };
+        let results = vec![
+            result(b"ATGCGTACGTTAGCCTAGGATCCAGTTGACAGGC"),
+            result(b"ATGCGTACGTTAGCCTAGGATCCAGTTGACAGGCTTACGAGGGGGGGGGG"),
+            result(b"ACACACACACACACACACACACACACACACAC"),
+            result(b"ATGCGTACGTTAGCCTAGGATCCAGTTGACAGGC"),
+        ];
+
+        let summary = summarise_fasta_results(&results);
+        assert_eq!(summary.low_complexity_rate, 0.25);
+        assert_eq!(summary.poly_g_rate, 0.25);
+    }
+
+    #[test]
+    fn test_summarise_fasta_results_empty() {
+        let results: Vec<FastaSeqResult> = Vec::new();
+
+        let summary = summarise_fasta_results(&results);
+        assert_eq!(summary.n_records, 0);
+        assert_eq!(summary.mean_len, 0.0);
+        assert_eq!(summary.result_type, "fasta");
+    }
+
+    #[test]
+    fn test_gc_distribution() {
+        let dist = gc_distribution(&[0.5, 0.5, 0.25]);
+        assert_eq!(dist.get(&50), Some(&2));
+        assert_eq!(dist.get(&25), Some(&1));
+    }
+
+    #[test]
+    fn test_n_content_by_position() {
+        let seqs: Vec<&[u8]> = vec![b"NAC", b"NNCGT", b"ACn", b"ACN"];
+
+        let n_content = n_content_by_position(seqs);
+        assert_eq!(n_content, vec![50.0, 25.0, 50.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_with_sequence_metrics() {
+        let seqs: Vec<&[u8]> = vec![b"ACGT", b"ACGT", b"TTAGATCGGAAGAGCTT", b"GGGG"];
+
+        let summary = with_sequence_metrics(SeqSummary::default(), seqs);
+        assert_eq!(summary.dup_rate, 0.25);
+        assert_eq!(summary.adapter_rate, 0.25);
+    }
+}
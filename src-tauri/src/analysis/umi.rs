@@ -0,0 +1,190 @@
+use crate::models::{UmiSource, UmiSummary};
+use bio::io::fastq;
+use std::collections::{HashMap, HashSet};
+
+/// Which of the leading bases of a read are UMI (`N`) and which are kept in
+/// the read (`X`), in the style of UMI-tools' string patterns.
+#[derive(Debug, PartialEq)]
+pub struct UmiPattern {
+    is_umi: Vec<bool>,
+}
+
+impl UmiPattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let is_umi: Vec<bool> = pattern
+            .chars()
+            .map(|c| match c.to_ascii_uppercase() {
+                'N' => Ok(true),
+                'X' => Ok(false),
+                other => Err(format!(
+                    "UMI patterns may only contain N and X, not '{}'.",
+                    other
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        if !is_umi.contains(&true) {
+            return Err("A UMI pattern needs at least one N.".to_owned());
+        }
+        Ok(UmiPattern { is_umi })
+    }
+
+    /// Move the UMI bases of a read into its ID as `id_UMI`. Reads shorter than
+    /// the pattern have no UMI.
+    pub fn extract(&self, rec: &fastq::Record) -> Option<(String, fastq::Record)> {
+        let len = self.is_umi.len();
+        if rec.seq().len() < len || rec.qual().len() < len {
+            return None;
+        }
+
+        let mut umi = String::new();
+        let mut seq = Vec::with_capacity(rec.seq().len());
+        let mut qual = Vec::with_capacity(rec.qual().len());
+        for (pos, is_umi) in self.is_umi.iter().enumerate() {
+            if *is_umi {
+                umi.push(char::from(rec.seq()[pos].to_ascii_uppercase()));
+            } else {
+                seq.push(rec.seq()[pos]);
+                qual.push(rec.qual()[pos]);
+            }
+        }
+        seq.extend_from_slice(&rec.seq()[len..]);
+        qual.extend_from_slice(&rec.qual()[len..]);
+
+        let id = format!("{}_{}", rec.id(), umi);
+        Some((umi, fastq::Record::with_attrs(&id, rec.desc(), &seq, &qual)))
+    }
+}
+
+/// A UMI already in a read ID, either as the eighth field of a bcl-convert ID
+/// or after the last underscore as UMI-tools writes it.
+pub fn header_umi(id: &str) -> Option<&str> {
+    let fields: Vec<&str> = id.split(':').collect();
+    let umi = if fields.len() == 8 {
+        fields[7]
+    } else {
+        id.rsplit_once('_')?.1
+    };
+
+    // Dual UMIs are joined with '+'
+    let is_umi = !umi.is_empty() && umi.bytes().all(|base| b"ACGTNacgtn+".contains(&base));
+    if is_umi {
+        Some(umi)
+    } else {
+        None
+    }
+}
+
+/// Get the UMI of each read, moving sequence UMIs into the read IDs.
+pub fn extract_umis(
+    records: &[fastq::Record],
+    source: UmiSource,
+    pattern: Option<&UmiPattern>,
+) -> Vec<(Option<String>, fastq::Record)> {
+    records
+        .iter()
+        .map(|rec| match (source, pattern) {
+            (UmiSource::Sequence, Some(pattern)) => match pattern.extract(rec) {
+                Some((umi, rec)) => (Some(umi), rec),
+                None => (None, rec.clone()),
+            },
+            _ => (header_umi(rec.id()).map(|umi| umi.to_owned()), rec.clone()),
+        })
+        .collect()
+}
+
+/// UMI diversity, and duplication with and without collapsing on the UMI.
+pub fn summarise_umis(reads: &[(Option<String>, fastq::Record)]) -> UmiSummary {
+    let mut umi_counts: HashMap<&str, usize> = HashMap::new();
+    let mut seqs = HashSet::new();
+    let mut umi_seqs = HashSet::new();
+    let mut n_with_umi = 0;
+    for (umi, rec) in reads {
+        let umi = match umi {
+            Some(umi) => umi.as_str(),
+            None => continue,
+        };
+        n_with_umi += 1;
+        *umi_counts.entry(umi).or_insert(0) += 1;
+        seqs.insert(rec.seq());
+        umi_seqs.insert((umi, rec.seq()));
+    }
+
+    let dup_rate = |n_distinct: usize| {
+        if n_with_umi == 0 {
+            0.0
+        } else {
+            1.0 - n_distinct as f32 / n_with_umi as f32
+        }
+    };
+    let entropy: f32 = umi_counts
+        .values()
+        .map(|count| {
+            let freq = *count as f32 / n_with_umi as f32;
+            -freq * freq.log2()
+        })
+        .sum();
+
+    UmiSummary {
+        n_reads: reads.len(),
+        n_with_umi,
+        distinct_umis: umi_counts.len(),
+        umi_entropy: entropy,
+        dup_rate: dup_rate(seqs.len()),
+        umi_dup_rate: dup_rate(umi_seqs.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_umis, header_umi, summarise_umis, UmiPattern};
+    use crate::models::UmiSource;
+    use bio::io::fastq;
+
+    #[test]
+    fn test_umi_pattern_moves_umi_into_id() {
+        let pattern = UmiPattern::parse("NNNXNN").unwrap();
+        let rec = fastq::Record::with_attrs("r1", Some("desc"), b"ACGTTTGATTACA", b"ABCDEFGHIJKLM");
+
+        let (umi, extracted) = pattern.extract(&rec).unwrap();
+        assert_eq!(umi, "ACGTT");
+        assert_eq!(extracted.id(), "r1_ACGTT");
+        assert_eq!(extracted.seq(), b"TGATTACA");
+        assert_eq!(extracted.qual(), b"DGHIJKLM");
+        assert!(pattern
+            .extract(&fastq::Record::with_attrs("r2", None, b"ACG", b"III"))
+            .is_none());
+        assert!(UmiPattern::parse("NNB").is_err());
+        assert!(UmiPattern::parse("XXX").is_err());
+    }
+
+    #[test]
+    fn test_header_umi() {
+        assert_eq!(
+            header_umi("A00:1:FC:1:1101:1000:2000:ACGTACGT"),
+            Some("ACGTACGT")
+        );
+        assert_eq!(header_umi("read1_GGTTAACC"), Some("GGTTAACC"));
+        assert_eq!(header_umi("A00:1:FC:1:1101:1000:2000"), None);
+        assert_eq!(header_umi("sample_one"), None);
+    }
+
+    #[test]
+    fn test_summarise_umis_collapses_on_umi() {
+        let records = vec![
+            fastq::Record::with_attrs("r1_AAAA", None, b"GATTACA", b"IIIIIII"),
+            fastq::Record::with_attrs("r2_AAAA", None, b"GATTACA", b"IIIIIII"),
+            fastq::Record::with_attrs("r3_CCCC", None, b"GATTACA", b"IIIIIII"),
+            fastq::Record::with_attrs("r4_GGGG", None, b"TTTTTTT", b"IIIIIII"),
+            fastq::Record::with_attrs("r5", None, b"TTTTTTT", b"IIIIIII"),
+        ];
+
+        let reads = extract_umis(&records, UmiSource::Header, None);
+        let summary = summarise_umis(&reads);
+        assert_eq!(summary.n_reads, 5);
+        assert_eq!(summary.n_with_umi, 4);
+        assert_eq!(summary.distinct_umis, 3);
+        assert_eq!(summary.umi_entropy, 1.5);
+        assert_eq!(summary.dup_rate, 0.5);
+        assert_eq!(summary.umi_dup_rate, 0.25);
+    }
+}
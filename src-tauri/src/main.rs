@@ -9,11 +9,40 @@ mod services;
 
 fn main() {
     tauri::Builder::default()
+        .manage(analysis::store::ResultStore::default())
         .invoke_handler(tauri::generate_handler![
             analysis::commands::analyse_fastq_sequences,
             analysis::commands::analyse_fastq_file,
             analysis::commands::analyse_fasta_sequences,
-            analysis::commands::analyse_fasta_file
+            analysis::commands::analyse_fasta_file,
+            analysis::commands::analyse_sequences,
+            analysis::commands::analyse_file,
+            analysis::commands::analyse_protein_sequences,
+            analysis::commands::analyse_protein_file,
+            analysis::commands::export_fastq_multiqc,
+            analysis::commands::export_fasta_multiqc,
+            analysis::commands::export_fastqc_data,
+            analysis::commands::analyse_batch,
+            analysis::commands::compare_samples,
+            analysis::commands::list_qc_profiles,
+            analysis::commands::evaluate_qc,
+            analysis::commands::analyse_long_reads,
+            analysis::commands::analyse_flowcell_quality,
+            analysis::commands::analyse_n_content,
+            analysis::commands::analyse_kmers,
+            analysis::commands::build_contamination_index,
+            analysis::commands::screen_contamination,
+            analysis::commands::demultiplex,
+            analysis::commands::analyse_umis,
+            analysis::commands::subsample_reads,
+            analysis::commands::open_results,
+            analysis::commands::get_results_page,
+            analysis::commands::find_results,
+            analysis::commands::get_record,
+            analysis::commands::close_results,
+            analysis::commands::index_sequence_file,
+            analysis::commands::fetch_region,
+            analysis::commands::fetch_indexed_record
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
 pub struct FastqSeqResult {
     pub id: String,
     pub desc: String,
@@ -8,8 +9,21 @@ pub struct FastqSeqResult {
     pub n_orfs: usize,
     pub is_valid: bool,
     pub phred_score: u32,
+    // Phred score of the mean per-base error probability
+    pub mean_quality: f32,
+    // Sum of the per-base error probabilities
+    pub expected_errors: f32,
     pub seq_len: usize,
+    pub n_count: usize,
+    // IUPAC ambiguity codes other than N
+    pub ambiguous_count: usize,
+    pub longest_n_run: usize,
+    pub complexity: SeqComplexity,
+    // The alphabet the record was validated against, if any
+    pub alphabet: Option<SeqAlphabet>,
+    pub alphabet_error: Option<AlphabetError>,
     pub result_type: String,
+    pub illumina: Option<IlluminaHeader>,
 }
 
 impl Default for FastqSeqResult {
@@ -21,13 +35,83 @@ impl Default for FastqSeqResult {
             n_orfs: 0,
             is_valid: false,
             phred_score: 0,
+            mean_quality: 0.0,
+            expected_errors: 0.0,
             seq_len: 0,
+            n_count: 0,
+            ambiguous_count: 0,
+            longest_n_run: 0,
+            complexity: SeqComplexity::default(),
+            alphabet: None,
+            alphabet_error: None,
             result_type: String::from("fastq"),
+            illumina: None,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum SeqAlphabet {
+    Dna,
+    IupacDna,
+    Rna,
+    Protein,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct AlphabetError {
+    pub character: char,
+    pub position: usize,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct SeqComplexity {
+    // Highest windowed DUST score, from 0 to 100
+    pub dust_score: f32,
+    // Shannon entropy of the base composition in bits
+    pub entropy: f32,
+    pub longest_homopolymer: usize,
+    // Most tandem copies of a dinucleotide unit
+    pub longest_dinucleotide_repeat: usize,
+    // Length of the run of Gs at the 3' end
+    pub poly_g_tail: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct IlluminaHeader {
+    pub instrument: String,
+    // Only present in Casava 1.8+ headers
+    pub run_number: Option<u32>,
+    pub flowcell: Option<String>,
+    pub lane: u32,
+    pub tile: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct LaneQuality {
+    pub lane: u32,
+    pub n_reads: usize,
+    pub mean_quality: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct TileQuality {
+    pub lane: u32,
+    pub tile: u32,
+    pub n_reads: usize,
+    pub mean_quality: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FlowcellQuality {
+    pub lanes: Vec<LaneQuality>,
+    pub tiles: Vec<TileQuality>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
 pub struct FastaSeqResult {
     pub id: String,
     pub desc: String,
@@ -35,6 +119,14 @@ pub struct FastaSeqResult {
     pub n_orfs: usize,
     pub is_valid: bool,
     pub seq_len: usize,
+    pub n_count: usize,
+    // IUPAC ambiguity codes other than N
+    pub ambiguous_count: usize,
+    pub longest_n_run: usize,
+    pub complexity: SeqComplexity,
+    // The alphabet the record was validated against, if any
+    pub alphabet: Option<SeqAlphabet>,
+    pub alphabet_error: Option<AlphabetError>,
     pub result_type: String,
 }
 
@@ -47,7 +139,581 @@ impl Default for FastaSeqResult {
             n_orfs: 0,
             is_valid: false,
             seq_len: 0,
+            n_count: 0,
+            ambiguous_count: 0,
+            longest_n_run: 0,
+            complexity: SeqComplexity::default(),
+            alphabet: None,
+            alphabet_error: None,
             result_type: String::from("fasta"),
         }
     }
 }
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct ProteinSeqResult {
+    pub id: String,
+    pub desc: String,
+    pub is_valid: bool,
+    pub seq_len: usize,
+    // Average mass in Daltons
+    pub molecular_weight: f32,
+    pub isoelectric_point: f32,
+    // Grand average of hydropathy
+    pub gravy: f32,
+    pub instability_index: f32,
+    // Percentage of the sequence made up by each residue
+    pub composition: BTreeMap<char, f32>,
+    pub alphabet_error: Option<AlphabetError>,
+    pub result_type: String,
+}
+
+impl Default for ProteinSeqResult {
+    fn default() -> Self {
+        ProteinSeqResult {
+            id: String::from("id"),
+            desc: String::from("..."),
+            is_valid: false,
+            seq_len: 0,
+            molecular_weight: 0.0,
+            isoelectric_point: 0.0,
+            gravy: 0.0,
+            instability_index: 0.0,
+            composition: BTreeMap::new(),
+            alphabet_error: None,
+            result_type: String::from("protein"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+pub struct SeqSummary {
+    pub n_records: usize,
+    pub n_valid: usize,
+    pub total_bases: usize,
+    pub min_len: usize,
+    pub max_len: usize,
+    pub mean_len: f32,
+    pub mean_gc: f32,
+    pub mean_quality: f32,
+    pub mean_expected_errors: f32,
+    pub dup_rate: f32,
+    pub adapter_rate: f32,
+    pub mean_entropy: f32,
+    pub low_complexity_rate: f32,
+    pub poly_g_rate: f32,
+    pub result_type: String,
+}
+
+impl Default for SeqSummary {
+    fn default() -> Self {
+        SeqSummary {
+            n_records: 0,
+            n_valid: 0,
+            total_bases: 0,
+            min_len: 0,
+            max_len: 0,
+            mean_len: 0.0,
+            mean_gc: 0.0,
+            mean_quality: 0.0,
+            mean_expected_errors: 0.0,
+            dup_rate: 0.0,
+            adapter_rate: 0.0,
+            mean_entropy: 0.0,
+            low_complexity_rate: 0.0,
+            poly_g_rate: 0.0,
+            result_type: String::from("fastq"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum QcStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl QcStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QcStatus::Pass => "pass",
+            QcStatus::Warn => "warn",
+            QcStatus::Fail => "fail",
+        }
+    }
+
+    /// Grade a value where higher is worse.
+    pub fn from_upper_limits(value: f32, warn: f32, fail: f32) -> Self {
+        if value > fail {
+            QcStatus::Fail
+        } else if value > warn {
+            QcStatus::Warn
+        } else {
+            QcStatus::Pass
+        }
+    }
+
+    /// Grade a value where lower is worse.
+    pub fn from_lower_limits(value: f32, warn: f32, fail: f32) -> Self {
+        if value < fail {
+            QcStatus::Fail
+        } else if value < warn {
+            QcStatus::Warn
+        } else {
+            QcStatus::Pass
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FastqcModule {
+    pub name: String,
+    pub status: QcStatus,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct FastqcThresholds {
+    pub base_lower_quartile_warn: f32,
+    pub base_lower_quartile_fail: f32,
+    pub base_median_warn: f32,
+    pub base_median_fail: f32,
+    pub sequence_quality_warn: f32,
+    pub sequence_quality_fail: f32,
+    pub base_content_warn: f32,
+    pub base_content_fail: f32,
+    pub gc_deviation_warn: f32,
+    pub gc_deviation_fail: f32,
+    pub n_content_warn: f32,
+    pub n_content_fail: f32,
+    pub tile_deviation_warn: f32,
+    pub tile_deviation_fail: f32,
+    pub duplication_warn: f32,
+    pub duplication_fail: f32,
+    pub overrepresented_warn: f32,
+    pub overrepresented_fail: f32,
+    pub adapter_warn: f32,
+    pub adapter_fail: f32,
+}
+
+impl Default for FastqcThresholds {
+    // Mirrors the defaults in FastQC's limits.txt
+    fn default() -> Self {
+        FastqcThresholds {
+            base_lower_quartile_warn: 10.0,
+            base_lower_quartile_fail: 5.0,
+            base_median_warn: 25.0,
+            base_median_fail: 20.0,
+            sequence_quality_warn: 27.0,
+            sequence_quality_fail: 20.0,
+            base_content_warn: 10.0,
+            base_content_fail: 20.0,
+            gc_deviation_warn: 15.0,
+            gc_deviation_fail: 30.0,
+            n_content_warn: 5.0,
+            n_content_fail: 20.0,
+            tile_deviation_warn: 5.0,
+            tile_deviation_fail: 10.0,
+            // Percentage of sequences left after deduplication
+            duplication_warn: 70.0,
+            duplication_fail: 50.0,
+            overrepresented_warn: 0.1,
+            overrepresented_fail: 1.0,
+            adapter_warn: 5.0,
+            adapter_fail: 10.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct BatchFileSummary {
+    pub path: String,
+    pub sample: String,
+    pub summary: Option<SeqSummary>,
+    pub qc: Option<QcReport>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct SampleOutlier {
+    pub sample: String,
+    pub metric: String,
+    pub value: f32,
+    pub z_score: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct SampleComparison {
+    pub samples: Vec<String>,
+    pub metrics: Vec<String>,
+    // One row per sample, one column per metric
+    pub values: Vec<Vec<f32>>,
+    pub z_scores: Vec<Vec<f32>>,
+    pub outliers: Vec<SampleOutlier>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThresholdDirection {
+    // The metric must be at least the threshold values
+    Min,
+    // The metric must be at most the threshold values
+    Max,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct MetricThreshold {
+    pub metric: String,
+    pub direction: ThresholdDirection,
+    pub warn: f32,
+    pub fail: f32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct QcProfile {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub thresholds: Vec<MetricThreshold>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct QcVerdict {
+    pub metric: String,
+    pub value: Option<f32>,
+    pub status: QcStatus,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct QcReport {
+    pub profile: String,
+    pub status: QcStatus,
+    pub verdicts: Vec<QcVerdict>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SeqFormat {
+    Fastq,
+    Fasta,
+    Sam,
+    Bam,
+    Cram,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FileFormat {
+    pub format: SeqFormat,
+    pub compression: Compression,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum SeqResults {
+    Fastq(Vec<FastqSeqResult>),
+    Fasta(Vec<FastaSeqResult>),
+}
+
+/// The results for part or all of a file.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FileResults<T> {
+    pub results: Vec<T>,
+    // Index of the first record analysed
+    pub start_record: usize,
+    // Where to continue the analysis if it stopped before the end of the file
+    pub next_record: Option<usize>,
+    // Where `next_record` starts in the decompressed file
+    pub next_offset: Option<u64>,
+    pub is_preview: bool,
+    // Set when the results were graded against a QC profile
+    pub qc: Option<QcReport>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct FileAnalysis {
+    pub detected: FileFormat,
+    pub results: SeqResults,
+    // Set when only a subsample of the records was analysed
+    pub subsample: Option<Subsample>,
+    // Set when the results were graded against a QC profile
+    pub qc: Option<QcReport>,
+    // Set when the analysis stopped at a record or byte limit before the end of the file
+    pub next_record: Option<usize>,
+    pub is_preview: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct YieldBin {
+    pub minutes: u64,
+    pub n_reads: usize,
+    pub bases: usize,
+    pub cumulative_bases: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ChannelStats {
+    pub channel: u32,
+    pub n_reads: usize,
+    pub bases: usize,
+    pub mean_quality: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct LongReadSummary {
+    pub n_reads: usize,
+    pub total_bases: usize,
+    pub mean_len: f32,
+    pub median_len: usize,
+    pub max_len: usize,
+    pub n50: usize,
+    // Phred score of the mean per-base error probability
+    pub mean_quality: f32,
+    pub median_read_quality: f32,
+    // GC content weighted by read length
+    pub weighted_gc: f32,
+    pub yield_over_time: Vec<YieldBin>,
+    pub channels: Vec<ChannelStats>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct EnrichedKmer {
+    pub kmer: String,
+    pub count: usize,
+    // Highest ratio of observed to expected count at any one position
+    pub max_obs_exp: f32,
+    pub max_position: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct GenomeEstimate {
+    pub coverage_peak: usize,
+    pub genome_size: usize,
+    pub heterozygosity: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct KmerSpectrum {
+    pub k: usize,
+    pub total_kmers: usize,
+    pub distinct_kmers: usize,
+    // Number of distinct canonical k-mers seen each number of times
+    pub spectrum: BTreeMap<usize, usize>,
+    pub enriched: Vec<EnrichedKmer>,
+    pub genome: Option<GenomeEstimate>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ContaminantHit {
+    pub reference: String,
+    pub n_reads: usize,
+    pub percentage: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ContaminationReport {
+    pub n_screened: usize,
+    pub hits: Vec<ContaminantHit>,
+    // Reads hitting none of the references
+    pub unmatched_percentage: f32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct SampleSheetEntry {
+    pub sample_id: String,
+    pub sample_name: String,
+    pub index: String,
+    pub index2: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BarcodeLocation {
+    // The index reads in the Casava 1.8+ header, e.g. `1:N:0:ACGTACGT+TTGCAAGG`
+    Header,
+    // The first bases of the read, which are trimmed off
+    Inline,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct DemuxSample {
+    pub sample_id: String,
+    pub barcode: String,
+    pub n_reads: usize,
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct BarcodeCount {
+    pub barcode: String,
+    pub n_reads: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct DemuxReport {
+    pub samples: Vec<DemuxSample>,
+    pub n_undetermined: usize,
+    pub undetermined_path: String,
+    // The most common barcodes among the undetermined reads
+    pub undetermined_barcodes: Vec<BarcodeCount>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UmiSource {
+    // The leading bases of the read, cut out with a pattern such as `NNNNNNNN`
+    Sequence,
+    // Already in the read ID, e.g. from bcl-convert or UMI-tools
+    Header,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct UmiSummary {
+    pub n_reads: usize,
+    pub n_with_umi: usize,
+    pub distinct_umis: usize,
+    // Shannon entropy of the UMI counts in bits
+    pub umi_entropy: f32,
+    // Duplication among reads with a UMI, by sequence alone
+    pub dup_rate: f32,
+    // Duplication among reads with a UMI, by UMI and sequence together
+    pub umi_dup_rate: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct UmiAnalysis {
+    pub results: Vec<FastqSeqResult>,
+    pub summary: UmiSummary,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubsampleSize {
+    // Keep each read with this probability
+    Fraction(f64),
+    // Keep exactly this many reads, or all of them if there are fewer
+    Count(usize),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct Subsample {
+    pub size: SubsampleSize,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct SubsampleReport {
+    pub n_reads: usize,
+    pub n_sampled: usize,
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ResultsHandle {
+    pub handle: u64,
+    pub n_records: usize,
+    pub result_type: String,
+    // Whether only the first records of the file were analysed
+    pub is_preview: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ResultSort {
+    // Name of the result field to sort by, e.g. `gc` or `complexity.entropy`
+    pub field: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct FieldRange {
+    pub field: String,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct ResultFilter {
+    #[serde(default)]
+    pub valid_only: bool,
+    // Matched against the id and description, ignoring case unless a regex
+    pub search: Option<String>,
+    #[serde(default)]
+    pub regex: bool,
+    // Every range must hold, with both ends inclusive
+    #[serde(default)]
+    pub ranges: Vec<FieldRange>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ResultMatches {
+    pub total: usize,
+    pub indices: Vec<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ResultsPage {
+    // Number of records matching the filter, across all pages
+    pub total: usize,
+    pub offset: usize,
+    // Index of each record on the page within the file
+    pub indices: Vec<usize>,
+    pub results: SeqResults,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct OrfSpan {
+    pub start: usize,
+    // Exclusive, just past the stop codon
+    pub end: usize,
+    pub frame: i8,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct SeqRecordDetail {
+    // Position of the record in its file
+    pub index: usize,
+    pub id: String,
+    pub desc: String,
+    pub seq: String,
+    // The raw quality string and its Phred scores; FASTA records have neither
+    pub qual: Option<String>,
+    pub phred: Option<Vec<u8>>,
+    pub orfs: Vec<OrfSpan>,
+    // Adapter read-through runs from here to the end of the read
+    pub adapter_start: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct SeqIndexSummary {
+    pub n_records: usize,
+    pub fai_path: String,
+    // Only BGZF files have a block index
+    pub gzi_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct RegionSequence {
+    pub name: String,
+    // One-based and inclusive, as in the region string
+    pub start: u64,
+    pub end: u64,
+    pub seq: String,
+    pub qual: Option<String>,
+}
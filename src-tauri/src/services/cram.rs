@@ -0,0 +1,1564 @@
+use crate::services::faidx::{load_or_build_index, IndexedReader, Region, SeqIndex};
+use crate::services::rans::decode_rans4x8;
+use crate::services::sam::to_fastq_record;
+use bio::io::fastq;
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::ErrorKind::{InvalidData, InvalidInput, NotFound, UnexpectedEof};
+use std::io::{BufReader, Cursor, Error as IOError, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use xz2::read::XzDecoder;
+
+const FLAG_UNMAPPED: u16 = 0x4;
+
+// CRAM record flags
+const CF_QUAL_ARRAY: i32 = 0x1;
+const CF_DETACHED: i32 = 0x2;
+const CF_MATE_DOWNSTREAM: i32 = 0x4;
+const CF_UNKNOWN_BASES: i32 = 0x8;
+
+// Block content types
+const FILE_HEADER: u8 = 0;
+const MAPPED_SLICE: u8 = 2;
+const CORE_DATA: u8 = 5;
+
+/// Slices holding reads from several references mark them with this ID.
+const MULTI_REF: i32 = -2;
+
+/// How many reference bases to fetch at once, so neighbouring reads share a read.
+const REF_WINDOW: u64 = 1 << 20;
+// The longest bit code read into a 32-bit value, so shifts by it cannot overflow
+const MAX_CODE_BITS: u32 = 31;
+
+/// The bases a substitution code can refer to, in order, for each reference base.
+const SUB_BASES: &[u8; 5] = b"ACGTN";
+
+/// Read the primary records of a CRAM 3 file as FASTQ records, in their original
+/// sequencing orientation. Aligned reads are rebuilt from `reference`, the FASTA
+/// file they were aligned against, unless the file embeds its reference.
+pub fn load_cram_records(
+    path: &Path,
+    reference: Option<&Path>,
+) -> Result<Vec<fastq::Record>, IOError> {
+    open_cram_records(path, reference)?.collect()
+}
+
+/// Where each slice of a CRAM file holding primary records starts, and the
+/// index of its first record, so single records can be decoded from their
+/// slice alone.
+pub fn cram_slices(path: &Path, reference: Option<&Path>) -> Result<Vec<CramSlice>, IOError> {
+    let mut records = open_cram_records(path, reference)?;
+    let mut slices = Vec::new();
+    while let Some((slice, decoded)) = records.next_slice()? {
+        if !decoded.is_empty() {
+            slices.push(slice);
+        }
+    }
+    Ok(slices)
+}
+
+/// Decode only the primary records of one slice found by `cram_slices`.
+pub fn load_cram_slice(
+    path: &Path,
+    reference: Option<&Path>,
+    slice: &CramSlice,
+) -> Result<Vec<fastq::Record>, IOError> {
+    let mut records = open_cram_records(path, reference)?;
+    records.seek_slice(slice)?;
+    Ok(records
+        .next_slice()?
+        .map_or_else(Vec::new, |(_, decoded)| decoded))
+}
+
+/// A slice of a CRAM file, and the index of the first primary record in it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CramSlice {
+    // Where the container holding the slice starts in the file
+    pub container: u64,
+    // How many slices come before it in its container
+    pub slice: usize,
+    pub first_record: usize,
+}
+
+/// Decodes the primary records of a CRAM 3 file a slice at a time, so only the
+/// records of one slice are held at once.
+pub struct CramRecords {
+    reader: BufReader<File>,
+    refs: References,
+    container: Option<Container>,
+    decoded: std::vec::IntoIter<fastq::Record>,
+    n_decoded: usize,
+    // How far through the file the containers read so far reach
+    offset: u64,
+}
+
+/// A container being decoded, and how far through its slices decoding is.
+struct Container {
+    offset: u64,
+    compression: CompressionHeader,
+    data: Vec<u8>,
+    pos: usize,
+    n_slices: usize,
+}
+
+/// Open a CRAM 3 file to decode its primary records, reading its SAM header.
+pub fn open_cram_records(path: &Path, reference: Option<&Path>) -> Result<CramRecords, IOError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut definition = [0; 26];
+    reader.read_exact(&mut definition)?;
+    if &definition[..4] != b"CRAM" {
+        return Err(IOError::new(
+            InvalidData,
+            format!("{} is not a CRAM file.", path.display()),
+        ));
+    }
+    if definition[4] != 3 {
+        return Err(IOError::new(
+            InvalidData,
+            format!(
+                "{} is a CRAM {}.{} file; only CRAM 3 is supported.",
+                path.display(),
+                definition[4],
+                definition[5]
+            ),
+        ));
+    }
+
+    let header = read_container_header(&mut reader)?
+        .ok_or_else(|| IOError::new(InvalidData, "CRAM file has no SAM header."))?;
+    let mut data = vec![0; header.length];
+    reader.read_exact(&mut data)?;
+    let text = read_sam_header(&data)?;
+    let refs = References::new(path, &text, reference)?;
+
+    Ok(CramRecords {
+        offset: reader.stream_position()?,
+        reader,
+        refs,
+        container: None,
+        decoded: Vec::new().into_iter(),
+        n_decoded: 0,
+    })
+}
+
+impl CramRecords {
+    /// How far through the file the records decoded so far reach.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Decode the primary records of the next slice, along with where it is.
+    pub fn next_slice(&mut self) -> Result<Option<(CramSlice, Vec<fastq::Record>)>, IOError> {
+        loop {
+            if let Some(container) = self.container.as_mut() {
+                let mut data = &container.data[container.pos..];
+                while !data.is_empty() {
+                    let block = read_block(&mut data)?;
+                    if block.content_type != MAPPED_SLICE {
+                        continue;
+                    }
+                    let slice = SliceHeader::parse(&block.data)?;
+                    let mut blocks = SliceBlocks::default();
+                    for _ in 0..slice.n_blocks {
+                        let block = read_block(&mut data)?;
+                        if block.content_type == CORE_DATA {
+                            blocks.core = BitReader::new(block.data);
+                        } else {
+                            blocks
+                                .external
+                                .insert(block.content_id, Cursor::new(block.data));
+                        }
+                    }
+                    let mut records = Vec::new();
+                    decode_slice(
+                        &container.compression,
+                        &slice,
+                        &mut blocks,
+                        &mut self.refs,
+                        &mut records,
+                    )?;
+
+                    container.pos = container.data.len() - data.len();
+                    let location = CramSlice {
+                        container: container.offset,
+                        slice: container.n_slices,
+                        first_record: self.n_decoded,
+                    };
+                    container.n_slices += 1;
+                    self.n_decoded += records.len();
+                    return Ok(Some((location, records)));
+                }
+                self.container = None;
+            }
+            if !self.read_container()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Move to a slice found by `cram_slices`, skipping the slices before it in
+    /// its container without decoding them.
+    pub fn seek_slice(&mut self, slice: &CramSlice) -> Result<(), IOError> {
+        self.reader.seek(SeekFrom::Start(slice.container))?;
+        self.decoded = Vec::new().into_iter();
+        if !self.read_container()? {
+            return Err(IOError::new(
+                InvalidData,
+                "There is no CRAM container at the slice's offset.",
+            ));
+        }
+        let container = self.container.as_mut().unwrap();
+        let mut data = &container.data[container.pos..];
+        while container.n_slices < slice.slice && !data.is_empty() {
+            let block = read_block(&mut data)?;
+            if block.content_type != MAPPED_SLICE {
+                continue;
+            }
+            for _ in 0..SliceHeader::parse(&block.data)?.n_blocks {
+                skip_block(&mut data)?;
+            }
+            container.n_slices += 1;
+        }
+        container.pos = container.data.len() - data.len();
+        self.n_decoded = slice.first_record;
+        Ok(())
+    }
+
+    /// Read the next container holding records, returning false at the end of
+    /// the file.
+    fn read_container(&mut self) -> Result<bool, IOError> {
+        loop {
+            let offset = self.reader.stream_position()?;
+            let header = match read_container_header(&mut self.reader)? {
+                Some(header) => header,
+                None => return Ok(false),
+            };
+            let mut data = vec![0; header.length];
+            self.reader.read_exact(&mut data)?;
+            self.offset = self.reader.stream_position()?;
+            if header.n_records == 0 {
+                // The end-of-file marker, or a container of header padding
+                continue;
+            }
+
+            let mut rest = data.as_slice();
+            let compression = CompressionHeader::parse(&read_block(&mut rest)?.data)?;
+            self.container = Some(Container {
+                offset,
+                compression,
+                pos: data.len() - rest.len(),
+                data,
+                n_slices: 0,
+            });
+            return Ok(true);
+        }
+    }
+}
+
+impl Iterator for CramRecords {
+    type Item = Result<fastq::Record, IOError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(rec) = self.decoded.next() {
+                return Some(Ok(rec));
+            }
+            match self.next_slice() {
+                Ok(Some((_, records))) => self.decoded = records.into_iter(),
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+struct ContainerHeader {
+    length: usize,
+    n_records: i32,
+}
+
+/// Read the next container header, or `None` at the end of the file.
+fn read_container_header<R: Read>(reader: &mut R) -> Result<Option<ContainerHeader>, IOError> {
+    let mut length = [0; 4];
+    match reader.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(err) if err.kind() == UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let length = i32::from_le_bytes(length);
+    if length < 0 {
+        return Err(IOError::new(
+            InvalidData,
+            "CRAM container length is negative.",
+        ));
+    }
+    let _ref_id = read_itf8(reader)?;
+    let _start = read_itf8(reader)?;
+    let _span = read_itf8(reader)?;
+    let n_records = read_itf8(reader)?;
+    let _record_counter = read_ltf8(reader)?;
+    let _bases = read_ltf8(reader)?;
+    let _n_blocks = read_itf8(reader)?;
+    for _ in 0..read_itf8(reader)? {
+        let _landmark = read_itf8(reader)?;
+    }
+    let _crc32 = read_i32(reader)?;
+
+    Ok(Some(ContainerHeader {
+        length: length as usize,
+        n_records,
+    }))
+}
+
+/// The SAM header text held in the first container.
+fn read_sam_header(mut data: &[u8]) -> Result<String, IOError> {
+    let block = read_block(&mut data)?;
+    if block.content_type != FILE_HEADER {
+        return Err(IOError::new(InvalidData, "CRAM file has no SAM header."));
+    }
+    let mut text = block.data.as_slice();
+    let len = read_i32(&mut text)?.max(0) as usize;
+    let text = text.get(..len).unwrap_or(text);
+    Ok(String::from_utf8_lossy(text).into_owned())
+}
+
+struct Block {
+    content_type: u8,
+    content_id: i32,
+    data: Vec<u8>,
+}
+
+fn read_block(data: &mut &[u8]) -> Result<Block, IOError> {
+    let method = read_u8(data)?;
+    let content_type = read_u8(data)?;
+    let content_id = read_itf8(data)?;
+    let size = read_len(data)?;
+    let raw_size = read_len(data)?;
+    let compressed = read_bytes(data, size)?;
+    let _crc32 = read_i32(data)?;
+
+    let mut raw = Vec::with_capacity(raw_size);
+    match method {
+        // Empty blocks may be marked compressed without any compressed data
+        _ if raw_size == 0 => {}
+        0 => raw = compressed,
+        1 => {
+            MultiGzDecoder::new(compressed.as_slice()).read_to_end(&mut raw)?;
+        }
+        2 => {
+            MultiBzDecoder::new(compressed.as_slice()).read_to_end(&mut raw)?;
+        }
+        3 => {
+            XzDecoder::new_multi_decoder(compressed.as_slice()).read_to_end(&mut raw)?;
+        }
+        4 => raw = decode_rans4x8(&compressed)?,
+        _ => {
+            let codec = match method {
+                5 => "rANS Nx16",
+                6 => "arithmetic",
+                7 => "fqzcomp",
+                8 => "name tokeniser",
+                _ => "unknown",
+            };
+            return Err(IOError::new(
+                InvalidData,
+                format!(
+                    "The CRAM file uses the {} codec, which is not supported. \
+                     Rewrite it as CRAM 3.0, e.g. with `samtools view -O cram,version=3.0`.",
+                    codec
+                ),
+            ));
+        }
+    }
+    if raw.len() != raw_size {
+        return Err(IOError::new(
+            InvalidData,
+            "CRAM block does not decompress to its stated size.",
+        ));
+    }
+
+    Ok(Block {
+        content_type,
+        content_id,
+        data: raw,
+    })
+}
+
+/// Move past a block without decompressing it.
+fn skip_block(data: &mut &[u8]) -> Result<(), IOError> {
+    let _method = read_u8(data)?;
+    let _content_type = read_u8(data)?;
+    let _content_id = read_itf8(data)?;
+    let size = read_len(data)?;
+    let _raw_size = read_len(data)?;
+    // The data is followed by its CRC32
+    if data.len() < size + 4 {
+        return Err(IOError::new(UnexpectedEof, "CRAM data is truncated."));
+    }
+    *data = &data[size + 4..];
+    Ok(())
+}
+
+/// How the records of a container are encoded.
+struct CompressionHeader {
+    read_names: bool,
+    ap_delta: bool,
+    ref_required: bool,
+    // The base for each substitution code, by reference base
+    substitutions: [[u8; 4]; 5],
+    // The tags of each record, as the keys of their encodings
+    tag_lines: Vec<Vec<i32>>,
+    series: HashMap<[u8; 2], Encoding>,
+    tags: HashMap<i32, Encoding>,
+}
+
+impl CompressionHeader {
+    fn parse(mut data: &[u8]) -> Result<Self, IOError> {
+        let mut header = CompressionHeader {
+            read_names: true,
+            ap_delta: true,
+            ref_required: true,
+            substitutions: [*b"CGTN", *b"AGTN", *b"ACTN", *b"ACGN", *b"ACGT"],
+            tag_lines: Vec::new(),
+            series: HashMap::new(),
+            tags: HashMap::new(),
+        };
+
+        let preservation = read_map(&mut data)?;
+        let mut map = preservation.as_slice();
+        for _ in 0..read_itf8(&mut map)? {
+            let key = read_bytes(&mut map, 2)?;
+            match key.as_slice() {
+                b"RN" => header.read_names = read_u8(&mut map)? != 0,
+                b"AP" => header.ap_delta = read_u8(&mut map)? != 0,
+                b"RR" => header.ref_required = read_u8(&mut map)? != 0,
+                b"SM" => {
+                    let matrix = read_bytes(&mut map, 5)?;
+                    for (ref_idx, codes) in matrix.iter().enumerate() {
+                        let alts = SUB_BASES.iter().filter(|b| **b != SUB_BASES[ref_idx]);
+                        for (i, alt) in alts.enumerate() {
+                            let code = (codes >> (6 - 2 * i)) & 0x3;
+                            header.substitutions[ref_idx][usize::from(code)] = *alt;
+                        }
+                    }
+                }
+                b"TD" => {
+                    let len = read_len(&mut map)?;
+                    let dictionary = read_bytes(&mut map, len)?;
+                    header.tag_lines = dictionary
+                        .split(|b| *b == 0)
+                        .map(|line| {
+                            line.chunks(3)
+                                .filter(|tag| tag.len() == 3)
+                                .map(|tag| {
+                                    i32::from(tag[0]) << 16
+                                        | i32::from(tag[1]) << 8
+                                        | i32::from(tag[2])
+                                })
+                                .collect()
+                        })
+                        .collect();
+                }
+                key => {
+                    return Err(IOError::new(
+                        InvalidData,
+                        format!(
+                            "Unknown CRAM preservation key {}.",
+                            String::from_utf8_lossy(key)
+                        ),
+                    ))
+                }
+            }
+        }
+
+        let series = read_map(&mut data)?;
+        let mut map = series.as_slice();
+        for _ in 0..read_itf8(&mut map)? {
+            let key = read_bytes(&mut map, 2)?;
+            let encoding = Encoding::parse(&mut map)?;
+            header.series.insert([key[0], key[1]], encoding);
+        }
+
+        let tags = read_map(&mut data)?;
+        let mut map = tags.as_slice();
+        for _ in 0..read_itf8(&mut map)? {
+            let key = read_itf8(&mut map)?;
+            let encoding = Encoding::parse(&mut map)?;
+            header.tags.insert(key, encoding);
+        }
+
+        Ok(header)
+    }
+
+    fn series(&self, key: &[u8; 2]) -> Result<&Encoding, IOError> {
+        self.series.get(key).ok_or_else(|| {
+            IOError::new(
+                InvalidData,
+                format!(
+                    "CRAM container has no encoding for the {} data series.",
+                    String::from_utf8_lossy(key)
+                ),
+            )
+        })
+    }
+}
+
+/// The bytes of a map whose size comes first.
+fn read_map(data: &mut &[u8]) -> Result<Vec<u8>, IOError> {
+    let len = read_len(data)?;
+    read_bytes(data, len)
+}
+
+struct SliceHeader {
+    ref_id: i32,
+    start: i64,
+    n_records: i32,
+    record_counter: i64,
+    n_blocks: i32,
+    embedded_ref: i32,
+}
+
+impl SliceHeader {
+    fn parse(mut data: &[u8]) -> Result<Self, IOError> {
+        let ref_id = read_itf8(&mut data)?;
+        let start = i64::from(read_itf8(&mut data)?);
+        let _span = read_itf8(&mut data)?;
+        let n_records = read_itf8(&mut data)?;
+        let record_counter = read_ltf8(&mut data)?;
+        let n_blocks = read_itf8(&mut data)?;
+        for _ in 0..read_itf8(&mut data)? {
+            let _content_id = read_itf8(&mut data)?;
+        }
+        let embedded_ref = read_itf8(&mut data)?;
+
+        Ok(SliceHeader {
+            ref_id,
+            start,
+            n_records,
+            record_counter,
+            n_blocks,
+            embedded_ref,
+        })
+    }
+}
+
+/// The core bit stream and external byte streams of one slice.
+#[derive(Default)]
+struct SliceBlocks {
+    core: BitReader,
+    external: HashMap<i32, Cursor<Vec<u8>>>,
+}
+
+impl SliceBlocks {
+    fn external(&mut self, content_id: i32) -> Result<&mut Cursor<Vec<u8>>, IOError> {
+        self.external.get_mut(&content_id).ok_or_else(|| {
+            IOError::new(
+                InvalidData,
+                format!("CRAM slice has no external block {}.", content_id),
+            )
+        })
+    }
+}
+
+#[derive(Default)]
+struct BitReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitReader {
+    fn new(data: Vec<u8>) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Result<u32, IOError> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or_else(|| IOError::new(InvalidData, "CRAM core data is truncated."))?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(u32::from(bit))
+    }
+
+    fn bits(&mut self, n_bits: u32) -> Result<u32, IOError> {
+        if n_bits > MAX_CODE_BITS {
+            return Err(code_too_long());
+        }
+        let mut value = 0;
+        for _ in 0..n_bits {
+            value = value << 1 | self.bit()?;
+        }
+        Ok(value)
+    }
+}
+
+/// How one data series or tag is encoded.
+enum Encoding {
+    Null,
+    External(i32),
+    Huffman(Huffman),
+    ByteArrayLen(Box<Encoding>, Box<Encoding>),
+    ByteArrayStop(u8, i32),
+    Beta { offset: i32, bits: u32 },
+    Subexp { offset: i32, k: u32 },
+    Gamma { offset: i32 },
+}
+
+impl Encoding {
+    fn parse(data: &mut &[u8]) -> Result<Self, IOError> {
+        let codec = read_itf8(data)?;
+        let len = read_len(data)?;
+        let params = read_bytes(data, len)?;
+        let mut params = params.as_slice();
+        let encoding = match codec {
+            0 => Encoding::Null,
+            1 => Encoding::External(read_itf8(&mut params)?),
+            3 => {
+                let symbols = read_itf8_array(&mut params)?;
+                let lengths = read_itf8_array(&mut params)?;
+                Encoding::Huffman(Huffman::new(&symbols, &lengths)?)
+            }
+            4 => Encoding::ByteArrayLen(
+                Box::new(Encoding::parse(&mut params)?),
+                Box::new(Encoding::parse(&mut params)?),
+            ),
+            5 => Encoding::ByteArrayStop(read_u8(&mut params)?, read_itf8(&mut params)?),
+            6 => Encoding::Beta {
+                offset: read_itf8(&mut params)?,
+                bits: read_code_bits(&mut params)?,
+            },
+            7 => Encoding::Subexp {
+                offset: read_itf8(&mut params)?,
+                k: read_code_bits(&mut params)?,
+            },
+            9 => Encoding::Gamma {
+                offset: read_itf8(&mut params)?,
+            },
+            _ => {
+                return Err(IOError::new(
+                    InvalidData,
+                    format!("CRAM encoding {} is not supported.", codec),
+                ))
+            }
+        };
+        Ok(encoding)
+    }
+
+    fn int(&self, blocks: &mut SliceBlocks) -> Result<i32, IOError> {
+        match self {
+            Encoding::External(id) => read_itf8(blocks.external(*id)?),
+            Encoding::Huffman(huffman) => huffman.decode(&mut blocks.core),
+            Encoding::Beta { offset, bits } => minus_offset(blocks.core.bits(*bits)?, *offset),
+            Encoding::Subexp { offset, k } => {
+                let core = &mut blocks.core;
+                let mut n = 0;
+                while core.bit()? == 1 {
+                    n += 1;
+                    if n > MAX_CODE_BITS {
+                        return Err(code_too_long());
+                    }
+                }
+                let value = if n == 0 {
+                    core.bits(*k)?
+                } else {
+                    let bits = n + k - 1;
+                    if bits > MAX_CODE_BITS {
+                        return Err(code_too_long());
+                    }
+                    1 << bits | core.bits(bits)?
+                };
+                minus_offset(value, *offset)
+            }
+            Encoding::Gamma { offset } => {
+                let core = &mut blocks.core;
+                let mut n = 0;
+                while core.bit()? == 0 {
+                    n += 1;
+                    if n > MAX_CODE_BITS {
+                        return Err(code_too_long());
+                    }
+                }
+                minus_offset(1 << n | core.bits(n)?, *offset)
+            }
+            _ => Err(IOError::new(
+                InvalidData,
+                "CRAM integer data has an unsupported encoding.",
+            )),
+        }
+    }
+
+    fn byte(&self, blocks: &mut SliceBlocks) -> Result<u8, IOError> {
+        match self {
+            Encoding::External(id) => read_u8(blocks.external(*id)?),
+            _ => Ok(self.int(blocks)? as u8),
+        }
+    }
+
+    /// An array of bytes, whose length is only needed by plain external encodings.
+    fn bytes(&self, blocks: &mut SliceBlocks, len: Option<usize>) -> Result<Vec<u8>, IOError> {
+        match (self, len) {
+            (Encoding::ByteArrayLen(lengths, values), _) => {
+                let len = lengths.int(blocks)?.max(0) as usize;
+                values.bytes(blocks, Some(len))
+            }
+            (Encoding::ByteArrayStop(stop, id), _) => {
+                let external = blocks.external(*id)?;
+                let mut bytes = Vec::new();
+                loop {
+                    let byte = read_u8(external)?;
+                    if byte == *stop {
+                        return Ok(bytes);
+                    }
+                    bytes.push(byte);
+                }
+            }
+            (Encoding::External(id), Some(len)) => read_bytes(blocks.external(*id)?, len),
+            (Encoding::Null, _) | (_, None) => Err(IOError::new(
+                InvalidData,
+                "CRAM byte array has an unsupported encoding.",
+            )),
+            (_, Some(len)) => (0..len).map(|_| self.byte(blocks)).collect(),
+        }
+    }
+}
+
+/// The bit count of a Beta or Subexp code, which must fit a 32-bit value.
+fn read_code_bits(data: &mut &[u8]) -> Result<u32, IOError> {
+    let bits = read_itf8(data)?;
+    if bits < 0 || bits as u32 > MAX_CODE_BITS {
+        return Err(code_too_long());
+    }
+    Ok(bits as u32)
+}
+
+fn code_too_long() -> IOError {
+    IOError::new(InvalidData, "CRAM bit code is too long to decode.")
+}
+
+/// A decoded value less the offset its encoding stores it with.
+fn minus_offset(value: u32, offset: i32) -> Result<i32, IOError> {
+    (value as i32)
+        .checked_sub(offset)
+        .ok_or_else(|| IOError::new(InvalidData, "CRAM integer is out of range."))
+}
+
+/// A canonical Huffman code.
+struct Huffman {
+    // (bit length, code, symbol), shortest codes first
+    codes: Vec<(u32, u32, i32)>,
+}
+
+impl Huffman {
+    fn new(symbols: &[i32], lengths: &[i32]) -> Result<Self, IOError> {
+        let malformed = || IOError::new(InvalidData, "CRAM Huffman code is malformed.");
+        if symbols.len() != lengths.len()
+            || lengths
+                .iter()
+                .any(|len| *len < 0 || *len as u32 > MAX_CODE_BITS)
+        {
+            return Err(malformed());
+        }
+        let mut sorted: Vec<(u32, i32)> = lengths
+            .iter()
+            .zip(symbols)
+            .map(|(len, sym)| (*len as u32, *sym))
+            .collect();
+        sorted.sort_unstable();
+
+        let mut codes = Vec::with_capacity(sorted.len());
+        let mut code = 0;
+        let mut prev_len = sorted.first().map_or(0, |(len, _)| *len);
+        for (len, sym) in sorted {
+            code <<= len - prev_len;
+            // More codes than fit in their length
+            if code >> len != 0 {
+                return Err(malformed());
+            }
+            codes.push((len, code, sym));
+            code += 1;
+            prev_len = len;
+        }
+        Ok(Huffman { codes })
+    }
+
+    fn decode(&self, core: &mut BitReader) -> Result<i32, IOError> {
+        let mut len = 0;
+        let mut value = 0;
+        for (code_len, code, sym) in &self.codes {
+            while len < *code_len {
+                value = value << 1 | core.bit()?;
+                len += 1;
+            }
+            if value == *code {
+                return Ok(*sym);
+            }
+        }
+        Err(IOError::new(
+            InvalidData,
+            "CRAM Huffman code is not in the table.",
+        ))
+    }
+}
+
+/// A change from the reference at a position in a read.
+enum Feature {
+    Bases(Vec<u8>),
+    Substitution(u8),
+    BaseQual(u8, u8),
+    Insertion(Vec<u8>),
+    SoftClip(Vec<u8>),
+    Deletion(u64),
+    RefSkip(u64),
+    Quals(Vec<u8>),
+    Qual(u8),
+    Padding,
+}
+
+fn decode_slice(
+    header: &CompressionHeader,
+    slice: &SliceHeader,
+    blocks: &mut SliceBlocks,
+    refs: &mut References,
+    records: &mut Vec<fastq::Record>,
+) -> Result<(), IOError> {
+    if slice.embedded_ref >= 0 {
+        let bases = blocks.external(slice.embedded_ref)?.get_ref().clone();
+        refs.window = Some((slice.ref_id, (slice.start - 1).max(0) as u64, bases));
+    }
+
+    let mut prev_pos = slice.start;
+    for i in 0..slice.n_records {
+        let flag = header.series(b"BF")?.int(blocks)? as u16;
+        let cram_flags = header.series(b"CF")?.int(blocks)?;
+        let ref_id = if slice.ref_id == MULTI_REF {
+            header.series(b"RI")?.int(blocks)?
+        } else {
+            slice.ref_id
+        };
+        let read_len = header.series(b"RL")?.int(blocks)?.max(0) as usize;
+        let mut pos = i64::from(header.series(b"AP")?.int(blocks)?);
+        if header.ap_delta {
+            pos += prev_pos;
+        }
+        prev_pos = pos;
+        let _read_group = header.series(b"RG")?.int(blocks)?;
+        let mut name = None;
+        if header.read_names {
+            name = Some(header.series(b"RN")?.bytes(blocks, None)?);
+        }
+
+        if cram_flags & CF_DETACHED != 0 {
+            let _mate_flags = header.series(b"MF")?.int(blocks)?;
+            if !header.read_names {
+                name = Some(header.series(b"RN")?.bytes(blocks, None)?);
+            }
+            let _mate_ref_id = header.series(b"NS")?.int(blocks)?;
+            let _mate_pos = header.series(b"NP")?.int(blocks)?;
+            let _template_len = header.series(b"TS")?.int(blocks)?;
+        } else if cram_flags & CF_MATE_DOWNSTREAM != 0 {
+            let _next_fragment = header.series(b"NF")?.int(blocks)?;
+        }
+
+        let tag_line = header.series(b"TL")?.int(blocks)?;
+        let tag_keys = usize::try_from(tag_line)
+            .ok()
+            .and_then(|line| header.tag_lines.get(line))
+            .ok_or_else(|| IOError::new(InvalidData, "CRAM record has an unknown tag line."))?;
+        for key in tag_keys {
+            skip_tag(header, *key, blocks)?;
+        }
+
+        let mut seq = Vec::new();
+        let mut qual = vec![0; read_len];
+        if flag & FLAG_UNMAPPED == 0 {
+            let n_features = header.series(b"FN")?.int(blocks)?;
+            let mut features = Vec::new();
+            let mut feature_pos = 0;
+            for _ in 0..n_features {
+                let code = header.series(b"FC")?.byte(blocks)?;
+                feature_pos += header.series(b"FP")?.int(blocks)?;
+                let feature = match code {
+                    b'b' => Feature::Bases(header.series(b"BB")?.bytes(blocks, None)?),
+                    b'X' => Feature::Substitution(header.series(b"BS")?.byte(blocks)?),
+                    b'B' => Feature::BaseQual(
+                        header.series(b"BA")?.byte(blocks)?,
+                        header.series(b"QS")?.byte(blocks)?,
+                    ),
+                    b'I' => Feature::Insertion(header.series(b"IN")?.bytes(blocks, None)?),
+                    b'i' => Feature::Insertion(vec![header.series(b"BA")?.byte(blocks)?]),
+                    b'S' => Feature::SoftClip(header.series(b"SC")?.bytes(blocks, None)?),
+                    b'D' => Feature::Deletion(header.series(b"DL")?.int(blocks)?.max(0) as u64),
+                    b'N' => Feature::RefSkip(header.series(b"RS")?.int(blocks)?.max(0) as u64),
+                    b'q' => Feature::Quals(header.series(b"QQ")?.bytes(blocks, None)?),
+                    b'Q' => Feature::Qual(header.series(b"QS")?.byte(blocks)?),
+                    b'H' => {
+                        let _hard_clip = header.series(b"HC")?.int(blocks)?;
+                        Feature::Padding
+                    }
+                    b'P' => {
+                        let _padding = header.series(b"PD")?.int(blocks)?;
+                        Feature::Padding
+                    }
+                    _ => {
+                        return Err(IOError::new(
+                            InvalidData,
+                            format!("Unknown CRAM read feature '{}'.", char::from(code)),
+                        ))
+                    }
+                };
+                features.push(((feature_pos - 1).max(0) as usize, feature));
+            }
+            let _mapping_quality = header.series(b"MQ")?.int(blocks)?;
+            if cram_flags & CF_QUAL_ARRAY != 0 {
+                qual = read_quals(header, blocks, read_len)?;
+            }
+
+            if cram_flags & CF_UNKNOWN_BASES == 0 {
+                let ref_start = (pos - 1).max(0) as u64;
+                // Files with an embedded reference mark it as not required externally
+                let bases = if header.ref_required || slice.embedded_ref >= 0 {
+                    let ref_len = ref_span(&features, read_len);
+                    Some(refs.bases(ref_id, ref_start, ref_start + ref_len)?)
+                } else {
+                    None
+                };
+                seq = apply_features(header, &features, read_len, bases, &mut qual);
+            }
+        } else {
+            if cram_flags & CF_UNKNOWN_BASES == 0 {
+                let bases = header.series(b"BA")?;
+                seq = (0..read_len)
+                    .map(|_| bases.byte(blocks))
+                    .collect::<Result<_, _>>()?;
+            }
+            if cram_flags & CF_QUAL_ARRAY != 0 {
+                qual = read_quals(header, blocks, read_len)?;
+            }
+        }
+
+        let name = match name {
+            Some(name) => String::from_utf8_lossy(&name).into_owned(),
+            None => (slice.record_counter + i64::from(i) + 1).to_string(),
+        };
+        qual.truncate(seq.len());
+        // Missing qualities are stored as 0xff and reported as the lowest score
+        let qual: Vec<u8> = qual
+            .iter()
+            .map(|q| if *q == 0xff { b'!' } else { q.min(&93) + 33 })
+            .collect();
+        if let Some(rec) = to_fastq_record(&name, flag, &seq, &qual) {
+            records.push(rec);
+        }
+    }
+    Ok(())
+}
+
+fn read_quals(
+    header: &CompressionHeader,
+    blocks: &mut SliceBlocks,
+    read_len: usize,
+) -> Result<Vec<u8>, IOError> {
+    let quals = header.series(b"QS")?;
+    (0..read_len).map(|_| quals.byte(blocks)).collect()
+}
+
+fn skip_tag(header: &CompressionHeader, key: i32, blocks: &mut SliceBlocks) -> Result<(), IOError> {
+    let encoding = header
+        .tags
+        .get(&key)
+        .ok_or_else(|| IOError::new(InvalidData, "CRAM container has no encoding for a tag."))?;
+    // Single values of a fixed size can be stored as plain external bytes
+    let fixed_len = match key as u8 {
+        b'A' | b'c' | b'C' => Some(1),
+        b's' | b'S' => Some(2),
+        b'i' | b'I' | b'f' => Some(4),
+        _ => None,
+    };
+    encoding.bytes(blocks, fixed_len)?;
+    Ok(())
+}
+
+/// How many reference bases a read covers, from its first aligned base.
+fn ref_span(features: &[(usize, Feature)], read_len: usize) -> u64 {
+    let mut read_pos = 0;
+    let mut ref_len = 0;
+    for (pos, feature) in features {
+        ref_len += pos.saturating_sub(read_pos) as u64;
+        read_pos = read_pos.max(*pos);
+        match feature {
+            Feature::Bases(bases) => {
+                read_pos += bases.len();
+                ref_len += bases.len() as u64;
+            }
+            Feature::Substitution(_) | Feature::BaseQual(..) => {
+                read_pos += 1;
+                ref_len += 1;
+            }
+            Feature::Insertion(bases) | Feature::SoftClip(bases) => read_pos += bases.len(),
+            Feature::Deletion(len) | Feature::RefSkip(len) => ref_len += len,
+            Feature::Quals(_) | Feature::Qual(_) | Feature::Padding => {}
+        }
+    }
+    ref_len + read_len.saturating_sub(read_pos) as u64
+}
+
+/// Rebuild a read's bases from the reference and its features, filling in any
+/// qualities the features carry. Without a reference, matched bases are N.
+fn apply_features(
+    header: &CompressionHeader,
+    features: &[(usize, Feature)],
+    read_len: usize,
+    reference: Option<&[u8]>,
+    qual: &mut [u8],
+) -> Vec<u8> {
+    let ref_base = |ref_pos: usize| {
+        reference
+            .and_then(|bases| bases.get(ref_pos))
+            .map_or(b'N', |base| base.to_ascii_uppercase())
+    };
+    let mut seq = Vec::with_capacity(read_len);
+    let mut ref_pos = 0;
+    for (pos, feature) in features {
+        while seq.len() < (*pos).min(read_len) {
+            seq.push(ref_base(ref_pos));
+            ref_pos += 1;
+        }
+        match feature {
+            Feature::Bases(bases) => {
+                seq.extend_from_slice(bases);
+                ref_pos += bases.len();
+            }
+            Feature::Substitution(code) => {
+                let base = ref_base(ref_pos);
+                let ref_idx = SUB_BASES.iter().position(|b| *b == base).unwrap_or(4);
+                seq.push(header.substitutions[ref_idx][usize::from(code & 0x3)]);
+                ref_pos += 1;
+            }
+            Feature::BaseQual(base, q) => {
+                if let Some(slot) = qual.get_mut(seq.len()) {
+                    *slot = *q;
+                }
+                seq.push(*base);
+                ref_pos += 1;
+            }
+            Feature::Insertion(bases) | Feature::SoftClip(bases) => seq.extend_from_slice(bases),
+            Feature::Deletion(len) | Feature::RefSkip(len) => ref_pos += *len as usize,
+            Feature::Quals(quals) => {
+                for (slot, q) in qual.iter_mut().skip(*pos).zip(quals) {
+                    *slot = *q;
+                }
+            }
+            Feature::Qual(q) => {
+                if let Some(slot) = qual.get_mut(*pos) {
+                    *slot = *q;
+                }
+            }
+            Feature::Padding => {}
+        }
+    }
+    while seq.len() < read_len {
+        seq.push(ref_base(ref_pos));
+        ref_pos += 1;
+    }
+    seq.truncate(read_len);
+    seq
+}
+
+/// The reference sequences named in the SAM header, read from a FASTA file.
+struct References {
+    cram: PathBuf,
+    // Name and length of each @SQ line, by reference ID
+    sequences: Vec<(String, Option<u64>)>,
+    fasta: Option<(PathBuf, SeqIndex)>,
+    // Reference ID, start and bases of the last fetched stretch
+    window: Option<(i32, u64, Vec<u8>)>,
+}
+
+impl References {
+    fn new(cram: &Path, header: &str, reference: Option<&Path>) -> Result<Self, IOError> {
+        let sequences = header
+            .lines()
+            .filter(|line| line.starts_with("@SQ\t"))
+            .map(|line| {
+                let field = |tag: &str| {
+                    line.split('\t')
+                        .find_map(|field| field.strip_prefix(tag))
+                        .map(|value| value.to_owned())
+                };
+                let length = field("LN:").and_then(|len| len.parse().ok());
+                (field("SN:").unwrap_or_default(), length)
+            })
+            .collect();
+        let fasta = match reference {
+            Some(reference) => Some((reference.to_path_buf(), load_or_build_index(reference)?)),
+            None => None,
+        };
+
+        Ok(References {
+            cram: cram.to_path_buf(),
+            sequences,
+            fasta,
+            window: None,
+        })
+    }
+
+    /// The reference bases from `start` to `end` of a reference, zero-based. Reads
+    /// can hang off the end of a reference, so fewer bases may be returned.
+    fn bases(&mut self, ref_id: i32, start: u64, end: u64) -> Result<&[u8], IOError> {
+        let length = usize::try_from(ref_id)
+            .ok()
+            .and_then(|id| self.sequences.get(id))
+            .and_then(|(_, length)| *length);
+        let covered = match &self.window {
+            Some((id, from, bases)) => {
+                let to = *from + bases.len() as u64;
+                *id == ref_id && *from <= start && (end <= to || Some(to) >= length)
+            }
+            None => false,
+        };
+        if !covered {
+            self.window = Some((ref_id, start, self.fetch(ref_id, start, end)?));
+        }
+        let (_, from, bases) = self.window.as_ref().unwrap();
+        let start = ((start - from) as usize).min(bases.len());
+        let end = ((end - from) as usize).min(bases.len());
+        Ok(&bases[start..end])
+    }
+
+    fn fetch(&self, ref_id: i32, start: u64, end: u64) -> Result<Vec<u8>, IOError> {
+        let (name, length) = usize::try_from(ref_id)
+            .ok()
+            .and_then(|id| self.sequences.get(id))
+            .ok_or_else(|| {
+                IOError::new(
+                    InvalidData,
+                    format!(
+                        "{} has a read aligned to an unknown reference.",
+                        self.cram.display()
+                    ),
+                )
+            })?;
+        let (reference, index) = self.fasta.as_ref().ok_or_else(|| {
+            IOError::new(
+                InvalidInput,
+                format!(
+                    "{} holds reads aligned to {}. Choose the FASTA reference it was \
+                     aligned against to read them.",
+                    self.cram.display(),
+                    name
+                ),
+            )
+        })?;
+        let entry = index.entry(name).ok_or_else(|| {
+            IOError::new(
+                NotFound,
+                format!("{} has no sequence named {}.", reference.display(), name),
+            )
+        })?;
+        if length.map_or(false, |length| length != entry.length) {
+            return Err(IOError::new(
+                InvalidInput,
+                format!(
+                    "{} in {} is not the length {} expects; choose the reference it was \
+                     aligned against.",
+                    name,
+                    reference.display(),
+                    self.cram.display()
+                ),
+            ));
+        }
+
+        let region = Region {
+            entry,
+            start: start.min(entry.length),
+            end: end.max(start + REF_WINDOW).min(entry.length),
+        };
+        let (bases, _) = IndexedReader::open(reference, index)?.fetch(&region)?;
+        Ok(bases)
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, IOError> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> Result<i32, IOError> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> Result<Vec<u8>, IOError> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(IOError::new(UnexpectedEof, "CRAM data is truncated."));
+    }
+    Ok(bytes)
+}
+
+fn read_len<R: Read>(reader: &mut R) -> Result<usize, IOError> {
+    let len = read_itf8(reader)?;
+    if len < 0 {
+        return Err(IOError::new(InvalidData, "CRAM length field is negative."));
+    }
+    Ok(len as usize)
+}
+
+fn read_itf8_array<R: Read>(reader: &mut R) -> Result<Vec<i32>, IOError> {
+    let len = read_len(reader)?;
+    (0..len).map(|_| read_itf8(reader)).collect()
+}
+
+/// Read a CRAM ITF-8 integer, whose leading one bits count the bytes that follow.
+fn read_itf8<R: Read>(reader: &mut R) -> Result<i32, IOError> {
+    let first = read_u8(reader)?;
+    let n_extra = first.leading_ones().min(4);
+    let mask = if n_extra == 4 {
+        0x0f
+    } else {
+        0xff >> (n_extra + 1)
+    };
+    let mut value = u32::from(first) & mask;
+    for i in 0..n_extra {
+        let byte = u32::from(read_u8(reader)?);
+        value = if i == 3 {
+            // The fifth byte only contributes its low four bits
+            value << 4 | (byte & 0x0f)
+        } else {
+            value << 8 | byte
+        };
+    }
+    Ok(value as i32)
+}
+
+/// Read a CRAM LTF-8 integer, the 64-bit form of ITF-8.
+fn read_ltf8<R: Read>(reader: &mut R) -> Result<i64, IOError> {
+    let first = read_u8(reader)?;
+    let n_extra = first.leading_ones();
+    let mut value = if n_extra >= 7 {
+        0
+    } else {
+        u64::from(first) & (0xff >> (n_extra + 1))
+    };
+    for _ in 0..n_extra {
+        value = value << 8 | u64::from(read_u8(reader)?);
+    }
+    Ok(value as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind::InvalidInput;
+
+    use uuid::Uuid;
+
+    use super::{
+        cram_slices, load_cram_records, load_cram_slice, open_cram_records, read_itf8, read_ltf8,
+        BitReader, Encoding, Huffman, SliceBlocks,
+    };
+    use std::collections::HashMap;
+
+    fn itf8(value: i32) -> Vec<u8> {
+        let v = value as u32;
+        match v {
+            0..=0x7f => vec![v as u8],
+            0x80..=0x3fff => vec![0x80 | (v >> 8) as u8, v as u8],
+            0x4000..=0x1f_ffff => vec![0xc0 | (v >> 16) as u8, (v >> 8) as u8, v as u8],
+            0x20_0000..=0x0fff_ffff => vec![
+                0xe0 | (v >> 24) as u8,
+                (v >> 16) as u8,
+                (v >> 8) as u8,
+                v as u8,
+            ],
+            _ => vec![
+                0xf0 | (v >> 28) as u8,
+                (v >> 20) as u8,
+                (v >> 12) as u8,
+                (v >> 4) as u8,
+                (v & 0x0f) as u8,
+            ],
+        }
+    }
+
+    /// A block stored without compression.
+    fn block(content_type: u8, content_id: i32, data: &[u8]) -> Vec<u8> {
+        let mut block = vec![0, content_type];
+        block.extend(itf8(content_id));
+        block.extend(itf8(data.len() as i32));
+        block.extend(itf8(data.len() as i32));
+        block.extend_from_slice(data);
+        block.extend_from_slice(&[0; 4]); // CRC32, not checked
+        block
+    }
+
+    fn container(ref_id: i32, n_records: i32, n_blocks: i32, blocks: &[u8]) -> Vec<u8> {
+        let mut container = (blocks.len() as i32).to_le_bytes().to_vec();
+        container.extend(itf8(ref_id));
+        container.extend(itf8(1)); // start
+        container.extend(itf8(20)); // span
+        container.extend(itf8(n_records));
+        container.extend_from_slice(&[0, 0]); // record counter and bases, as LTF-8
+        container.extend(itf8(n_blocks));
+        container.extend(itf8(0)); // no landmarks
+        container.extend_from_slice(&[0; 4]); // CRC32, not checked
+        container.extend_from_slice(blocks);
+        container
+    }
+
+    /// Put each data series in its own external block, numbered by position.
+    const SERIES: [&[u8; 2]; 14] = [
+        b"BF", b"CF", b"RL", b"AP", b"RG", b"RN", b"TL", b"FN", b"FC", b"FP", b"BS", b"MQ", b"QS",
+        b"BA",
+    ];
+
+    /// A CRAM file of four reads against `ref`, a reference of 20 bases: a
+    /// forward read with a substitution, a reverse read, an unmapped read and a
+    /// secondary alignment.
+    fn cram_file(n_containers: usize) -> Vec<u8> {
+        let mut cram = b"CRAM\x03\x00".to_vec();
+        cram.extend_from_slice(&[0; 20]);
+
+        let sam_header = b"@HD\tVN:1.6\n@SQ\tSN:ref\tLN:20\n";
+        let mut header_data = (sam_header.len() as i32).to_le_bytes().to_vec();
+        header_data.extend_from_slice(sam_header);
+        cram.extend(container(0, 0, 1, &block(0, 0, &header_data)));
+
+        let mut preservation = itf8(2);
+        preservation.extend_from_slice(b"AP\x00TD");
+        preservation.extend(itf8(1));
+        preservation.push(0); // one empty tag line
+        let mut series = itf8(SERIES.len() as i32);
+        for (id, key) in SERIES.iter().enumerate() {
+            series.extend_from_slice(*key);
+            let params = if **key == *b"RN" {
+                // Names end with a NUL
+                let mut params = vec![0];
+                params.extend(itf8(id as i32));
+                series.extend(itf8(5));
+                params
+            } else {
+                series.extend(itf8(1));
+                itf8(id as i32)
+            };
+            series.extend(itf8(params.len() as i32));
+            series.extend(params);
+        }
+        let mut compression = Vec::new();
+        for map in [preservation, series, itf8(0)].iter() {
+            compression.extend(itf8(map.len() as i32));
+            compression.extend_from_slice(map);
+        }
+
+        // (flag, read length, position, name, features, qualities, unmapped bases)
+        let reads: [(i32, i32, i32, &[u8], &[u8], &[u8], &[u8]); 4] = [
+            (0, 6, 3, b"r1", b"X", &[30, 30, 20, 30, 30, 30], b""),
+            (0x10, 4, 6, b"r2", b"", &[10, 20, 30, 40], b""),
+            (0x4, 3, 0, b"r3", b"", &[40, 40, 40], b"GGA"),
+            (0x100, 2, 1, b"r4", b"", &[40, 40], b""),
+        ];
+        let mut streams = vec![Vec::new(); SERIES.len()];
+        for (flag, read_len, pos, name, features, quals, bases) in reads.iter() {
+            streams[0].extend(itf8(*flag));
+            streams[1].extend(itf8(1)); // qualities stored as an array
+            streams[2].extend(itf8(*read_len));
+            streams[3].extend(itf8(*pos));
+            streams[4].extend(itf8(-1));
+            streams[5].extend_from_slice(name);
+            streams[5].push(0);
+            streams[6].extend(itf8(0));
+            if flag & 0x4 == 0 {
+                streams[7].extend(itf8(features.len() as i32));
+                for code in features.iter() {
+                    // A substitution of the second base, with the first alternative
+                    streams[8].push(*code);
+                    streams[9].extend(itf8(2));
+                    streams[10].push(0);
+                }
+                streams[11].extend(itf8(60));
+            } else {
+                streams[13].extend_from_slice(bases);
+            }
+            streams[12].extend_from_slice(quals);
+        }
+
+        let mut slice_header = itf8(0); // reference ID
+        slice_header.extend(itf8(1)); // start
+        slice_header.extend(itf8(20)); // span
+        slice_header.extend(itf8(reads.len() as i32));
+        slice_header.push(0); // record counter, as LTF-8
+        slice_header.extend(itf8(SERIES.len() as i32 + 1));
+        slice_header.extend(itf8(0)); // no block content IDs listed
+        slice_header.extend(itf8(-1)); // no embedded reference
+        slice_header.extend_from_slice(&[0; 16]); // reference MD5
+
+        let mut blocks = block(1, 0, &compression);
+        blocks.extend(block(2, 0, &slice_header));
+        blocks.extend(block(5, 0, &[]));
+        for (id, stream) in streams.iter().enumerate() {
+            blocks.extend(block(4, id as i32, stream));
+        }
+        for _ in 0..n_containers {
+            cram.extend(container(
+                0,
+                reads.len() as i32,
+                SERIES.len() as i32 + 3,
+                &blocks,
+            ));
+        }
+        cram
+    }
+
+    #[test]
+    fn test_read_itf8_and_ltf8() {
+        for value in [0, 0x7f, 0x80, 0x3fff, 0x4000, 0x1f_ffff, 0x0fff_ffff, -1] {
+            assert_eq!(read_itf8(&mut itf8(value).as_slice()).unwrap(), value);
+        }
+        assert_eq!(read_ltf8(&mut [0x7f].as_ref()).unwrap(), 0x7f);
+        assert_eq!(read_ltf8(&mut [0x81, 0x02].as_ref()).unwrap(), 0x102);
+        assert_eq!(
+            read_ltf8(&mut [0xff, 0, 0, 0, 1, 0, 0, 0, 0].as_ref()).unwrap(),
+            1 << 32
+        );
+    }
+
+    #[test]
+    fn test_corrupt_bit_codes_are_rejected() {
+        // A run of zero bits longer than any gamma code
+        let mut blocks = SliceBlocks {
+            core: BitReader::new(vec![0; 8]),
+            external: HashMap::new(),
+        };
+        let gamma = Encoding::Gamma { offset: 0 };
+        let mut subexp_blocks = SliceBlocks {
+            core: BitReader::new(vec![0xff; 8]),
+            external: HashMap::new(),
+        };
+        let subexp = Encoding::Subexp { offset: 0, k: 4 };
+
+        assert!(gamma.int(&mut blocks).is_err());
+        assert!(subexp.int(&mut subexp_blocks).is_err());
+        assert!(BitReader::new(vec![0; 8]).bits(40).is_err());
+        assert!(Huffman::new(&[1, 2], &[1, -1]).is_err());
+        assert!(Huffman::new(&[1, 2, 3], &[1, 1, 1]).is_err());
+        assert!(Huffman::new(&[1, 2, 3], &[1, 2, 2]).is_ok());
+    }
+
+    #[test]
+    fn test_load_cram_records_rebuilds_reads_from_reference() {
+        // Arrange
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".cram");
+        let reference = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        std::fs::write(&test_file, cram_file(1)).unwrap();
+        std::fs::write(&reference, ">ref\nACGTACGTAC\nGTACGTACGT\n").unwrap();
+
+        // Act
+        let records = load_cram_records(test_file.as_path(), Some(reference.as_path()));
+
+        // Assert
+        let records = records.unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].id(), "r1");
+        assert_eq!(records[0].seq(), b"GAACGT");
+        assert_eq!(records[0].qual(), b"??5???");
+        assert_eq!(records[1].id(), "r2");
+        assert_eq!(records[1].seq(), b"TACG");
+        assert_eq!(records[1].qual(), b"I?5+");
+        assert_eq!(records[2].id(), "r3");
+        assert_eq!(records[2].seq(), b"GGA");
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+        assert!(std::fs::remove_file(&reference).is_ok());
+        let mut fai = reference.into_os_string();
+        fai.push(".fai");
+        assert!(std::fs::remove_file(fai).is_ok());
+    }
+
+    #[test]
+    fn test_load_cram_slice_decodes_only_its_slice() {
+        // Arrange
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".cram");
+        let reference = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        std::fs::write(&test_file, cram_file(2)).unwrap();
+        std::fs::write(&reference, ">ref\nACGTACGTAC\nGTACGTACGT\n").unwrap();
+
+        // Act
+        let slices = cram_slices(test_file.as_path(), Some(reference.as_path())).unwrap();
+        let second = load_cram_slice(test_file.as_path(), Some(reference.as_path()), &slices[1]);
+        let mut records =
+            open_cram_records(test_file.as_path(), Some(reference.as_path())).unwrap();
+        let first = records.next().unwrap().unwrap();
+        let offset_after_first = records.offset();
+
+        // Assert
+        assert_eq!(slices.len(), 2);
+        assert_eq!(slices[0].first_record, 0);
+        assert_eq!(slices[1].first_record, 3);
+        assert!(slices[1].container > slices[0].container);
+        let second = second.unwrap();
+        assert_eq!(second.len(), 3);
+        assert_eq!(second[0], first);
+        assert_eq!(offset_after_first, slices[1].container);
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+        assert!(std::fs::remove_file(&reference).is_ok());
+        let mut fai = reference.into_os_string();
+        fai.push(".fai");
+        assert!(std::fs::remove_file(fai).is_ok());
+    }
+
+    #[test]
+    fn test_cram_records_decode_one_container_at_a_time() {
+        // Arrange
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".cram");
+        let reference = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        let cram = cram_file(2);
+        std::fs::write(&test_file, &cram).unwrap();
+        std::fs::write(&reference, ">ref\nACGTACGTAC\nGTACGTACGT\n").unwrap();
+
+        // Act
+        let mut records =
+            open_cram_records(test_file.as_path(), Some(reference.as_path())).unwrap();
+        let first = records.next().unwrap().unwrap();
+        let offset_after_first = records.offset();
+        let n_rest = records.count();
+
+        // Assert
+        assert_eq!(first.id(), "r1");
+        assert!(offset_after_first < cram.len() as u64);
+        assert_eq!(n_rest, 5);
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+        assert!(std::fs::remove_file(&reference).is_ok());
+        let mut fai = reference.into_os_string();
+        fai.push(".fai");
+        assert!(std::fs::remove_file(fai).is_ok());
+    }
+
+    #[test]
+    fn test_load_cram_records_needs_reference_for_aligned_reads() {
+        // Arrange
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".cram");
+        std::fs::write(&test_file, cram_file(1)).unwrap();
+
+        // Act
+        let records = load_cram_records(test_file.as_path(), None);
+
+        // Assert
+        let err = records.unwrap_err();
+        assert_eq!(err.kind(), InvalidInput);
+        assert!(err.to_string().contains("ref"));
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+    }
+}
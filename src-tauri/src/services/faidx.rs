@@ -0,0 +1,876 @@
+use crate::models::Compression;
+use crate::services::io::{detect_compression, open_seq_file};
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::ErrorKind::{InvalidData, InvalidInput, NotFound};
+use std::io::{BufRead, BufReader, BufWriter, Error as IOError, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// One line of a samtools `.fai` index. FASTQ indexes (fqidx) also record where
+/// the qualities start. Offsets are into the uncompressed data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaidxEntry {
+    pub name: String,
+    pub length: u64,
+    pub offset: u64,
+    pub line_bases: u64,
+    pub line_width: u64,
+    pub qual_offset: Option<u64>,
+}
+
+/// The records of an indexed file, and for BGZF files the compressed and
+/// uncompressed offset of each block.
+#[derive(Debug, PartialEq)]
+pub struct SeqIndex {
+    pub entries: Vec<FaidxEntry>,
+    pub blocks: Option<Vec<(u64, u64)>>,
+    names: HashMap<String, usize>,
+}
+
+impl SeqIndex {
+    /// Index the records by name, which must be unique to look them up by.
+    pub fn new(entries: Vec<FaidxEntry>, blocks: Option<Vec<(u64, u64)>>) -> Result<Self, IOError> {
+        let mut names = HashMap::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            if names.insert(entry.name.clone(), i).is_some() {
+                return Err(IOError::new(
+                    InvalidData,
+                    format!(
+                        "More than one record is named '{}', so they cannot be indexed.",
+                        entry.name
+                    ),
+                ));
+            }
+        }
+        Ok(SeqIndex {
+            entries,
+            blocks,
+            names,
+        })
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&FaidxEntry> {
+        self.names.get(name).map(|i| &self.entries[*i])
+    }
+}
+
+/// A region of one record, as zero-based, end-exclusive base positions.
+#[derive(Debug, PartialEq)]
+pub struct Region<'a> {
+    pub entry: &'a FaidxEntry,
+    pub start: u64,
+    pub end: u64,
+}
+
+pub fn fai_path(path: &Path) -> PathBuf {
+    let mut fai = path.as_os_str().to_owned();
+    fai.push(".fai");
+    PathBuf::from(fai)
+}
+
+pub fn gzi_path(path: &Path) -> PathBuf {
+    let mut gzi = path.as_os_str().to_owned();
+    gzi.push(".gzi");
+    PathBuf::from(gzi)
+}
+
+/// Load the indexes saved beside a file, building and saving them first if they
+/// are missing or older than the file. Only uncompressed and BGZF files can be
+/// indexed, as other compression cannot be read from an arbitrary offset.
+pub fn load_or_build_index(path: &Path) -> Result<SeqIndex, IOError> {
+    let is_bgzf = match detect_compression(path)? {
+        Compression::None => false,
+        Compression::Gzip if is_bgzf(path)? => true,
+        _ => {
+            return Err(IOError::new(
+                InvalidInput,
+                format!(
+                    "{} must be uncompressed or compressed with bgzip to be indexed.",
+                    path.display()
+                ),
+            ))
+        }
+    };
+
+    let fai = fai_path(path);
+    let entries = if is_stale(&fai, path)? {
+        let entries = build_fai(path)?;
+        save_fai(&entries, &fai)?;
+        entries
+    } else {
+        load_fai(&fai)?
+    };
+    let blocks = if is_bgzf {
+        let gzi = gzi_path(path);
+        let blocks = if is_stale(&gzi, path)? {
+            let blocks = build_gzi(path)?;
+            save_gzi(&blocks, &gzi)?;
+            blocks
+        } else {
+            load_gzi(&gzi)?
+        };
+        Some(blocks)
+    } else {
+        None
+    };
+
+    SeqIndex::new(entries, blocks)
+}
+
+/// Load the indexes saved beside a file, if it has them and they are newer than
+/// the file. Nothing is built or written, so files without indexes give None.
+pub fn load_index(path: &Path) -> Result<Option<SeqIndex>, IOError> {
+    let fai = fai_path(path);
+    if is_stale(&fai, path)? {
+        return Ok(None);
+    }
+    let blocks = match detect_compression(path)? {
+        Compression::None => None,
+        Compression::Gzip if is_bgzf(path)? => {
+            let gzi = gzi_path(path);
+            if is_stale(&gzi, path)? {
+                return Ok(None);
+            }
+            Some(load_gzi(&gzi)?)
+        }
+        _ => return Ok(None),
+    };
+
+    SeqIndex::new(load_fai(&fai)?, blocks).map(Some)
+}
+
+fn is_stale(index: &Path, source: &Path) -> Result<bool, IOError> {
+    if !index.is_file() {
+        return Ok(true);
+    }
+    Ok(index.metadata()?.modified()? < source.metadata()?.modified()?)
+}
+
+/// Whether a gzip file is made of BGZF blocks, which carry their compressed
+/// size in a `BC` extra subfield.
+pub fn is_bgzf(path: &Path) -> Result<bool, IOError> {
+    let mut header = Vec::with_capacity(16);
+    File::open(path)?.take(16).read_to_end(&mut header)?;
+    Ok(header.len() == 16
+        && header[..4] == [0x1f, 0x8b, 0x08, 0x04]
+        && header[12..14] == [b'B', b'C'])
+}
+
+/// Scan a FASTA or FASTQ file for the name, length and layout of each record.
+pub fn build_fai(path: &Path) -> Result<Vec<FaidxEntry>, IOError> {
+    let mut reader = BufReader::new(open_seq_file(path)?);
+    let mut lines = LineReader {
+        reader: &mut reader,
+        line: Vec::new(),
+        pos: 0,
+    };
+
+    let mut entries = Vec::new();
+    let mut has_line = lines.next()?;
+    while has_line {
+        match lines.line.first() {
+            Some(b'>') => {
+                // FASTA records end on the next header, which has already been read
+                entries.push(index_fasta_record(&mut lines)?);
+                has_line = !lines.line.is_empty();
+            }
+            Some(b'@') => {
+                entries.push(index_fastq_record(&mut lines)?);
+                has_line = lines.next()?;
+            }
+            _ if lines.bases().is_empty() => has_line = lines.next()?,
+            _ => {
+                return Err(IOError::new(
+                    InvalidData,
+                    format!("{} is not a FASTA or FASTQ file.", path.display()),
+                ))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Reads lines while keeping track of the offset of each one.
+struct LineReader<'a, R: BufRead> {
+    reader: &'a mut R,
+    line: Vec<u8>,
+    pos: u64,
+}
+
+impl<'a, R: BufRead> LineReader<'a, R> {
+    /// Move to the next line, returning false at the end of the file.
+    fn next(&mut self) -> Result<bool, IOError> {
+        self.pos += self.line.len() as u64;
+        self.line.clear();
+        Ok(self.reader.read_until(b'\n', &mut self.line)? > 0)
+    }
+
+    /// The line without its line ending.
+    fn bases(&self) -> &[u8] {
+        let mut bases = self.line.as_slice();
+        while let Some((b'\n', rest)) | Some((b'\r', rest)) = bases.split_last() {
+            bases = rest;
+        }
+        bases
+    }
+
+    fn name(&self) -> String {
+        let header = String::from_utf8_lossy(&self.bases()[1..]).into_owned();
+        header.split_whitespace().next().unwrap_or("").to_owned()
+    }
+
+    /// Where the line after this one starts.
+    fn next_pos(&self) -> u64 {
+        self.pos + self.line.len() as u64
+    }
+}
+
+/// How the sequence or qualities of a record are wrapped over lines. Every line
+/// but the last must be the same length, or offsets could not be computed.
+struct LineLayout {
+    offset: u64,
+    length: u64,
+    line_bases: u64,
+    line_width: u64,
+    seen_short_line: bool,
+}
+
+impl LineLayout {
+    fn new(offset: u64) -> Self {
+        LineLayout {
+            offset,
+            length: 0,
+            line_bases: 0,
+            line_width: 0,
+            seen_short_line: false,
+        }
+    }
+
+    fn add_line(&mut self, n_bases: u64, width: u64, name: &str) -> Result<(), IOError> {
+        if n_bases == 0 {
+            self.seen_short_line = true;
+            return Ok(());
+        }
+        if self.seen_short_line || (self.line_bases > 0 && n_bases > self.line_bases) {
+            return Err(IOError::new(
+                InvalidData,
+                format!(
+                    "Record {} has lines of different lengths, so it cannot be indexed.",
+                    name
+                ),
+            ));
+        }
+        if self.line_bases == 0 {
+            self.line_bases = n_bases;
+            self.line_width = width;
+        } else if n_bases < self.line_bases {
+            self.seen_short_line = true;
+        }
+        self.length += n_bases;
+        Ok(())
+    }
+}
+
+/// Index the FASTA record whose header is the current line, leaving the reader
+/// on the next header or at the end of the file.
+fn index_fasta_record<R: BufRead>(lines: &mut LineReader<R>) -> Result<FaidxEntry, IOError> {
+    let name = lines.name();
+    let mut seq = LineLayout::new(lines.next_pos());
+    while lines.next()? && lines.line.first() != Some(&b'>') {
+        seq.add_line(lines.bases().len() as u64, lines.line.len() as u64, &name)?;
+    }
+
+    Ok(FaidxEntry {
+        name,
+        length: seq.length,
+        offset: seq.offset,
+        line_bases: seq.line_bases,
+        line_width: seq.line_width,
+        qual_offset: None,
+    })
+}
+
+/// Index the FASTQ record whose header is the current line, leaving the reader
+/// on its last quality line.
+fn index_fastq_record<R: BufRead>(lines: &mut LineReader<R>) -> Result<FaidxEntry, IOError> {
+    let name = lines.name();
+    let truncated = || IOError::new(InvalidData, format!("Record {} is truncated.", name));
+
+    let mut seq = LineLayout::new(lines.next_pos());
+    loop {
+        if !lines.next()? {
+            return Err(truncated());
+        }
+        if lines.line.first() == Some(&b'+') {
+            break;
+        }
+        seq.add_line(lines.bases().len() as u64, lines.line.len() as u64, &name)?;
+    }
+    // Quality lines can start with '@' or '+', so they are read by length
+    let mut qual = LineLayout::new(lines.next_pos());
+    while qual.length < seq.length {
+        if !lines.next()? {
+            return Err(truncated());
+        }
+        qual.add_line(lines.bases().len() as u64, lines.line.len() as u64, &name)?;
+    }
+    if qual.length != seq.length {
+        return Err(truncated());
+    }
+
+    Ok(FaidxEntry {
+        name,
+        length: seq.length,
+        offset: seq.offset,
+        line_bases: seq.line_bases,
+        line_width: seq.line_width,
+        qual_offset: Some(qual.offset),
+    })
+}
+
+/// The compressed and uncompressed offset of every BGZF block after the first,
+/// read from the block headers without decompressing anything.
+pub fn build_gzi(path: &Path) -> Result<Vec<(u64, u64)>, IOError> {
+    let mut blocks = Vec::new();
+    walk_bgzf_blocks(path, |compressed, uncompressed| {
+        if compressed > 0 {
+            blocks.push((compressed, uncompressed));
+        }
+        true
+    })?;
+    Ok(blocks)
+}
+
+/// The compressed and uncompressed offset of the BGZF block holding an offset
+/// into the uncompressed data, walking the block headers only as far as it.
+pub fn bgzf_block_at(path: &Path, offset: u64) -> Result<(u64, u64), IOError> {
+    let mut found = (0, 0);
+    walk_bgzf_blocks(path, |compressed, uncompressed| {
+        if uncompressed > offset {
+            return false;
+        }
+        found = (compressed, uncompressed);
+        true
+    })?;
+    Ok(found)
+}
+
+/// Visit the compressed and uncompressed offset of each BGZF block in turn,
+/// until the visitor returns false.
+fn walk_bgzf_blocks<F>(path: &Path, mut visit: F) -> Result<(), IOError>
+where
+    F: FnMut(u64, u64) -> bool,
+{
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let (mut compressed, mut uncompressed) = (0, 0);
+    while compressed < file_len {
+        if !visit(compressed, uncompressed) {
+            break;
+        }
+        file.seek(SeekFrom::Start(compressed))?;
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+        let mut extra = vec![0; usize::from(u16::from_le_bytes([header[10], header[11]]))];
+        file.read_exact(&mut extra)?;
+        let block_size = bgzf_block_size(&extra).ok_or_else(|| {
+            IOError::new(
+                InvalidData,
+                format!("{} is not BGZF compressed.", path.display()),
+            )
+        })?;
+
+        // The uncompressed size closes each block
+        file.seek(SeekFrom::Start(compressed + block_size - 4))?;
+        let mut isize = [0; 4];
+        file.read_exact(&mut isize)?;
+        compressed += block_size;
+        uncompressed += u64::from(u32::from_le_bytes(isize));
+    }
+    Ok(())
+}
+
+fn bgzf_block_size(extra: &[u8]) -> Option<u64> {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let len = usize::from(u16::from_le_bytes([rest[2], rest[3]]));
+        if rest[..2] == [b'B', b'C'] && len == 2 && rest.len() >= 6 {
+            return Some(u64::from(u16::from_le_bytes([rest[4], rest[5]])) + 1);
+        }
+        rest = rest.get(4 + len..)?;
+    }
+    None
+}
+
+pub fn save_fai(entries: &[FaidxEntry], dest: &Path) -> Result<PathBuf, IOError> {
+    let mut writer = BufWriter::new(File::create(dest)?);
+    for entry in entries {
+        write!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            entry.name, entry.length, entry.offset, entry.line_bases, entry.line_width
+        )?;
+        if let Some(qual_offset) = entry.qual_offset {
+            write!(writer, "\t{}", qual_offset)?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+
+    Ok(dest.to_path_buf())
+}
+
+pub fn load_fai(path: &Path) -> Result<Vec<FaidxEntry>, IOError> {
+    let invalid = || {
+        IOError::new(
+            InvalidData,
+            format!("{} is not a .fai index.", path.display()),
+        )
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 5 && fields.len() != 6 {
+            return Err(invalid());
+        }
+        let number = |i: usize| fields[i].parse::<u64>().map_err(|_| invalid());
+        entries.push(FaidxEntry {
+            name: fields[0].to_owned(),
+            length: number(1)?,
+            offset: number(2)?,
+            line_bases: number(3)?,
+            line_width: number(4)?,
+            qual_offset: if fields.len() == 6 {
+                Some(number(5)?)
+            } else {
+                None
+            },
+        });
+    }
+    Ok(entries)
+}
+
+/// Write a `.gzi` index in the htslib layout: the number of blocks, then each
+/// block's compressed and uncompressed offset, all as little-endian u64s.
+pub fn save_gzi(blocks: &[(u64, u64)], dest: &Path) -> Result<PathBuf, IOError> {
+    let mut writer = BufWriter::new(File::create(dest)?);
+    writer.write_all(&(blocks.len() as u64).to_le_bytes())?;
+    for (compressed, uncompressed) in blocks {
+        writer.write_all(&compressed.to_le_bytes())?;
+        writer.write_all(&uncompressed.to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    Ok(dest.to_path_buf())
+}
+
+pub fn load_gzi(path: &Path) -> Result<Vec<(u64, u64)>, IOError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut blocks = Vec::new();
+    for _ in 0..read_u64(&mut reader)? {
+        blocks.push((read_u64(&mut reader)?, read_u64(&mut reader)?));
+    }
+    Ok(blocks)
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, IOError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Parse a samtools-style region such as `chr1:100-200`, `chr1:100` or `chr1`,
+/// with one-based inclusive positions. Names containing ':' are matched whole
+/// first.
+pub fn parse_region<'a>(index: &'a SeqIndex, region: &str) -> Result<Region<'a>, String> {
+    if let Some(entry) = index.entry(region) {
+        return Ok(Region {
+            entry,
+            start: 0,
+            end: entry.length,
+        });
+    }
+
+    let invalid = || format!("'{}' is not a valid region.", region);
+    let (name, span) = region
+        .rsplit_once(':')
+        .ok_or_else(|| format!("There is no record named '{}' in the index.", region))?;
+    let entry = index
+        .entry(name)
+        .ok_or_else(|| format!("There is no record named '{}' in the index.", name))?;
+    let span = span.replace(',', "");
+    let (start, end) = match span.split_once('-') {
+        Some((start, end)) => (start, Some(end)),
+        None => (span.as_str(), None),
+    };
+    let start: u64 = start.parse().map_err(|_| invalid())?;
+    let end: u64 = match end {
+        Some(end) => end.parse().map_err(|_| invalid())?,
+        None => entry.length,
+    };
+    if start == 0 || end < start {
+        return Err(invalid());
+    }
+    if start > entry.length {
+        return Err(format!(
+            "'{}' starts past the end of {}, which is {} bases long.",
+            region, name, entry.length
+        ));
+    }
+
+    Ok(Region {
+        entry,
+        start: start - 1,
+        end: end.min(entry.length),
+    })
+}
+
+/// Reads byte ranges of the uncompressed contents of an indexed file.
+pub struct IndexedReader<'a> {
+    file: File,
+    index: &'a SeqIndex,
+}
+
+impl<'a> IndexedReader<'a> {
+    pub fn open(path: &Path, index: &'a SeqIndex) -> Result<Self, IOError> {
+        Ok(IndexedReader {
+            file: File::open(path)?,
+            index,
+        })
+    }
+
+    /// The bases of a region, and their qualities for FASTQ records.
+    pub fn fetch(&mut self, region: &Region) -> Result<(Vec<u8>, Option<Vec<u8>>), IOError> {
+        let seq = self.read_bases(region.entry.offset, region)?;
+        let qual = match region.entry.qual_offset {
+            Some(qual_offset) => Some(self.read_bases(qual_offset, region)?),
+            None => None,
+        };
+        Ok((seq, qual))
+    }
+
+    /// The description of a record, read from the end of the header line just
+    /// before its bases, as the index only keeps the name.
+    pub fn description(&mut self, entry: &FaidxEntry) -> Result<Option<String>, IOError> {
+        let mut window = 256;
+        let header = loop {
+            let start = entry.offset.saturating_sub(window);
+            let bytes = self.read_at(start, entry.offset - start)?;
+            let mut line = bytes.as_slice();
+            while let Some((b'\n', rest)) | Some((b'\r', rest)) = line.split_last() {
+                line = rest;
+            }
+            match line.iter().rposition(|byte| *byte == b'\n') {
+                Some(newline) => break line[newline + 1..].to_vec(),
+                None if start == 0 => break line.to_vec(),
+                None => window *= 4,
+            }
+        };
+        match header.first() {
+            Some(b'>') | Some(b'@') => {}
+            _ => {
+                return Err(IOError::new(
+                    InvalidData,
+                    format!("The index does not match the file at {}.", entry.name),
+                ))
+            }
+        }
+        let header = String::from_utf8_lossy(&header[1..]);
+        Ok(header
+            .trim_end()
+            .split_once(char::is_whitespace)
+            .map(|(_, desc)| desc.to_owned()))
+    }
+
+    fn read_bases(&mut self, offset: u64, region: &Region) -> Result<Vec<u8>, IOError> {
+        if region.start >= region.end {
+            return Ok(Vec::new());
+        }
+        let entry = region.entry;
+        let line_bases = entry.line_bases.max(1);
+        let byte_pos = |pos: u64| offset + pos / line_bases * entry.line_width + pos % line_bases;
+        let first = byte_pos(region.start);
+        let last = byte_pos(region.end - 1);
+
+        let bytes = self.read_at(first, last - first + 1)?;
+        let bases: Vec<u8> = bytes
+            .into_iter()
+            .filter(|byte| *byte != b'\n' && *byte != b'\r')
+            .collect();
+        if bases.len() as u64 != region.end - region.start {
+            return Err(IOError::new(
+                InvalidData,
+                format!("The index does not match the file at {}.", entry.name),
+            ));
+        }
+        Ok(bases)
+    }
+
+    fn read_at(&mut self, offset: u64, len: u64) -> Result<Vec<u8>, IOError> {
+        let mut bytes = Vec::new();
+        match &self.index.blocks {
+            None => {
+                self.file.seek(SeekFrom::Start(offset))?;
+                (&mut self.file).take(len).read_to_end(&mut bytes)?;
+            }
+            Some(blocks) => {
+                // The block holding the offset is the last one starting at or before it
+                let after = blocks.partition_point(|(_, start)| *start <= offset);
+                let (compressed, uncompressed) = match after {
+                    0 => (0, 0),
+                    _ => blocks[after - 1],
+                };
+                self.file.seek(SeekFrom::Start(compressed))?;
+                let mut decoder = MultiGzDecoder::new(&mut self.file);
+                std::io::copy(
+                    &mut (&mut decoder).take(offset - uncompressed),
+                    &mut std::io::sink(),
+                )?;
+                decoder.take(len).read_to_end(&mut bytes)?;
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// Look up a record by name, or by its position in the file, returning both.
+pub fn find_entry<'a>(
+    index: &'a SeqIndex,
+    name: Option<&str>,
+    position: Option<usize>,
+) -> Result<(usize, &'a FaidxEntry), IOError> {
+    let position = match (name, position) {
+        (Some(name), _) => index.names.get(name).copied(),
+        (None, Some(position)) if position < index.entries.len() => Some(position),
+        (None, Some(_)) => None,
+        (None, None) => {
+            return Err(IOError::new(
+                InvalidInput,
+                "Give either the name or the position of a record.",
+            ))
+        }
+    };
+    position
+        .map(|position| (position, &index.entries[position]))
+        .ok_or_else(|| IOError::new(NotFound, "There is no such record in the index."))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::DeflateEncoder;
+    use flate2::Crc;
+    use uuid::Uuid;
+
+    use super::{
+        bgzf_block_at, build_fai, fai_path, gzi_path, load_fai, load_index, load_or_build_index,
+        parse_region, IndexedReader,
+    };
+    use crate::services::io::open_seq_file_at;
+
+    const TEST_FASTA: &[u8] = b">chr1 first\nACGTA\nCCGTA\nGG\n>chr2\nTTTTT\nAAA\n";
+    const TEST_FASTQ: &[u8] = b"@r1 desc\nACGTAC\n+\n>!!!!!\n@r2\nGGCC\n+r2\nIIII\n";
+
+    /// Compress data as a single BGZF block, as bgzip would.
+    fn bgzf_block(data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        let deflated = encoder.finish().unwrap();
+        let mut crc = Crc::new();
+        crc.update(data);
+
+        let block_size = (18 + deflated.len() + 8 - 1) as u16;
+        let mut block = vec![
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+        ];
+        block.extend_from_slice(&block_size.to_le_bytes());
+        block.extend_from_slice(&deflated);
+        block.extend_from_slice(&crc.sum().to_le_bytes());
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn test_build_fai_fasta_and_fastq() {
+        // Arrange
+        let fasta = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        let fastq = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq");
+        std::fs::write(&fasta, TEST_FASTA).unwrap();
+        std::fs::write(&fastq, TEST_FASTQ).unwrap();
+
+        // Act
+        let fasta_entries = build_fai(&fasta).unwrap();
+        let fastq_entries = build_fai(&fastq).unwrap();
+
+        // Assert
+        assert_eq!(fasta_entries.len(), 2);
+        assert_eq!(fasta_entries[0].name, "chr1");
+        assert_eq!(fasta_entries[0].length, 12);
+        assert_eq!(fasta_entries[0].offset, 12);
+        assert_eq!(
+            (fasta_entries[0].line_bases, fasta_entries[0].line_width),
+            (5, 6)
+        );
+        assert_eq!(fasta_entries[1].offset, 33);
+        assert_eq!(fasta_entries[1].length, 8);
+        assert_eq!(fastq_entries.len(), 2);
+        assert_eq!(fastq_entries[0].offset, 9);
+        assert_eq!(fastq_entries[0].qual_offset, Some(18));
+        assert_eq!(fastq_entries[1].name, "r2");
+        assert_eq!(fastq_entries[1].offset, 29);
+        assert_eq!(fastq_entries[1].qual_offset, Some(38));
+
+        // Clean up
+        assert!(std::fs::remove_file(fasta).is_ok());
+        assert!(std::fs::remove_file(fastq).is_ok());
+    }
+
+    #[test]
+    fn test_load_or_build_index_errors_on_duplicate_names() {
+        // Arrange
+        let fasta = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        std::fs::write(&fasta, b">chr1\nACGT\n>chr1\nGG\n").unwrap();
+
+        // Act
+        let index = load_or_build_index(&fasta);
+
+        // Assert
+        assert!(index.is_err());
+
+        // Clean up
+        assert!(std::fs::remove_file(fai_path(&fasta)).is_ok());
+        assert!(std::fs::remove_file(fasta).is_ok());
+    }
+
+    #[test]
+    fn test_load_index_only_reads_saved_indexes() {
+        // Arrange
+        let fasta = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        std::fs::write(&fasta, TEST_FASTA).unwrap();
+
+        // Act
+        let before = load_index(&fasta).unwrap();
+        let written = fai_path(&fasta).exists();
+        load_or_build_index(&fasta).unwrap();
+        let index = load_index(&fasta).unwrap().unwrap();
+        let mut reader = IndexedReader::open(&fasta, &index).unwrap();
+        let chr1 = reader.description(&index.entries[0]).unwrap();
+        let chr2 = reader.description(&index.entries[1]).unwrap();
+
+        // Assert
+        assert!(before.is_none());
+        assert!(!written);
+        assert_eq!(chr1.as_deref(), Some("first"));
+        assert!(chr2.is_none());
+
+        // Clean up
+        assert!(std::fs::remove_file(fai_path(&fasta)).is_ok());
+        assert!(std::fs::remove_file(fasta).is_ok());
+    }
+
+    #[test]
+    fn test_build_fai_errors_on_uneven_lines() {
+        // Arrange
+        let fasta = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        std::fs::write(&fasta, b">chr1\nACG\nACGTA\n").unwrap();
+
+        // Act
+        let entries = build_fai(&fasta);
+
+        // Assert
+        assert!(entries.is_err());
+
+        // Clean up
+        assert!(std::fs::remove_file(fasta).is_ok());
+    }
+
+    #[test]
+    fn test_fetch_region_from_plain_fasta() {
+        // Arrange
+        let fasta = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        std::fs::write(&fasta, TEST_FASTA).unwrap();
+
+        // Act
+        let index = load_or_build_index(&fasta).unwrap();
+        let saved = load_fai(&fai_path(&fasta)).unwrap();
+        let mut reader = IndexedReader::open(&fasta, &index).unwrap();
+        let region = parse_region(&index, "chr1:4-11").unwrap();
+        let (seq, qual) = reader.fetch(&region).unwrap();
+        let whole = parse_region(&index, "chr2").unwrap();
+        let (chr2, _) = reader.fetch(&whole).unwrap();
+
+        // Assert
+        assert_eq!(saved, index.entries);
+        assert_eq!(seq, b"TACCGTAG");
+        assert!(qual.is_none());
+        assert_eq!(chr2, b"TTTTTAAA");
+        assert!(parse_region(&index, "chr3:1-2").is_err());
+        assert!(parse_region(&index, "chr1:5-2").is_err());
+        assert!(parse_region(&index, "chr1:13-20").is_err());
+        assert!(parse_region(&index, "chr1:12-20").is_ok());
+
+        // Clean up
+        assert!(std::fs::remove_file(fai_path(&fasta)).is_ok());
+        assert!(std::fs::remove_file(fasta).is_ok());
+    }
+
+    #[test]
+    fn test_fetch_region_from_bgzf_fastq() {
+        // Arrange: split the reads over two blocks, mid-record
+        let fastq = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq.gz");
+        let (first, second) = TEST_FASTQ.split_at(30);
+        let mut bgzf = bgzf_block(first);
+        bgzf.extend(bgzf_block(second));
+        bgzf.extend(bgzf_block(b""));
+        std::fs::write(&fastq, bgzf).unwrap();
+
+        // Act
+        let index = load_or_build_index(&fastq).unwrap();
+        let mut reader = IndexedReader::open(&fastq, &index).unwrap();
+        let region = parse_region(&index, "r2:2-3").unwrap();
+        let (seq, qual) = reader.fetch(&region).unwrap();
+
+        // Assert
+        assert_eq!(index.blocks.as_ref().unwrap()[0].1, 30);
+        assert!(gzi_path(&fastq).is_file());
+        assert_eq!(seq, b"GC");
+        assert_eq!(qual.unwrap(), b"II");
+
+        // Clean up
+        assert!(std::fs::remove_file(fai_path(&fastq)).is_ok());
+        assert!(std::fs::remove_file(gzi_path(&fastq)).is_ok());
+        assert!(std::fs::remove_file(fastq).is_ok());
+    }
+
+    #[test]
+    fn test_open_seq_file_at_seeks_into_bgzf_block() {
+        // Arrange
+        let fastq = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq.gz");
+        let (first, second) = TEST_FASTQ.split_at(30);
+        let mut bgzf = bgzf_block(first);
+        let second_block = bgzf.len() as u64;
+        bgzf.extend(bgzf_block(second));
+        bgzf.extend(bgzf_block(b""));
+        std::fs::write(&fastq, bgzf).unwrap();
+
+        // Act
+        let block = bgzf_block_at(&fastq, 35);
+        let mut rest = Vec::new();
+        let read_action = open_seq_file_at(&fastq, 35).and_then(|mut source| {
+            source.read_to_end(&mut rest)?;
+            Ok(())
+        });
+
+        // Assert
+        assert_eq!(bgzf_block_at(&fastq, 29).unwrap(), (0, 0));
+        assert_eq!(block.unwrap(), (second_block, 30));
+        assert!(read_action.is_ok());
+        assert_eq!(rest, &TEST_FASTQ[35..]);
+
+        // Clean up
+        assert!(std::fs::remove_file(fastq).is_ok());
+    }
+}
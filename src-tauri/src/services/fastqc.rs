@@ -0,0 +1,56 @@
+use crate::models::FastqcModule;
+use std::fs::File;
+use std::io::{BufWriter, Error as IOError, Write};
+use std::path::Path;
+
+/// The `fastqc_data.txt` format version written in the file header.
+const FASTQC_FORMAT_VERSION: &str = "0.11.9";
+
+/// Write report modules in the layout of FastQC's `fastqc_data.txt`.
+pub fn save_fastqc_data(modules: &[FastqcModule], dest: &Path) -> Result<(), IOError> {
+    let mut writer = BufWriter::new(File::create(dest)?);
+    write_fastqc_data(modules, &mut writer)?;
+    writer.flush()
+}
+
+fn write_fastqc_data<W: Write>(modules: &[FastqcModule], writer: &mut W) -> Result<(), IOError> {
+    writeln!(writer, "##FastQC\t{}", FASTQC_FORMAT_VERSION)?;
+    for module in modules {
+        writeln!(writer, ">>{}\t{}", module.name, module.status.as_str())?;
+        writeln!(writer, "#{}", module.columns.join("\t"))?;
+        for row in &module.rows {
+            writeln!(writer, "{}", row.join("\t"))?;
+        }
+        writeln!(writer, ">>END_MODULE")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{FastqcModule, QcStatus};
+
+    use super::write_fastqc_data;
+
+    #[test]
+    fn test_write_fastqc_data_uses_module_format() {
+        // Arrange
+        let modules = vec![FastqcModule {
+            name: "Basic Statistics".to_owned(),
+            status: QcStatus::Warn,
+            columns: vec!["Measure".to_owned(), "Value".to_owned()],
+            rows: vec![vec!["Total Sequences".to_owned(), "20".to_owned()]],
+        }];
+        let mut buffer = Vec::new();
+
+        // Act
+        let write_action = write_fastqc_data(&modules, &mut buffer);
+
+        // Assert
+        assert!(write_action.is_ok());
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "##FastQC\t0.11.9\n>>Basic Statistics\twarn\n#Measure\tValue\nTotal Sequences\t20\n>>END_MODULE\n"
+        );
+    }
+}
@@ -0,0 +1,430 @@
+use crate::models::{FileFormat, SeqFormat};
+use crate::services::cram::open_cram_records;
+use crate::services::io::{
+    detect_compression, fasta_record_offsets, fastq_record_offsets, load_fasta_record_range,
+    load_fasta_records, load_fastq_record_range, open_seq_file, read_fastq, PartialRecords,
+    RecordRange,
+};
+use crate::services::sam::{
+    bam_record_offsets, load_bam_record_at, load_sam_record_at, open_bam_records, open_sam_records,
+    sam_record_offsets,
+};
+use bio::io::{fasta, fastq};
+use std::io::ErrorKind::InvalidInput;
+use std::io::{Error as IOError, Read};
+use std::path::Path;
+
+/// How many decompressed bytes to inspect; enough to see a full SAM header line.
+const SNIFF_LEN: u64 = 4096;
+
+/// SAM header lines start with one of these record types.
+const SAM_HEADER_TAGS: [&[u8]; 5] = [b"@HD\t", b"@SQ\t", b"@RG\t", b"@PG\t", b"@CO\t"];
+
+/// Work out the compression and record format of a file from its contents,
+/// ignoring its extension.
+pub fn detect_format(path: &Path) -> Result<FileFormat, IOError> {
+    let compression = detect_compression(path)?;
+    let mut head = Vec::new();
+    open_seq_file(path)?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut head)?;
+
+    Ok(FileFormat {
+        format: format_from_bytes(&head),
+        compression,
+    })
+}
+
+/// Read a FASTQ, SAM, BAM or CRAM file as FASTQ records, whatever its extension.
+/// Aligned CRAM reads are rebuilt from `reference`, the FASTA file they were
+/// aligned against.
+pub fn load_read_records(
+    path: &Path,
+    reference: Option<&Path>,
+) -> Result<Vec<fastq::Record>, IOError> {
+    read_reads(path, reference)?.collect()
+}
+
+/// The reads of a FASTQ, SAM, BAM or CRAM file, decoded as they are iterated
+/// over so that a file never has to be held in memory whole.
+pub type ReadRecords = Box<dyn Iterator<Item = Result<fastq::Record, IOError>> + Send>;
+
+/// Open a FASTQ, SAM, BAM or CRAM file to stream its reads as FASTQ records.
+pub fn read_reads(path: &Path, reference: Option<&Path>) -> Result<ReadRecords, IOError> {
+    match detect_format(path)?.format {
+        // Unreadable FASTQ records are kept as empty ones that fail `check()`
+        SeqFormat::Fastq => Ok(Box::new(
+            read_fastq(path)?
+                .records()
+                .map(|rec| Ok(rec.unwrap_or_default())),
+        )),
+        SeqFormat::Sam => Ok(Box::new(open_sam_records(path, 0)?)),
+        SeqFormat::Bam => Ok(Box::new(open_bam_records(path, 0)?)),
+        SeqFormat::Cram => Ok(Box::new(open_cram_records(path, reference)?)),
+        _ => Err(no_reads(path)),
+    }
+}
+
+fn no_reads(path: &Path) -> IOError {
+    IOError::new(
+        InvalidInput,
+        format!(
+            "{} does not contain reads with base qualities.",
+            path.display()
+        ),
+    )
+}
+
+/// Read the reads within a range of a FASTQ, SAM, BAM or CRAM file, stopping as
+/// soon as the range is filled. SAM and BAM ranges can carry on from the offset
+/// they report; CRAM reads are decoded a slice at a time, so a range of them
+/// can only carry on from its record index.
+pub fn load_read_record_range(
+    path: &Path,
+    range: &RecordRange,
+    reference: Option<&Path>,
+) -> Result<PartialRecords<fastq::Record>, IOError> {
+    let offset = range.start_offset.unwrap_or(0);
+    match detect_format(path)?.format {
+        SeqFormat::Fastq => load_fastq_record_range(path, range),
+        SeqFormat::Sam => take_read_range(open_sam_records(path, offset)?, |r| r.offset(), range),
+        SeqFormat::Bam => take_read_range(open_bam_records(path, offset)?, |r| r.offset(), range),
+        SeqFormat::Cram => {
+            let range = RecordRange {
+                start_offset: None,
+                ..*range
+            };
+            let partial =
+                take_read_range(open_cram_records(path, reference)?, |r| r.offset(), &range)?;
+            Ok(PartialRecords {
+                next_offset: None,
+                ..partial
+            })
+        }
+        _ => Err(no_reads(path)),
+    }
+}
+
+/// Take the reads within a range from a reader that reports how far through the
+/// file it has got, which is where the range carries on from.
+fn take_read_range<I, F>(
+    mut records: I,
+    offset: F,
+    range: &RecordRange,
+) -> Result<PartialRecords<fastq::Record>, IOError>
+where
+    I: Iterator<Item = Result<fastq::Record, IOError>>,
+    F: Fn(&I) -> u64,
+{
+    // A known offset already points at the first record of the range
+    if range.start_offset.is_none() {
+        for _ in 0..range.start {
+            if records.next().transpose()?.is_none() {
+                break;
+            }
+        }
+    }
+
+    let start_bytes = offset(&records);
+    let mut taken = Vec::new();
+    while let Some(rec) = records.next() {
+        taken.push(rec?);
+        let reached_records = range.max_records.map_or(false, |max| taken.len() >= max);
+        let reached_bytes = range
+            .max_bytes
+            .map_or(false, |max| offset(&records) - start_bytes >= max);
+        if reached_records || reached_bytes {
+            break;
+        }
+    }
+
+    // Reading on to see if the file ended moves the reader, so note where it was
+    let next_offset = offset(&records);
+    let next_record = match records.next() {
+        Some(rec) => rec.map(|_| Some(range.start + taken.len()))?,
+        None => None,
+    };
+    Ok(PartialRecords {
+        records: taken,
+        next_offset: next_record.map(|_| next_offset),
+        next_record,
+    })
+}
+
+/// The records of a file whose format was detected from its contents.
+pub enum SeqRecords {
+    Fasta(Vec<fasta::Record>),
+    Fastq(Vec<fastq::Record>),
+}
+
+impl SeqRecords {
+    /// The sequences of the records that pass `check()`.
+    pub fn valid_seqs(&self) -> Vec<&[u8]> {
+        match self {
+            SeqRecords::Fasta(records) => records
+                .iter()
+                .filter(|rec| rec.check().is_ok())
+                .map(|rec| rec.seq())
+                .collect(),
+            SeqRecords::Fastq(records) => records
+                .iter()
+                .filter(|rec| rec.check().is_ok())
+                .map(|rec| rec.seq())
+                .collect(),
+        }
+    }
+}
+
+/// Read a FASTA, FASTQ, SAM, BAM or CRAM file, whatever its extension.
+pub fn load_seq_records(path: &Path, reference: Option<&Path>) -> Result<SeqRecords, IOError> {
+    match detect_format(path)?.format {
+        SeqFormat::Fasta => Ok(SeqRecords::Fasta(load_fasta_records(path)?)),
+        _ => Ok(SeqRecords::Fastq(load_read_records(path, reference)?)),
+    }
+}
+
+/// Read the records within a range of a file, whatever its format, reading
+/// only as far as the range.
+pub fn load_seq_record_range(
+    path: &Path,
+    range: &RecordRange,
+    reference: Option<&Path>,
+) -> Result<SeqRecords, IOError> {
+    match detect_format(path)?.format {
+        SeqFormat::Fasta => Ok(SeqRecords::Fasta(
+            load_fasta_record_range(path, range)?.records,
+        )),
+        _ => Ok(SeqRecords::Fastq(
+            load_read_record_range(path, range, reference)?.records,
+        )),
+    }
+}
+
+/// Read only the record at an index of a file, whatever its format.
+pub fn load_record_at(
+    path: &Path,
+    index: usize,
+    reference: Option<&Path>,
+) -> Result<SeqRecords, IOError> {
+    let range = RecordRange {
+        start: index,
+        max_records: Some(1),
+        ..Default::default()
+    };
+    load_seq_record_range(path, &range, reference)
+}
+
+/// Where each record of a file starts in its decompressed data, so single
+/// records can be read back without parsing everything before them. CRAM
+/// records are decoded a slice at a time, so they have no offsets of their own;
+/// see `cram_slices` for those.
+pub fn load_record_offsets(path: &Path) -> Result<Option<Vec<u64>>, IOError> {
+    match detect_format(path)?.format {
+        SeqFormat::Fasta => Ok(Some(fasta_record_offsets(path)?)),
+        SeqFormat::Fastq => Ok(Some(fastq_record_offsets(path)?)),
+        SeqFormat::Sam => Ok(Some(sam_record_offsets(path)?)),
+        SeqFormat::Bam => Ok(Some(bam_record_offsets(path)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Read the record starting at an offset found by `load_record_offsets`.
+pub fn load_record_at_offset(path: &Path, offset: u64) -> Result<SeqRecords, IOError> {
+    let range = RecordRange {
+        start_offset: Some(offset),
+        max_records: Some(1),
+        ..Default::default()
+    };
+    match detect_format(path)?.format {
+        SeqFormat::Fasta => Ok(SeqRecords::Fasta(
+            load_fasta_record_range(path, &range)?.records,
+        )),
+        SeqFormat::Fastq => Ok(SeqRecords::Fastq(
+            load_fastq_record_range(path, &range)?.records,
+        )),
+        SeqFormat::Sam => Ok(SeqRecords::Fastq(
+            load_sam_record_at(path, offset)?.into_iter().collect(),
+        )),
+        SeqFormat::Bam => Ok(SeqRecords::Fastq(
+            load_bam_record_at(path, offset)?.into_iter().collect(),
+        )),
+        _ => Err(IOError::new(
+            InvalidInput,
+            format!("Records of {} cannot be read by offset.", path.display()),
+        )),
+    }
+}
+
+/// Classify uncompressed sequence data by its leading bytes.
+pub fn format_from_bytes(head: &[u8]) -> SeqFormat {
+    if head.starts_with(b"BAM\x01") {
+        return SeqFormat::Bam;
+    }
+    if head.starts_with(b"CRAM") {
+        return SeqFormat::Cram;
+    }
+
+    let start = head
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(head.len());
+    let text = &head[start..];
+    match text.first() {
+        Some(b'>') => SeqFormat::Fasta,
+        Some(b'@') if SAM_HEADER_TAGS.iter().any(|tag| text.starts_with(tag)) => SeqFormat::Sam,
+        Some(b'@') => SeqFormat::Fastq,
+        Some(_) if is_sam_alignment_line(text) => SeqFormat::Sam,
+        _ => SeqFormat::Unknown,
+    }
+}
+
+fn is_sam_alignment_line(text: &[u8]) -> bool {
+    // A headerless SAM file starts straight into the 11 mandatory columns
+    let line = text.split(|b| *b == b'\n').next().unwrap_or(&[]);
+    line.split(|b| *b == b'\t').count() >= 11
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::models::{Compression, SeqFormat};
+    use flate2::write::GzEncoder;
+    use uuid::Uuid;
+
+    use super::{
+        detect_format, format_from_bytes, load_read_record_range, load_record_at,
+        load_record_at_offset, load_record_offsets, SeqRecords,
+    };
+    use crate::services::io::RecordRange;
+
+    #[test]
+    fn test_format_from_bytes() {
+        assert_eq!(
+            format_from_bytes(b"@id desc\nACGT\n+\n!!!!\n"),
+            SeqFormat::Fastq
+        );
+        assert_eq!(format_from_bytes(b"\n>id desc\nACGT\n"), SeqFormat::Fasta);
+        assert_eq!(format_from_bytes(b"@HD\tVN:1.6\n"), SeqFormat::Sam);
+        assert_eq!(
+            format_from_bytes(b"r1\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t!!!!\n"),
+            SeqFormat::Sam
+        );
+        assert_eq!(format_from_bytes(b"BAM\x01\x00\x00"), SeqFormat::Bam);
+        assert_eq!(format_from_bytes(b"CRAM\x03\x00"), SeqFormat::Cram);
+        assert_eq!(format_from_bytes(b"hello"), SeqFormat::Unknown);
+        assert_eq!(format_from_bytes(b""), SeqFormat::Unknown);
+    }
+
+    #[test]
+    fn test_detect_format_sees_through_compression() {
+        // Arrange
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq");
+        let mut encoder = GzEncoder::new(
+            std::fs::File::create(&test_file).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b">id desc\nACGT\n").unwrap();
+        encoder.finish().unwrap();
+
+        // Act
+        let detected = detect_format(test_file.as_path());
+
+        // Assert
+        assert!(detected.is_ok());
+        let detected = detected.unwrap();
+        assert_eq!(detected.format, SeqFormat::Fasta);
+        assert_eq!(detected.compression, Compression::Gzip);
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+    }
+
+    #[test]
+    fn test_load_record_at_reads_only_that_record() {
+        // Arrange
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        std::fs::write(&test_file, b">r0\nACGT\n>r1\nGGCC\n>r2\nTTAA\n").unwrap();
+
+        // Act
+        let second = load_record_at(test_file.as_path(), 1, None);
+        let past_end = load_record_at(test_file.as_path(), 3, None);
+
+        // Assert
+        match second.unwrap() {
+            SeqRecords::Fasta(records) => {
+                assert_eq!(records.len(), 1);
+                assert_eq!(records[0].id(), "r1");
+            }
+            SeqRecords::Fastq(_) => panic!("expected FASTA records"),
+        }
+        assert!(past_end.unwrap().valid_seqs().is_empty());
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+    }
+
+    #[test]
+    fn test_load_record_at_offset_reads_back_each_record() {
+        // Arrange
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq");
+        std::fs::write(&test_file, b"@r0\nACGT\n+\nIIII\n@r1 two\nGG\n+\n!!\n").unwrap();
+
+        // Act
+        let offsets = load_record_offsets(test_file.as_path()).unwrap();
+        let second = load_record_at_offset(test_file.as_path(), offsets.as_ref().unwrap()[1]);
+
+        // Assert
+        assert_eq!(offsets, Some(vec![0, 16]));
+        match second.unwrap() {
+            SeqRecords::Fastq(records) => {
+                assert_eq!(records.len(), 1);
+                assert_eq!(records[0].id(), "r1");
+                assert_eq!(records[0].seq(), b"GG");
+            }
+            SeqRecords::Fasta(_) => panic!("expected FASTQ records"),
+        }
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+    }
+
+    #[test]
+    fn test_load_read_record_range_stops_and_resumes_sam() {
+        // Arrange
+        let sam = "@HD\tVN:1.6\n\
+                   read1\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n\
+                   read1\t260\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n\
+                   read2\t4\t*\t0\t0\t*\t*\t0\t0\tGG\t*\n\
+                   read3\t4\t*\t0\t0\t*\t*\t0\t0\tTT\t*\n";
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".sam");
+        std::fs::write(&test_file, sam).unwrap();
+        let first_range = RecordRange {
+            max_records: Some(1),
+            ..Default::default()
+        };
+
+        // Act
+        let first = load_read_record_range(test_file.as_path(), &first_range, None).unwrap();
+        let rest_range = RecordRange {
+            start: first.next_record.unwrap(),
+            start_offset: first.next_offset,
+            ..Default::default()
+        };
+        let rest = load_read_record_range(test_file.as_path(), &rest_range, None).unwrap();
+
+        // Assert
+        assert_eq!(first.records.len(), 1);
+        assert_eq!(first.next_record, Some(1));
+        assert_eq!(
+            first.next_offset,
+            Some(sam.find("read1\t260").unwrap() as u64)
+        );
+        assert_eq!(rest.records.len(), 2);
+        assert_eq!(rest.records[0].id(), "read2");
+        assert_eq!(rest.next_record, None);
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+    }
+}
@@ -1,29 +1,444 @@
+use crate::models::{Compression, QcProfile};
+use crate::services::faidx::{bgzf_block_at, is_bgzf};
 use bio::io::{fasta, fastq};
-use flate2::read::GzDecoder;
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fs::File;
 use std::io::ErrorKind::{InvalidData, InvalidInput, NotFound, Other};
-use std::io::{BufReader, BufWriter, Error as IOError, Read, Write};
-use std::path::Path;
-
-pub fn read_fasta(path: &Path) -> fasta::Reader<BufReader<File>> {
-    let path_str = path.as_os_str().to_str().unwrap();
-    if path_str.ends_with(".gz") {
-        let extracted_gz_path = extract_gzip(path);
-        return fasta::Reader::from_file(extracted_gz_path).unwrap();
+use std::io::{BufRead, BufReader, BufWriter, Error as IOError, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use xz2::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+/// A reader over the decompressed contents of a sequence file.
+pub type SeqSource = Box<dyn Read + Send>;
+
+/// Identify the compression of a file from its leading magic bytes, so
+/// mislabelled files are still opened correctly.
+pub fn detect_compression(path: &Path) -> Result<Compression, IOError> {
+    let mut magic = Vec::with_capacity(6);
+    File::open(path)?.take(6).read_to_end(&mut magic)?;
+    Ok(compression_from_magic(&magic))
+}
+
+fn compression_from_magic(magic: &[u8]) -> Compression {
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Compression::Gzip
+    } else if magic.starts_with(b"BZh") {
+        Compression::Bzip2
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Compression::Xz
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Compression::Zstd
     } else {
-        return fasta::Reader::from_file(path).unwrap();
+        Compression::None
     }
 }
 
-pub fn read_fastq(path: &Path) -> fastq::Reader<BufReader<File>> {
-    let path_str = path.as_os_str().to_str().unwrap();
-    if path_str.ends_with(".gz") {
-        let extracted_gz_path = extract_gzip(path);
-        return fastq::Reader::from_file(extracted_gz_path).unwrap();
+/// Open a sequence file, transparently decompressing gzip (including BGZF),
+/// bzip2, xz and zstd streams.
+pub fn open_seq_file(path: &Path) -> Result<SeqSource, IOError> {
+    let compression = detect_compression(path)?;
+    let file = File::open(path)?;
+    let source: SeqSource = match compression {
+        Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(file)),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(file)),
+        Compression::Zstd => Box::new(ZstdDecoder::new(file)?),
+        Compression::None => Box::new(file),
+    };
+    Ok(source)
+}
+
+pub fn read_fasta(path: &Path) -> Result<fasta::Reader<BufReader<SeqSource>>, IOError> {
+    Ok(fasta::Reader::new(open_seq_file(path)?))
+}
+
+pub fn read_fastq(path: &Path) -> Result<fastq::Reader<BufReader<SeqSource>>, IOError> {
+    Ok(fastq::Reader::new(open_seq_file(path)?))
+}
+
+/// Read every record in a FASTA file.
+pub fn load_fasta_records(path: &Path) -> Result<Vec<fasta::Record>, IOError> {
+    let reader = read_fasta(path)?;
+    Ok(reader
+        .records()
+        .map(|rec| rec.unwrap_or_default())
+        .collect())
+}
+
+/// Read every record in a FASTQ file.
+pub fn load_fastq_records(path: &Path) -> Result<Vec<fastq::Record>, IOError> {
+    let reader = read_fastq(path)?;
+    Ok(reader
+        .records()
+        .map(|rec| rec.unwrap_or_default())
+        .collect())
+}
+
+/// Where to start reading a file, and when to stop early for a preview.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RecordRange {
+    pub start: usize,
+    // Where record `start` begins in the decompressed data, when a previous
+    // range reported it, so reading can seek there instead of parsing up to it
+    pub start_offset: Option<u64>,
+    pub max_records: Option<usize>,
+    // Decompressed bytes, checked after each record
+    pub max_bytes: Option<u64>,
+}
+
+/// The records read within a range, and where to carry on if the file did not
+/// end there.
+pub struct PartialRecords<T> {
+    pub records: Vec<T>,
+    pub next_record: Option<usize>,
+    // Where `next_record` begins in the decompressed data
+    pub next_offset: Option<u64>,
+}
+
+/// How far a parser has got through the decompressed data of a file.
+#[derive(Debug, Default)]
+struct ReadPosition {
+    consumed: AtomicU64,
+    // Where the line holding the last consumed byte starts
+    line_start: AtomicU64,
+}
+
+/// Buffers a sequence file and tracks how much of it the parser owning it has
+/// consumed, so a range can tell exactly where its next record starts.
+struct TrackedReader {
+    inner: BufReader<SeqSource>,
+    position: Arc<ReadPosition>,
+    at_line_end: bool,
+}
+
+impl Read for TrackedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n_read = available.len().min(buf.len());
+        buf[..n_read].copy_from_slice(&available[..n_read]);
+        self.consume(n_read);
+        Ok(n_read)
+    }
+}
+
+impl BufRead for TrackedReader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let start = self.position.consumed.load(Ordering::Relaxed);
+        let consumed = &self.inner.buffer()[..amt.min(self.inner.buffer().len())];
+        if let Some((last, rest)) = consumed.split_last() {
+            match rest.iter().rposition(|byte| *byte == b'\n') {
+                Some(newline) => self
+                    .position
+                    .line_start
+                    .store(start + newline as u64 + 1, Ordering::Relaxed),
+                None if self.at_line_end => {
+                    self.position.line_start.store(start, Ordering::Relaxed)
+                }
+                None => (),
+            }
+            self.at_line_end = *last == b'\n';
+        }
+        self.position
+            .consumed
+            .store(start + amt as u64, Ordering::Relaxed);
+        self.inner.consume(amt);
+    }
+}
+
+fn open_tracked_seq_file(
+    path: &Path,
+    offset: u64,
+) -> Result<(TrackedReader, Arc<ReadPosition>), IOError> {
+    let position = Arc::new(ReadPosition {
+        consumed: AtomicU64::new(offset),
+        line_start: AtomicU64::new(offset),
+    });
+    let reader = TrackedReader {
+        inner: BufReader::new(open_seq_file_at(path, offset)?),
+        position: Arc::clone(&position),
+        at_line_end: true,
+    };
+    Ok((reader, position))
+}
+
+/// Open a sequence file at an offset into its decompressed contents. Plain and
+/// BGZF files are read from there straight away, but other compression has to
+/// be decompressed from the start.
+pub fn open_seq_file_at(path: &Path, offset: u64) -> Result<SeqSource, IOError> {
+    if offset == 0 {
+        return open_seq_file(path);
+    }
+    let source: SeqSource = match detect_compression(path)? {
+        Compression::None => {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            Box::new(file)
+        }
+        Compression::Gzip if is_bgzf(path)? => {
+            let (compressed, uncompressed) = bgzf_block_at(path, offset)?;
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(compressed))?;
+            let mut decoder = MultiGzDecoder::new(file);
+            skip_bytes(&mut decoder, offset - uncompressed)?;
+            Box::new(decoder)
+        }
+        _ => {
+            let mut source = open_seq_file(path)?;
+            skip_bytes(&mut source, offset)?;
+            source
+        }
+    };
+    Ok(source)
+}
+
+fn skip_bytes<R: Read>(reader: &mut R, n_bytes: u64) -> Result<(), IOError> {
+    std::io::copy(&mut reader.take(n_bytes), &mut std::io::sink())?;
+    Ok(())
+}
+
+/// Read the FASTA records within a range.
+pub fn load_fasta_record_range(
+    path: &Path,
+    range: &RecordRange,
+) -> Result<PartialRecords<fasta::Record>, IOError> {
+    let (source, position) = open_tracked_seq_file(path, range.start_offset.unwrap_or(0))?;
+    // The parser reads ahead the header line of the next record
+    Ok(take_record_range(
+        fasta::Reader::from_bufread(source).records(),
+        &position,
+        |position| position.line_start.load(Ordering::Relaxed),
+        range,
+    ))
+}
+
+/// Read the FASTQ records within a range.
+pub fn load_fastq_record_range(
+    path: &Path,
+    range: &RecordRange,
+) -> Result<PartialRecords<fastq::Record>, IOError> {
+    let (source, position) = open_tracked_seq_file(path, range.start_offset.unwrap_or(0))?;
+    Ok(take_record_range(
+        fastq::Reader::from_bufread(source).records(),
+        &position,
+        |position| position.consumed.load(Ordering::Relaxed),
+        range,
+    ))
+}
+
+/// Where each FASTA record starts in the decompressed data of a file.
+pub fn fasta_record_offsets(path: &Path) -> Result<Vec<u64>, IOError> {
+    let (source, position) = open_tracked_seq_file(path, 0)?;
+    Ok(record_offsets(
+        fasta::Reader::from_bufread(source).records(),
+        &position,
+        |position| position.line_start.load(Ordering::Relaxed),
+    ))
+}
+
+/// Where each FASTQ record starts in the decompressed data of a file.
+pub fn fastq_record_offsets(path: &Path) -> Result<Vec<u64>, IOError> {
+    let (source, position) = open_tracked_seq_file(path, 0)?;
+    Ok(record_offsets(
+        fastq::Reader::from_bufread(source).records(),
+        &position,
+        |position| position.consumed.load(Ordering::Relaxed),
+    ))
+}
+
+fn record_offsets<I: Iterator>(
+    records: I,
+    position: &ReadPosition,
+    next_offset: fn(&ReadPosition) -> u64,
+) -> Vec<u64> {
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    for _ in records {
+        offsets.push(offset);
+        offset = next_offset(position);
+    }
+    offsets
+}
+
+fn take_record_range<T, E, I>(
+    records: I,
+    position: &ReadPosition,
+    next_offset: fn(&ReadPosition) -> u64,
+    range: &RecordRange,
+) -> PartialRecords<T>
+where
+    T: Default,
+    I: Iterator<Item = Result<T, E>>,
+{
+    let mut records = records.map(|rec| rec.unwrap_or_default()).peekable();
+    // A known offset already points at the first record of the range
+    let n_to_skip = if range.start_offset.is_some() {
+        0
+    } else {
+        range.start
+    };
+    for _ in 0..n_to_skip {
+        if records.next().is_none() {
+            break;
+        }
+    }
+
+    let start_bytes = position.consumed.load(Ordering::Relaxed);
+    let mut taken = Vec::new();
+    for rec in records.by_ref() {
+        taken.push(rec);
+        let reached_records = range.max_records.map_or(false, |max| taken.len() >= max);
+        let reached_bytes = range.max_bytes.map_or(false, |max| {
+            position.consumed.load(Ordering::Relaxed) - start_bytes >= max
+        });
+        if reached_records || reached_bytes {
+            break;
+        }
+    }
+
+    // Peeking parses the next record, so take its offset first
+    let offset = next_offset(position);
+    let next_record = records.peek().map(|_| range.start + taken.len());
+    PartialRecords {
+        records: taken,
+        next_offset: next_record.map(|_| offset),
+        next_record,
+    }
+}
+
+/// A FASTQ file being written, gzip-compressed or not. It must be finished to
+/// flush it and, when compressed, to write the gzip trailer.
+pub enum FastqWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl FastqWriter {
+    pub fn write_record(&mut self, record: &fastq::Record) -> Result<(), IOError> {
+        let sink: &mut dyn Write = match self {
+            FastqWriter::Plain(writer) => writer,
+            FastqWriter::Gzip(encoder) => encoder,
+        };
+        sink.write_all(b"@")?;
+        sink.write_all(record.id().as_bytes())?;
+        if let Some(desc) = record.desc() {
+            sink.write_all(b" ")?;
+            sink.write_all(desc.as_bytes())?;
+        }
+        sink.write_all(b"\n")?;
+        sink.write_all(record.seq())?;
+        sink.write_all(b"\n+\n")?;
+        sink.write_all(record.qual())?;
+        sink.write_all(b"\n")
+    }
+
+    pub fn finish(self) -> Result<(), IOError> {
+        match self {
+            FastqWriter::Plain(mut writer) => writer.flush(),
+            FastqWriter::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+/// Create a FASTQ file to write to, gzip-compressed if asked.
+pub fn create_fastq_writer(dest: &Path, compress: bool) -> Result<FastqWriter, IOError> {
+    let file = BufWriter::new(File::create(dest)?);
+    if compress {
+        Ok(FastqWriter::Gzip(GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        )))
     } else {
-        return fastq::Reader::from_file(path).unwrap();
+        Ok(FastqWriter::Plain(file))
+    }
+}
+
+/// Gather the files for a batch from explicit paths and/or a directory glob.
+pub fn collect_batch_paths(
+    paths: &[PathBuf],
+    dir: Option<&Path>,
+    pattern: Option<&str>,
+) -> Result<Vec<PathBuf>, IOError> {
+    let mut batch = paths.to_vec();
+
+    if let Some(dir) = dir {
+        if !dir.is_dir() {
+            return Err(IOError::new(
+                NotFound,
+                format!("{} is not a directory.", dir.display()),
+            ));
+        }
+        let full_pattern = dir.join(pattern.unwrap_or("*"));
+        let matches = match glob::glob(&full_pattern.to_string_lossy()) {
+            Ok(matches) => matches,
+            Err(err) => return Err(IOError::new(InvalidInput, err.to_string())),
+        };
+        batch.extend(matches.filter_map(Result::ok).filter(|path| path.is_file()));
+    }
+
+    batch.sort();
+    batch.dedup();
+    Ok(batch)
+}
+
+/// Load a QC profile from a `.toml` file, or from JSON otherwise.
+pub fn load_qc_profile(source: &Path) -> Result<QcProfile, IOError> {
+    let contents = std::fs::read_to_string(source)?;
+    let is_toml = source.extension().map_or(false, |ext| ext == "toml");
+    let profile = if is_toml {
+        toml::from_str::<QcProfile>(&contents).map_err(|err| err.to_string())
+    } else {
+        serde_json::from_str::<QcProfile>(&contents).map_err(|err| err.to_string())
+    };
+
+    match profile {
+        Ok(profile) => Ok(profile),
+        Err(err) => Err(IOError::new(
+            InvalidData,
+            format!("Could not parse QC profile {}: {}", source.display(), err),
+        )),
+    }
+}
+
+/// Load every `.toml` and `.json` QC profile in a directory.
+pub fn load_qc_profiles(dir: &Path) -> Result<Vec<QcProfile>, IOError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .map_or(false, |ext| ext == "toml" || ext == "json")
+        })
+        .collect();
+    paths.sort();
+
+    paths.iter().map(|path| load_qc_profile(path)).collect()
+}
+
+/// Derive a sample name from a sequence file path, e.g. `reads.fq.gz` -> `reads`.
+pub fn sample_name(path: &Path) -> String {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = [".gz", ".bz2", ".xz", ".zst"]
+        .iter()
+        .find_map(|suffix| file_name.strip_suffix(suffix))
+        .unwrap_or(&file_name);
+    match file_name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem.to_owned(),
+        _ => file_name.to_owned(),
     }
 }
 
@@ -88,26 +503,282 @@ where
     }
 }
 
-fn extract_gzip(path: &Path) -> String {
-    let gzip_file = File::open(path).unwrap();
-    let mut decoder = GzDecoder::new(gzip_file);
-    let mut buf_string = String::new();
-    let out_file_path = path.as_os_str().to_str().unwrap().replace(".gz", "");
-    let extracted_file = File::create(&out_file_path).unwrap();
-    decoder.read_to_string(&mut buf_string).unwrap();
-    let mut file_buffer = std::io::BufWriter::new(extracted_file);
-    file_buffer.write_all(buf_string.as_bytes()).unwrap();
-    out_file_path
-}
-
 #[cfg(test)]
 mod tests {
     use std::fs::File;
+    use std::io::Write;
 
-    use crate::models::{FastaSeqResult, FastqSeqResult};
+    use crate::models::{Compression, FastaSeqResult, FastqSeqResult};
     use uuid::Uuid;
 
-    use super::{load_results, save_results};
+    use super::{
+        collect_batch_paths, create_fastq_writer, detect_compression, load_fasta_record_range,
+        load_fastq_record_range, load_fastq_records, load_qc_profile, load_qc_profiles,
+        load_results, sample_name, save_results, RecordRange,
+    };
+
+    const TEST_FASTQ: &[u8] = b"@id1 desc\nACGT\n+\n!!!!\n@id2 desc\nGGCC\n+\n!!!!\n";
+
+    fn compress(compression: Compression) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        match compression {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(&mut compressed, flate2::Compression::default());
+                encoder.write_all(TEST_FASTQ).unwrap();
+                encoder.finish().unwrap();
+            }
+            Compression::Bzip2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(&mut compressed, bzip2::Compression::default());
+                encoder.write_all(TEST_FASTQ).unwrap();
+                encoder.finish().unwrap();
+            }
+            Compression::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(&mut compressed, 6);
+                encoder.write_all(TEST_FASTQ).unwrap();
+                encoder.finish().unwrap();
+            }
+            Compression::Zstd => {
+                compressed = zstd::stream::encode_all(TEST_FASTQ, 0).unwrap();
+            }
+            Compression::None => compressed.extend_from_slice(TEST_FASTQ),
+        }
+        compressed
+    }
+
+    #[test]
+    fn test_load_fastq_records_decompresses_by_magic_bytes() {
+        for compression in [
+            Compression::None,
+            Compression::Gzip,
+            Compression::Bzip2,
+            Compression::Xz,
+            Compression::Zstd,
+        ] {
+            // Arrange: a deliberately misleading extension
+            let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq");
+            std::fs::write(&test_file, compress(compression)).unwrap();
+
+            // Act
+            let detected = detect_compression(test_file.as_path());
+            let records = load_fastq_records(test_file.as_path());
+
+            // Assert
+            assert_eq!(detected.unwrap(), compression);
+            assert!(records.is_ok());
+            let records = records.unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[1].seq(), b"GGCC");
+
+            // Clean up
+            assert!(std::fs::remove_file(test_file).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_create_fastq_writer_round_trips_records() {
+        for compress in [false, true] {
+            // Arrange
+            let source = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq");
+            let dest = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq");
+            std::fs::write(&source, TEST_FASTQ).unwrap();
+            let records = load_fastq_records(source.as_path()).unwrap();
+
+            // Act
+            let mut writer = create_fastq_writer(&dest, compress).unwrap();
+            for rec in &records {
+                writer.write_record(rec).unwrap();
+            }
+            let finish_action = writer.finish();
+
+            // Assert
+            assert!(finish_action.is_ok());
+            let expected = if compress {
+                Compression::Gzip
+            } else {
+                Compression::None
+            };
+            assert_eq!(detect_compression(dest.as_path()).unwrap(), expected);
+            assert_eq!(load_fastq_records(dest.as_path()).unwrap(), records);
+
+            // Clean up
+            assert!(std::fs::remove_file(source).is_ok());
+            assert!(std::fs::remove_file(dest).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_load_fastq_record_range_resumes_where_it_stopped() {
+        // Arrange
+        let source = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fq");
+        std::fs::write(&source, TEST_FASTQ.repeat(5)).unwrap();
+        let first = RecordRange {
+            max_records: Some(4),
+            ..Default::default()
+        };
+
+        // Act
+        let preview = load_fastq_record_range(&source, &first).unwrap();
+        let rest = RecordRange {
+            start: preview.next_record.unwrap(),
+            ..Default::default()
+        };
+        let remainder = load_fastq_record_range(&source, &rest).unwrap();
+        let seeked = RecordRange {
+            start: preview.next_record.unwrap(),
+            start_offset: preview.next_offset,
+            ..Default::default()
+        };
+        let seeked_remainder = load_fastq_record_range(&source, &seeked).unwrap();
+        let by_bytes = RecordRange {
+            max_bytes: Some(1),
+            ..Default::default()
+        };
+        let buffered = load_fastq_record_range(&source, &by_bytes).unwrap();
+
+        // Assert
+        assert_eq!(preview.records.len(), 4);
+        assert_eq!(preview.next_record, Some(4));
+        assert_eq!(preview.next_offset, Some(2 * TEST_FASTQ.len() as u64));
+        assert_eq!(remainder.records.len(), 6);
+        assert_eq!(remainder.records[0].id(), "id1");
+        assert!(remainder.next_record.is_none());
+        assert_eq!(seeked_remainder.records, remainder.records);
+        // The byte limit is checked after each record, so one is always read
+        assert_eq!(buffered.records.len(), 1);
+        assert_eq!(buffered.next_record, Some(1));
+
+        // Clean up
+        assert!(std::fs::remove_file(source).is_ok());
+    }
+
+    #[test]
+    fn test_load_fasta_record_range_reports_next_offset() {
+        // Arrange
+        let source = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        let fasta = ">chr1 first\nACGT\nAC\n\n>chr2\nGG\n>chr3\nTT\n";
+        std::fs::write(&source, fasta).unwrap();
+        let first = RecordRange {
+            max_records: Some(1),
+            ..Default::default()
+        };
+
+        // Act
+        let preview = load_fasta_record_range(&source, &first).unwrap();
+        let rest = RecordRange {
+            start: 1,
+            start_offset: preview.next_offset,
+            ..Default::default()
+        };
+        let remainder = load_fasta_record_range(&source, &rest).unwrap();
+
+        // Assert
+        assert_eq!(
+            preview.next_offset,
+            Some(fasta.find(">chr2").unwrap() as u64)
+        );
+        assert_eq!(remainder.records.len(), 2);
+        assert_eq!(remainder.records[0].id(), "chr2");
+        assert!(remainder.next_offset.is_none());
+
+        // Clean up
+        assert!(std::fs::remove_file(source).is_ok());
+    }
+
+    #[test]
+    fn test_load_qc_profiles_reads_toml_and_json() {
+        // Arrange
+        let profile_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir(&profile_dir).unwrap();
+        std::fs::write(
+            profile_dir.join("a.toml"),
+            "name = \"A\"\n[[thresholds]]\nmetric = \"mean_gc\"\ndirection = \"max\"\nwarn = 0.6\nfail = 0.7\n",
+        )
+        .unwrap();
+        std::fs::write(
+            profile_dir.join("b.json"),
+            r#"{"name": "B", "thresholds": []}"#,
+        )
+        .unwrap();
+
+        // Act
+        let profiles = load_qc_profiles(profile_dir.as_path());
+
+        // Assert
+        assert!(profiles.is_ok());
+        let profiles = profiles.unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].name, "A");
+        assert_eq!(profiles[0].thresholds.len(), 1);
+        assert_eq!(profiles[1].name, "B");
+
+        // Clean up
+        assert!(std::fs::remove_dir_all(profile_dir).is_ok());
+    }
+
+    #[test]
+    fn test_load_qc_profile_errors_on_invalid_profile() {
+        // Arrange
+        let profile_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".json");
+        std::fs::write(&profile_file, "{}").unwrap();
+
+        // Act
+        let profile = load_qc_profile(profile_file.as_path());
+
+        // Assert
+        assert!(profile.is_err());
+
+        // Clean up
+        assert!(std::fs::remove_file(profile_file).is_ok());
+    }
+
+    #[test]
+    fn test_collect_batch_paths_globs_directory() {
+        // Arrange
+        let batch_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir(&batch_dir).unwrap();
+        for name in ["a.fq", "b.fq", "notes.txt"] {
+            File::create(batch_dir.join(name)).unwrap();
+        }
+        let explicit = vec![batch_dir.join("a.fq")];
+
+        // Act
+        let paths = collect_batch_paths(&explicit, Some(batch_dir.as_path()), Some("*.fq"));
+
+        // Assert
+        assert!(paths.is_ok());
+        assert_eq!(
+            paths.unwrap(),
+            vec![batch_dir.join("a.fq"), batch_dir.join("b.fq")]
+        );
+
+        // Clean up
+        assert!(std::fs::remove_dir_all(batch_dir).is_ok());
+    }
+
+    #[test]
+    fn test_collect_batch_paths_errors_on_missing_dir() {
+        // Arrange
+        let batch_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+
+        // Act
+        let paths = collect_batch_paths(&[], Some(batch_dir.as_path()), None);
+
+        // Assert
+        assert!(paths.is_err())
+    }
+
+    #[test]
+    fn test_sample_name_strips_extensions() {
+        assert_eq!(sample_name(std::path::Path::new("/data/reads.fq")), "reads");
+        assert_eq!(
+            sample_name(std::path::Path::new("reads_R1.fastq.gz")),
+            "reads_R1"
+        );
+        assert_eq!(sample_name(std::path::Path::new("reads.fq.zst")), "reads");
+        assert_eq!(sample_name(std::path::Path::new("reads")), "reads");
+    }
 
     #[test]
     fn test_save_results_saves_fastq_seq_result_to_dest() {
@@ -0,0 +1,247 @@
+use std::fs::File;
+use std::io::ErrorKind::InvalidData;
+use std::io::{BufReader, BufWriter, Error as IOError, Read, Write};
+use std::path::{Path, PathBuf};
+
+const INDEX_MAGIC: &[u8; 8] = b"FQAKIDX\x02";
+const INDEX_EXTENSION: &str = "kidx";
+
+/// A sorted set of canonical k-mers sampled from a reference, for containment
+/// screening. Only k-mers whose hash is divisible by `scale` are kept, so large
+/// references can be indexed in a fraction of the memory.
+#[derive(Debug, PartialEq)]
+pub struct KmerIndex {
+    pub name: String,
+    // The canonical path of the reference the index was built from
+    pub reference: PathBuf,
+    pub k: usize,
+    pub scale: u64,
+    pub kmers: Vec<u64>,
+}
+
+impl KmerIndex {
+    pub fn contains(&self, kmer: u64) -> bool {
+        self.kmers.binary_search(&kmer).is_ok()
+    }
+}
+
+/// Where the index of a reference is cached. The name carries a hash of the
+/// reference's full path, so same-named references in different directories and
+/// indexes built with different settings do not overwrite each other.
+pub fn kmer_index_path(
+    index_dir: &Path,
+    reference: &Path,
+    k: usize,
+    scale: u64,
+) -> Result<PathBuf, IOError> {
+    let reference = reference.canonicalize()?;
+    let stem = reference
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "reference".to_owned());
+    Ok(index_dir.join(format!(
+        "{}.{:016x}.k{}.s{}.{}",
+        stem,
+        path_hash(&reference),
+        k,
+        scale,
+        INDEX_EXTENSION
+    )))
+}
+
+/// Load a cached index, or `None` if it is missing, older than its reference or
+/// was built from another reference or with other settings.
+pub fn load_cached_kmer_index(
+    index: &Path,
+    reference: &Path,
+    k: usize,
+    scale: u64,
+) -> Result<Option<KmerIndex>, IOError> {
+    if kmer_index_is_stale(index, reference)? {
+        return Ok(None);
+    }
+    let cached = load_kmer_index(index)?;
+    if cached.reference != reference.canonicalize()? || cached.k != k || cached.scale != scale {
+        return Ok(None);
+    }
+    Ok(Some(cached))
+}
+
+/// Whether a cached index is missing or older than its reference.
+fn kmer_index_is_stale(index: &Path, reference: &Path) -> Result<bool, IOError> {
+    if !index.is_file() {
+        return Ok(true);
+    }
+    let index_modified = index.metadata()?.modified()?;
+    let reference_modified = reference.metadata()?.modified()?;
+    Ok(index_modified < reference_modified)
+}
+
+/// FNV-1a, which unlike the standard library's hasher is stable between builds.
+fn path_hash(path: &Path) -> u64 {
+    path.to_string_lossy()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+pub fn save_kmer_index(index: &KmerIndex, dest: &Path) -> Result<PathBuf, IOError> {
+    let mut writer = BufWriter::new(File::create(dest)?);
+    writer.write_all(INDEX_MAGIC)?;
+    writer.write_all(&(index.k as u32).to_le_bytes())?;
+    writer.write_all(&index.scale.to_le_bytes())?;
+    writer.write_all(&(index.name.len() as u32).to_le_bytes())?;
+    writer.write_all(index.name.as_bytes())?;
+    let reference = index.reference.to_string_lossy();
+    writer.write_all(&(reference.len() as u32).to_le_bytes())?;
+    writer.write_all(reference.as_bytes())?;
+    writer.write_all(&(index.kmers.len() as u64).to_le_bytes())?;
+    for kmer in &index.kmers {
+        writer.write_all(&kmer.to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    Ok(dest.to_path_buf())
+}
+
+pub fn load_kmer_index(path: &Path) -> Result<KmerIndex, IOError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != INDEX_MAGIC {
+        return Err(IOError::new(
+            InvalidData,
+            format!("{} is not a k-mer index.", path.display()),
+        ));
+    }
+
+    let k = read_u32(&mut reader)? as usize;
+    let scale = read_u64(&mut reader)?;
+    let mut name = vec![0; read_u32(&mut reader)? as usize];
+    reader.read_exact(&mut name)?;
+    let mut reference = vec![0; read_u32(&mut reader)? as usize];
+    reader.read_exact(&mut reference)?;
+    let n_kmers = read_u64(&mut reader)?;
+    let mut kmers = Vec::new();
+    for _ in 0..n_kmers {
+        kmers.push(read_u64(&mut reader)?);
+    }
+
+    Ok(KmerIndex {
+        name: String::from_utf8_lossy(&name).into_owned(),
+        reference: PathBuf::from(String::from_utf8_lossy(&reference).into_owned()),
+        k,
+        scale,
+        kmers,
+    })
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, IOError> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, IOError> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use super::{
+        kmer_index_path, load_cached_kmer_index, load_kmer_index, save_kmer_index, KmerIndex,
+    };
+
+    #[test]
+    fn test_kmer_index_round_trip() {
+        // Arrange
+        let index = KmerIndex {
+            name: "phix".to_owned(),
+            reference: PathBuf::from("/refs/phix.fa"),
+            k: 21,
+            scale: 4,
+            kmers: vec![3, 17, 1 << 40],
+        };
+        let dest = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".kidx");
+
+        // Act
+        let save_action = save_kmer_index(&index, &dest);
+        let loaded = load_kmer_index(&dest);
+
+        // Assert
+        assert!(save_action.is_ok());
+        assert_eq!(loaded.unwrap(), index);
+        assert!(index.contains(17));
+        assert!(!index.contains(18));
+
+        // Clean up
+        assert!(std::fs::remove_file(dest).is_ok());
+    }
+
+    #[test]
+    fn test_load_cached_kmer_index_when_missing() {
+        // Arrange
+        let reference = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".fa");
+        std::fs::write(&reference, ">phix\nACGT\n").unwrap();
+        let index = kmer_index_path(&std::env::temp_dir(), &reference, 21, 1).unwrap();
+
+        // Act
+        let cached = load_cached_kmer_index(&index, &reference, 21, 1);
+
+        // Assert
+        assert!(cached.unwrap().is_none());
+        assert!(index.to_str().unwrap().ends_with(".k21.s1.kidx"));
+
+        // Clean up
+        assert!(std::fs::remove_file(reference).is_ok());
+    }
+
+    #[test]
+    fn test_kmer_index_path_keys_on_full_path() {
+        // Arrange
+        let dirs = [
+            std::env::temp_dir().join(Uuid::new_v4().to_string()),
+            std::env::temp_dir().join(Uuid::new_v4().to_string()),
+        ];
+        let references: Vec<PathBuf> = dirs.iter().map(|dir| dir.join("ref.fa")).collect();
+        for (dir, reference) in dirs.iter().zip(&references) {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(reference, ">ref\nACGT\n").unwrap();
+        }
+        let index_dir = std::env::temp_dir();
+        let first = kmer_index_path(&index_dir, &references[0], 21, 1).unwrap();
+        let second = kmer_index_path(&index_dir, &references[1], 21, 1).unwrap();
+        let index = KmerIndex {
+            name: "ref".to_owned(),
+            reference: references[0].canonicalize().unwrap(),
+            k: 21,
+            scale: 1,
+            kmers: vec![3],
+        };
+        save_kmer_index(&index, &first).unwrap();
+
+        // Act
+        let same = load_cached_kmer_index(&first, &references[0], 21, 1);
+        let other_reference = load_cached_kmer_index(&first, &references[1], 21, 1);
+        let other_k = load_cached_kmer_index(&first, &references[0], 15, 1);
+
+        // Assert
+        assert_ne!(first, second);
+        assert_eq!(same.unwrap(), Some(index));
+        assert!(other_reference.unwrap().is_none());
+        assert!(other_k.unwrap().is_none());
+
+        // Clean up
+        assert!(std::fs::remove_file(first).is_ok());
+        for dir in &dirs {
+            assert!(std::fs::remove_dir_all(dir).is_ok());
+        }
+    }
+}
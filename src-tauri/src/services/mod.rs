@@ -1 +1,10 @@
+pub mod cram;
+pub mod faidx;
+pub mod fastqc;
+pub mod format;
 pub mod io;
+pub mod kmer_index;
+pub mod multiqc;
+pub mod rans;
+pub mod sam;
+pub mod samplesheet;
@@ -0,0 +1,185 @@
+use crate::models::SeqSummary;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::ErrorKind::{InvalidData, NotFound};
+use std::io::{BufWriter, Error as IOError, Write};
+use std::path::{Path, PathBuf};
+
+/// MultiQC picks up custom content from any file ending in this suffix.
+const MQC_SUFFIX: &str = "_mqc.json";
+
+/// Write the summary metrics as MultiQC General Statistics columns.
+pub fn save_multiqc_general_stats(
+    sample: &str,
+    summary: &SeqSummary,
+    dest_dir: &Path,
+) -> Result<PathBuf, IOError> {
+    let mut headers = json!({
+        "n_records": {
+            "title": "Reads",
+            "description": "Number of records in the file",
+            "format": "{:,.0f}",
+        },
+        "mean_len": {
+            "title": "Mean length",
+            "description": "Mean sequence length of the valid records",
+            "suffix": " bp",
+        },
+        "mean_gc": {
+            "title": "% GC",
+            "description": "Mean GC content of the valid records",
+            "min": 0,
+            "max": 100,
+            "suffix": "%",
+        },
+        "dup_rate": {
+            "title": "% Dups",
+            "description": "Percentage of valid records duplicating an earlier sequence",
+            "min": 0,
+            "max": 100,
+            "suffix": "%",
+        },
+        "adapter_rate": {
+            "title": "% Adapter",
+            "description": "Percentage of valid records containing a known adapter",
+            "min": 0,
+            "max": 100,
+            "suffix": "%",
+        },
+    });
+    let mut stats = json!({
+        "n_records": summary.n_records,
+        "mean_len": summary.mean_len,
+        "mean_gc": summary.mean_gc * 100.0,
+        "dup_rate": summary.dup_rate * 100.0,
+        "adapter_rate": summary.adapter_rate * 100.0,
+    });
+    if summary.result_type == "fastq" {
+        headers["mean_quality"] = json!({
+            "title": "Mean Q",
+            "description": "Phred score of the mean base error probability",
+            "min": 0,
+        });
+        headers["mean_expected_errors"] = json!({
+            "title": "Mean EE",
+            "description": "Mean expected errors per valid record",
+            "min": 0,
+        });
+        stats["mean_quality"] = json!(summary.mean_quality);
+        stats["mean_expected_errors"] = json!(summary.mean_expected_errors);
+    }
+
+    let content = json!({
+        "id": "fastq_analyser_general_stats",
+        "plot_type": "generalstats",
+        "pconfig": headers,
+        "data": { sample: stats },
+    });
+
+    write_mqc_file(&content, &mqc_path(dest_dir, sample, "general_stats"))
+}
+
+/// Write a distribution as a MultiQC line graph section.
+pub fn save_multiqc_linegraph(
+    sample: &str,
+    plot_id: &str,
+    title: &str,
+    xlab: &str,
+    data: &BTreeMap<usize, usize>,
+    dest_dir: &Path,
+) -> Result<PathBuf, IOError> {
+    let content = json!({
+        "id": format!("fastq_analyser_{}", plot_id),
+        "section_name": title,
+        "plot_type": "linegraph",
+        "pconfig": {
+            "id": format!("fastq_analyser_{}_plot", plot_id),
+            "title": format!("FastQ Analyser: {}", title),
+            "xlab": xlab,
+            "ylab": "Count",
+        },
+        "data": { sample: data },
+    });
+
+    write_mqc_file(&content, &mqc_path(dest_dir, sample, plot_id))
+}
+
+fn mqc_path(dest_dir: &Path, sample: &str, section: &str) -> PathBuf {
+    dest_dir.join(format!("{}_{}{}", sample, section, MQC_SUFFIX))
+}
+
+fn write_mqc_file(content: &Value, dest: &Path) -> Result<PathBuf, IOError> {
+    match dest.parent() {
+        Some(dir) if !dir.is_dir() => {
+            return Err(IOError::new(
+                NotFound,
+                format!("{} is not a directory.", dir.display()),
+            ))
+        }
+        _ => (),
+    }
+
+    let serialised = match serde_json::to_string_pretty(content) {
+        Ok(serialised) => serialised,
+        Err(_) => return Err(IOError::new(InvalidData, "Could not save MultiQC data.")),
+    };
+
+    let mut writer = BufWriter::new(File::create(dest)?);
+    writer.write_all(serialised.as_bytes())?;
+    Ok(dest.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::models::SeqSummary;
+    use uuid::Uuid;
+
+    use super::{save_multiqc_general_stats, save_multiqc_linegraph};
+
+    #[test]
+    fn test_save_multiqc_general_stats_writes_sample_data() {
+        // Arrange
+        let summary = SeqSummary {
+            n_records: 20,
+            mean_gc: 0.5,
+            ..Default::default()
+        };
+        let save_dir = tauri::api::path::desktop_dir().unwrap();
+        let sample = Uuid::new_v4().to_string();
+
+        // Act
+        let save_action = save_multiqc_general_stats(&sample, &summary, save_dir.as_path());
+
+        // Assert
+        assert!(save_action.is_ok());
+        let save_dest = save_action.unwrap();
+        assert!(save_dest.to_str().unwrap().ends_with("_mqc.json"));
+        let content: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(&save_dest).unwrap()).unwrap();
+        assert_eq!(content["plot_type"], "generalstats");
+        assert_eq!(content["data"][sample.as_str()]["n_records"], 20);
+        assert_eq!(content["data"][sample.as_str()]["mean_gc"], 50.0);
+
+        // Clean up
+        assert!(std::fs::remove_file(save_dest).is_ok());
+    }
+
+    #[test]
+    fn test_save_multiqc_linegraph_errors_on_nonexistent_dest() {
+        // Arrange
+        let save_dir = tauri::api::path::desktop_dir()
+            .unwrap()
+            .join(Uuid::new_v4().to_string());
+        let data = BTreeMap::from([(50, 2)]);
+
+        // Act
+        let save_action =
+            save_multiqc_linegraph("sample", "gc", "GC content", "% GC", &data, &save_dir);
+
+        // Assert
+        assert!(save_action.is_err());
+    }
+}
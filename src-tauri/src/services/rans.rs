@@ -0,0 +1,225 @@
+use std::io::Error as IOError;
+use std::io::ErrorKind::InvalidData;
+
+/// Symbol frequencies always sum to 2^12.
+const TF_SHIFT: u32 = 12;
+/// The decoder state is kept at or above this, reading a byte whenever it drops below.
+const RANS_BYTE_L: u32 = 1 << 23;
+
+/// The cumulative frequency table of one context, and the symbol owning each slot.
+struct FreqTable {
+    freq: [u32; 256],
+    cum: [u32; 256],
+    symbols: Vec<u8>,
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn u8(&mut self) -> Result<u8, IOError> {
+        let byte = self.data.get(self.pos).copied().ok_or_else(truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn u32(&mut self) -> Result<u32, IOError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(truncated)?;
+        self.pos += 4;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Bytes past the end of the stream read as zero, as htslib does.
+    fn renorm(&mut self, mut state: u32) -> u32 {
+        while state < RANS_BYTE_L {
+            let byte = self.data.get(self.pos).copied().unwrap_or(0);
+            self.pos += 1;
+            state = (state << 8) | u32::from(byte);
+        }
+        state
+    }
+}
+
+fn truncated() -> IOError {
+    IOError::new(InvalidData, "rANS data is truncated.")
+}
+
+/// Decode a block compressed with the CRAM 3.0 rANS 4x8 codec, of order 0 or 1.
+pub fn decode_rans4x8(data: &[u8]) -> Result<Vec<u8>, IOError> {
+    let mut input = Input { data, pos: 0 };
+    let order = input.u8()?;
+    let _compressed_len = input.u32()?;
+    let len = input.u32()? as usize;
+
+    match order {
+        0 => decode_order0(&mut input, len),
+        1 => decode_order1(&mut input, len),
+        _ => Err(IOError::new(
+            InvalidData,
+            format!("rANS order {} is not supported.", order),
+        )),
+    }
+}
+
+fn decode_order0(input: &mut Input, len: usize) -> Result<Vec<u8>, IOError> {
+    let table = read_freq_table(input)?;
+    let mut states = [0; 4];
+    for state in states.iter_mut() {
+        *state = input.u32()?;
+    }
+
+    let mut out = Vec::with_capacity(len);
+    for i in 0..len {
+        let state = &mut states[i % 4];
+        let sym = decode_symbol(&table, state);
+        *state = input.renorm(*state);
+        out.push(sym);
+    }
+    Ok(out)
+}
+
+fn decode_order1(input: &mut Input, len: usize) -> Result<Vec<u8>, IOError> {
+    let mut tables: Vec<Option<FreqTable>> = (0..256).map(|_| None).collect();
+    read_symbol_runs(input, |input, context| {
+        tables[usize::from(context)] = Some(read_freq_table(input)?);
+        Ok(())
+    })?;
+    let mut states = [0; 4];
+    for state in states.iter_mut() {
+        *state = input.u32()?;
+    }
+
+    // Each state decodes its own quarter of the output, and the last one the remainder
+    let quarter = len / 4;
+    let mut out = vec![0; len];
+    let mut contexts = [0u8; 4];
+    let mut step = |k: usize, i: usize, input: &mut Input| -> Result<(), IOError> {
+        let table = tables[usize::from(contexts[k])]
+            .as_ref()
+            .ok_or_else(|| IOError::new(InvalidData, "rANS context has no frequencies."))?;
+        let sym = decode_symbol(table, &mut states[k]);
+        states[k] = input.renorm(states[k]);
+        out[i] = sym;
+        contexts[k] = sym;
+        Ok(())
+    };
+    for i in 0..quarter {
+        for k in 0..4 {
+            step(k, i + k * quarter, input)?;
+        }
+    }
+    for i in 4 * quarter..len {
+        step(3, i, input)?;
+    }
+    Ok(out)
+}
+
+fn decode_symbol(table: &FreqTable, state: &mut u32) -> u8 {
+    let slot = *state & ((1 << TF_SHIFT) - 1);
+    let sym = table.symbols[slot as usize];
+    let idx = usize::from(sym);
+    *state = table.freq[idx] * (*state >> TF_SHIFT) + slot - table.cum[idx];
+    sym
+}
+
+fn read_freq_table(input: &mut Input) -> Result<FreqTable, IOError> {
+    let mut table = FreqTable {
+        freq: [0; 256],
+        cum: [0; 256],
+        symbols: vec![0; 1 << TF_SHIFT],
+    };
+    let mut total = 0;
+    read_symbol_runs(input, |input, sym| {
+        let mut freq = u32::from(input.u8()?);
+        if freq >= 128 {
+            freq = ((freq & 0x7f) << 8) | u32::from(input.u8()?);
+        }
+        if total + freq > 1 << TF_SHIFT {
+            return Err(IOError::new(
+                InvalidData,
+                "rANS frequencies exceed the total.",
+            ));
+        }
+        let idx = usize::from(sym);
+        table.freq[idx] = freq;
+        table.cum[idx] = total;
+        for slot in total..total + freq {
+            table.symbols[slot as usize] = sym;
+        }
+        total += freq;
+        Ok(())
+    })?;
+    Ok(table)
+}
+
+/// Walk a list of symbols ending with a zero, where a symbol followed by its
+/// successor starts a run whose length follows.
+fn read_symbol_runs<F>(input: &mut Input, mut visit: F) -> Result<(), IOError>
+where
+    F: FnMut(&mut Input, u8) -> Result<(), IOError>,
+{
+    let mut sym = input.u8()?;
+    let mut run = 0;
+    loop {
+        visit(input, sym)?;
+        if run > 0 {
+            run -= 1;
+            sym = sym.wrapping_add(1);
+        } else {
+            let next = input.u8()?;
+            if next != 0 && next == sym.wrapping_add(1) {
+                sym = next;
+                run = input.u8()?;
+            } else {
+                sym = next;
+            }
+        }
+        if sym == 0 {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_rans4x8;
+
+    #[test]
+    fn test_decode_rans4x8_order0() {
+        // htscodecs' rans_compress(b"abracadabra abracadabra", order 0)
+        let compressed = [
+            0, 38, 0, 0, 0, 23, 0, 0, 0, 32, 128, 178, 97, 134, 245, 98, 2, 130, 200, 129, 100,
+            129, 100, 114, 130, 200, 0, 110, 231, 65, 45, 57, 153, 80, 11, 114, 255, 64, 45, 184,
+            213, 65, 1, 58, 187, 120, 222,
+        ];
+
+        let decoded = decode_rans4x8(&compressed);
+
+        assert_eq!(decoded.unwrap(), b"abracadabra abracadabra");
+    }
+
+    #[test]
+    fn test_decode_rans4x8_order1() {
+        // htscodecs' rans_compress(b"abracadabra abracadabra", order 1)
+        let compressed = [
+            1, 60, 0, 0, 0, 23, 0, 0, 0, 0, 97, 143, 255, 0, 32, 97, 143, 255, 0, 97, 32, 129, 153,
+            98, 136, 0, 99, 1, 131, 51, 131, 51, 0, 98, 2, 114, 143, 255, 0, 97, 143, 255, 0, 97,
+            143, 255, 0, 114, 97, 143, 255, 0, 0, 236, 68, 1, 5, 126, 78, 1, 5, 141, 176, 5, 10,
+            36, 60, 11, 25,
+        ];
+
+        let decoded = decode_rans4x8(&compressed);
+
+        assert_eq!(decoded.unwrap(), b"abracadabra abracadabra");
+    }
+
+    #[test]
+    fn test_decode_rans4x8_errors_on_truncated_table() {
+        assert!(decode_rans4x8(&[0, 38, 0, 0, 0, 23, 0, 0, 0, 32]).is_err());
+    }
+}
@@ -0,0 +1,441 @@
+use crate::services::io::{open_seq_file_at, SeqSource};
+use bio::alphabets::dna;
+use bio::io::fastq;
+use std::io::ErrorKind::{InvalidData, UnexpectedEof};
+use std::io::{BufRead, BufReader, Error as IOError, Read};
+use std::path::{Path, PathBuf};
+
+const FLAG_REVERSE: u16 = 0x10;
+const FLAG_SECONDARY: u16 = 0x100;
+const FLAG_SUPPLEMENTARY: u16 = 0x800;
+
+/// 4-bit encoded BAM bases, indexed by code.
+const BAM_BASES: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+
+/// Read the primary records of a SAM file as FASTQ records, in their original
+/// sequencing orientation.
+pub fn load_sam_records(path: &Path) -> Result<Vec<fastq::Record>, IOError> {
+    open_sam_records(path, 0)?.collect()
+}
+
+/// Where each primary record of a SAM file starts in its decompressed data.
+pub fn sam_record_offsets(path: &Path) -> Result<Vec<u64>, IOError> {
+    let mut records = open_sam_records(path, 0)?;
+    let mut offsets = Vec::new();
+    while let Some(rec) = records.next() {
+        rec?;
+        offsets.push(records.record_offset());
+    }
+    Ok(offsets)
+}
+
+/// Read the SAM record starting at an offset found by `sam_record_offsets`.
+pub fn load_sam_record_at(path: &Path, offset: u64) -> Result<Option<fastq::Record>, IOError> {
+    open_sam_records(path, offset)?.next().transpose()
+}
+
+/// Reads the primary records of a SAM file a line at a time, keeping track of
+/// where each starts in the decompressed data.
+pub struct SamRecords {
+    reader: BufReader<SeqSource>,
+    path: PathBuf,
+    line: String,
+    // Counted only when reading from the start of the file
+    line_no: Option<usize>,
+    offset: u64,
+    record_offset: u64,
+}
+
+/// Open a SAM file to read its primary records from an offset, which must be
+/// where a line starts.
+pub fn open_sam_records(path: &Path, offset: u64) -> Result<SamRecords, IOError> {
+    Ok(SamRecords {
+        reader: BufReader::new(open_seq_file_at(path, offset)?),
+        path: path.to_path_buf(),
+        line: String::new(),
+        line_no: Some(0).filter(|_| offset == 0),
+        offset,
+        record_offset: offset,
+    })
+}
+
+impl SamRecords {
+    /// Where the last record read starts.
+    pub fn record_offset(&self) -> u64 {
+        self.record_offset
+    }
+
+    /// Where the line after the last record read starts.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl Iterator for SamRecords {
+    type Item = Result<fastq::Record, IOError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            let n_read = match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(n_read) => n_read,
+                Err(err) => return Some(Err(err)),
+            };
+            let line_start = self.offset;
+            self.offset += n_read as u64;
+            if let Some(line_no) = self.line_no.as_mut() {
+                *line_no += 1;
+            }
+
+            match parse_sam_line(self.line.trim_end_matches(&['\n', '\r'][..])) {
+                Ok(Some(rec)) => {
+                    self.record_offset = line_start;
+                    return Some(Ok(rec));
+                }
+                Ok(None) => {}
+                Err(problem) => {
+                    let line = match self.line_no {
+                        Some(line_no) => format!("Line {}", line_no),
+                        None => format!("The line at byte {}", line_start),
+                    };
+                    return Some(Err(IOError::new(
+                        InvalidData,
+                        format!("{} of {} {}.", line, self.path.display(), problem),
+                    )));
+                }
+            }
+        }
+    }
+}
+
+/// Parse one line of a SAM file, skipping header lines and alignments that are
+/// not primary.
+fn parse_sam_line(line: &str) -> Result<Option<fastq::Record>, &'static str> {
+    if line.is_empty() || line.starts_with('@') {
+        return Ok(None);
+    }
+
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 11 {
+        return Err("has fewer than 11 SAM columns");
+    }
+    let flag: u16 = fields[1].parse().map_err(|_| "has an invalid FLAG")?;
+
+    let seq = if fields[9] == "*" { "" } else { fields[9] };
+    let qual = if fields[10] == "*" {
+        // Missing qualities are reported as the lowest score
+        vec![b'!'; seq.len()]
+    } else {
+        fields[10].as_bytes().to_vec()
+    };
+    Ok(to_fastq_record(fields[0], flag, seq.as_bytes(), &qual))
+}
+
+/// Read the primary records of a BAM file as FASTQ records, in their original
+/// sequencing orientation.
+pub fn load_bam_records(path: &Path) -> Result<Vec<fastq::Record>, IOError> {
+    open_bam_records(path, 0)?.collect()
+}
+
+/// Where each primary record of a BAM file starts in its decompressed data.
+pub fn bam_record_offsets(path: &Path) -> Result<Vec<u64>, IOError> {
+    let mut records = open_bam_records(path, 0)?;
+    let mut offsets = Vec::new();
+    while let Some(rec) = records.next() {
+        rec?;
+        offsets.push(records.record_offset());
+    }
+    Ok(offsets)
+}
+
+/// Read the BAM record starting at an offset found by `bam_record_offsets`.
+pub fn load_bam_record_at(path: &Path, offset: u64) -> Result<Option<fastq::Record>, IOError> {
+    open_bam_records(path, offset)?.next().transpose()
+}
+
+/// Reads the primary records of a BAM file a block at a time, keeping track of
+/// where each starts in the decompressed data.
+pub struct BamRecords {
+    reader: BufReader<SeqSource>,
+    block: Vec<u8>,
+    offset: u64,
+    record_offset: u64,
+}
+
+/// Open a BAM file to read its primary records from an offset, which must be
+/// where a record starts, or 0 to read past the header first.
+pub fn open_bam_records(path: &Path, offset: u64) -> Result<BamRecords, IOError> {
+    // BGZF is a series of gzip members, which `open_seq_file` already handles
+    let mut reader = BufReader::new(open_seq_file_at(path, offset)?);
+    let mut offset = offset;
+    if offset == 0 {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != b"BAM\x01" {
+            return Err(IOError::new(
+                InvalidData,
+                format!("{} is not a BAM file.", path.display()),
+            ));
+        }
+        let l_text = read_len(&mut reader)?;
+        skip(&mut reader, l_text)?;
+        let n_ref = read_len(&mut reader)?;
+        offset = (12 + l_text) as u64;
+        for _ in 0..n_ref {
+            let l_name = read_len(&mut reader)?;
+            skip(&mut reader, l_name + 4)?;
+            offset += (l_name + 8) as u64;
+        }
+    }
+
+    Ok(BamRecords {
+        reader,
+        block: Vec::new(),
+        offset,
+        record_offset: offset,
+    })
+}
+
+impl BamRecords {
+    /// Where the last record read starts.
+    pub fn record_offset(&self) -> u64 {
+        self.record_offset
+    }
+
+    /// Where the block after the last record read starts.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn read_record(&mut self) -> Result<Option<Option<fastq::Record>>, IOError> {
+        let block_size = match read_len(&mut self.reader) {
+            Ok(size) => size,
+            Err(err) if err.kind() == UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        self.block.resize(block_size, 0);
+        self.reader.read_exact(&mut self.block)?;
+        let block_start = self.offset;
+        self.offset += (block_size + 4) as u64;
+        let rec = parse_bam_record(&self.block)?;
+        if rec.is_some() {
+            self.record_offset = block_start;
+        }
+        Ok(Some(rec))
+    }
+}
+
+impl Iterator for BamRecords {
+    type Item = Result<fastq::Record, IOError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.read_record() {
+                Ok(Some(Some(rec))) => return Some(Ok(rec)),
+                Ok(Some(None)) => {}
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+fn parse_bam_record(block: &[u8]) -> Result<Option<fastq::Record>, IOError> {
+    let truncated = || IOError::new(InvalidData, "BAM record is truncated.");
+    if block.len() < 32 {
+        return Err(truncated());
+    }
+    let l_read_name = usize::from(block[8]);
+    let n_cigar_op = usize::from(u16::from_le_bytes([block[12], block[13]]));
+    let flag = u16::from_le_bytes([block[14], block[15]]);
+    let l_seq = u32::from_le_bytes([block[16], block[17], block[18], block[19]]) as usize;
+
+    let name_start = 32;
+    let seq_start = name_start + l_read_name + n_cigar_op * 4;
+    let qual_start = seq_start + (l_seq + 1) / 2;
+    if block.len() < qual_start + l_seq || l_read_name == 0 {
+        return Err(truncated());
+    }
+
+    // The read name is NUL-terminated
+    let name = String::from_utf8_lossy(&block[name_start..name_start + l_read_name - 1]);
+    let seq: Vec<u8> = (0..l_seq)
+        .map(|i| {
+            let packed = block[seq_start + i / 2];
+            let code = if i % 2 == 0 {
+                packed >> 4
+            } else {
+                packed & 0x0f
+            };
+            BAM_BASES[usize::from(code)]
+        })
+        .collect();
+    let raw_qual = &block[qual_start..qual_start + l_seq];
+    let qual: Vec<u8> = if raw_qual.first() == Some(&0xff) {
+        vec![b'!'; l_seq]
+    } else {
+        raw_qual.iter().map(|q| q.saturating_add(33)).collect()
+    };
+
+    Ok(to_fastq_record(&name, flag, &seq, &qual))
+}
+
+/// Convert an alignment to a FASTQ record, skipping secondary and supplementary
+/// alignments so each read is only counted once.
+pub fn to_fastq_record(name: &str, flag: u16, seq: &[u8], qual: &[u8]) -> Option<fastq::Record> {
+    if flag & (FLAG_SECONDARY | FLAG_SUPPLEMENTARY) != 0 {
+        return None;
+    }
+    if flag & FLAG_REVERSE != 0 {
+        let seq = dna::revcomp(seq);
+        let qual: Vec<u8> = qual.iter().rev().copied().collect();
+        return Some(fastq::Record::with_attrs(name, None, &seq, &qual));
+    }
+    Some(fastq::Record::with_attrs(name, None, seq, qual))
+}
+
+fn read_len<R: Read>(reader: &mut R) -> Result<usize, IOError> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    let len = i32::from_le_bytes(buf);
+    if len < 0 {
+        return Err(IOError::new(InvalidData, "BAM length field is negative."));
+    }
+    Ok(len as usize)
+}
+
+fn skip<R: Read>(reader: &mut R, n_bytes: usize) -> Result<(), IOError> {
+    let copied = std::io::copy(&mut reader.take(n_bytes as u64), &mut std::io::sink())?;
+    if copied < n_bytes as u64 {
+        return Err(IOError::new(UnexpectedEof, "BAM header is truncated."));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use uuid::Uuid;
+
+    use super::{
+        bam_record_offsets, load_bam_record_at, load_bam_records, load_sam_record_at,
+        load_sam_records, sam_record_offsets,
+    };
+
+    fn bam_record(name: &str, flag: u16, seq: &[u8], qual: &[u8]) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // refID
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // pos
+        block.push((name.len() + 1) as u8);
+        block.push(255); // mapq
+        block.extend_from_slice(&4680u16.to_le_bytes()); // bin
+        block.extend_from_slice(&0u16.to_le_bytes()); // n_cigar_op
+        block.extend_from_slice(&flag.to_le_bytes());
+        block.extend_from_slice(&(seq.len() as u32).to_le_bytes());
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // next refID
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // next pos
+        block.extend_from_slice(&0i32.to_le_bytes()); // tlen
+        block.extend_from_slice(name.as_bytes());
+        block.push(0);
+        for pair in seq.chunks(2) {
+            let code = |b: u8| b"=ACMGRSVTWYHKDBN".iter().position(|c| *c == b).unwrap() as u8;
+            let low = pair.get(1).map_or(0, |b| code(*b));
+            block.push(code(pair[0]) << 4 | low);
+        }
+        block.extend(qual.iter().map(|q| q - 33));
+
+        let mut record = (block.len() as i32).to_le_bytes().to_vec();
+        record.extend(block);
+        record
+    }
+
+    #[test]
+    fn test_load_bam_records_reads_unaligned_bam() {
+        // Arrange
+        let mut bam = b"BAM\x01".to_vec();
+        let header = b"@HD\tVN:1.6\tSO:unsorted\n";
+        bam.extend_from_slice(&(header.len() as i32).to_le_bytes());
+        bam.extend_from_slice(header);
+        bam.extend_from_slice(&0i32.to_le_bytes());
+        bam.extend(bam_record("read1", 4, b"ACGTN", b"!+5?I"));
+        bam.extend(bam_record("read2", 4 | 0x100, b"ACGT", b"IIII"));
+        bam.extend(bam_record("read3", 0x10, b"AACG", b"!!+I"));
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".bam");
+        let mut encoder = GzEncoder::new(
+            std::fs::File::create(&test_file).unwrap(),
+            Compression::default(),
+        );
+        encoder.write_all(&bam).unwrap();
+        encoder.finish().unwrap();
+
+        // Act
+        let records = load_bam_records(test_file.as_path());
+        let offsets = bam_record_offsets(test_file.as_path());
+
+        // Assert
+        assert!(records.is_ok());
+        let records = records.unwrap();
+        assert_eq!(records.len(), 2);
+        let offsets = offsets.unwrap();
+        assert_eq!(offsets.len(), 2);
+        let read3 = load_bam_record_at(test_file.as_path(), offsets[1]).unwrap();
+        assert_eq!(read3.as_ref(), Some(&records[1]));
+        assert_eq!(records[0].id(), "read1");
+        assert_eq!(records[0].seq(), b"ACGTN");
+        assert_eq!(records[0].qual(), b"!+5?I");
+        assert_eq!(records[1].seq(), b"CGTT");
+        assert_eq!(records[1].qual(), b"I+!!");
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+    }
+
+    #[test]
+    fn test_load_sam_records_skips_header_and_secondary() {
+        // Arrange
+        let sam = "@HD\tVN:1.6\n\
+                   read1\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n\
+                   read1\t260\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n\
+                   read2\t4\t*\t0\t0\t*\t*\t0\t0\tGG\t*\n";
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".sam");
+        std::fs::write(&test_file, sam).unwrap();
+
+        // Act
+        let records = load_sam_records(test_file.as_path());
+        let offsets = sam_record_offsets(test_file.as_path());
+
+        // Assert
+        assert!(records.is_ok());
+        let records = records.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].id(), "read2");
+        assert_eq!(records[1].qual(), b"!!");
+        let offsets = offsets.unwrap();
+        assert_eq!(offsets, vec![11, sam.find("read2").unwrap() as u64]);
+        let read2 = load_sam_record_at(test_file.as_path(), offsets[1]).unwrap();
+        assert_eq!(read2.as_ref(), Some(&records[1]));
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+    }
+
+    #[test]
+    fn test_load_sam_records_errors_on_short_line() {
+        // Arrange
+        let test_file = std::env::temp_dir().join(Uuid::new_v4().to_string() + ".sam");
+        std::fs::write(&test_file, "read1\t4\t*\n").unwrap();
+
+        // Act
+        let records = load_sam_records(test_file.as_path());
+
+        // Assert
+        assert!(records.is_err());
+
+        // Clean up
+        assert!(std::fs::remove_file(test_file).is_ok());
+    }
+}
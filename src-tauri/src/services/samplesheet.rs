@@ -0,0 +1,171 @@
+use crate::models::SampleSheetEntry;
+use std::io::Error as IOError;
+use std::io::ErrorKind::InvalidData;
+use std::path::Path;
+
+/// Read the samples from an Illumina-style sample sheet. Only the `[Data]`
+/// section is used; a plain CSV with a header row is read as-is. A sample listed
+/// once per lane with the same barcodes is kept once, as lanes are not split.
+pub fn load_sample_sheet(path: &Path) -> Result<Vec<SampleSheetEntry>, IOError> {
+    let contents = std::fs::read_to_string(path)?;
+    parse_sample_sheet(&contents).map_err(|err| {
+        IOError::new(
+            InvalidData,
+            format!("Could not read sample sheet {}: {}", path.display(), err),
+        )
+    })
+}
+
+fn parse_sample_sheet(contents: &str) -> Result<Vec<SampleSheetEntry>, String> {
+    let lines: Vec<&str> = contents.lines().map(|line| line.trim()).collect();
+    let has_sections = lines.iter().any(|line| line.starts_with('['));
+    let data_start = if has_sections {
+        match lines
+            .iter()
+            .position(|line| line.to_ascii_lowercase().starts_with("[data]"))
+        {
+            Some(pos) => pos + 1,
+            None => return Err("there is no [Data] section".to_owned()),
+        }
+    } else {
+        0
+    };
+
+    let mut rows = lines[data_start..]
+        .iter()
+        .take_while(|line| !line.starts_with('['))
+        .filter(|line| !line.trim_matches(',').is_empty());
+    let header: Vec<String> = match rows.next() {
+        Some(header) => split_row(header)
+            .iter()
+            .map(|col| col.to_ascii_lowercase())
+            .collect(),
+        None => return Err("there is no header row".to_owned()),
+    };
+    let column = |name: &str| header.iter().position(|col| col == name);
+    let sample_id_col = column("sample_id").ok_or("there is no Sample_ID column")?;
+    let index_col = column("index").ok_or("there is no index column")?;
+    let sample_name_col = column("sample_name");
+    let index2_col = column("index2");
+
+    let mut samples: Vec<SampleSheetEntry> = Vec::new();
+    for row in rows {
+        let fields = split_row(row);
+        let field = |col: Option<usize>| {
+            col.and_then(|col| fields.get(col))
+                .filter(|value| !value.is_empty())
+                .map(|value| value.to_string())
+        };
+        let sample_id = field(Some(sample_id_col))
+            .ok_or_else(|| format!("a row is missing its Sample_ID: {}", row))?;
+        // The ID names the sample's output file, so it must not reach outside it
+        if !sample_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!(
+                "Sample_ID {} may only contain letters, digits, '_' and '-'",
+                sample_id
+            ));
+        }
+        // Reads matching no sample are written to Undetermined, in any case as
+        // filesystems can ignore it
+        if sample_id.eq_ignore_ascii_case("Undetermined") {
+            return Err(format!(
+                "Sample_ID {} is kept for reads matching no sample",
+                sample_id
+            ));
+        }
+        let index =
+            field(Some(index_col)).ok_or_else(|| format!("sample {} has no index", sample_id))?;
+        let sample = SampleSheetEntry {
+            sample_name: field(sample_name_col).unwrap_or_else(|| sample_id.clone()),
+            sample_id,
+            index: index.to_ascii_uppercase(),
+            index2: field(index2_col).map(|index2| index2.to_ascii_uppercase()),
+        };
+        match samples
+            .iter()
+            .find(|prev| prev.sample_id.eq_ignore_ascii_case(&sample.sample_id))
+        {
+            Some(prev) if prev.sample_id != sample.sample_id => {
+                return Err(format!(
+                    "Sample_IDs {} and {} differ only in case, so their files would collide",
+                    prev.sample_id, sample.sample_id
+                ))
+            }
+            Some(prev) if prev.index == sample.index && prev.index2 == sample.index2 => {}
+            Some(_) => {
+                return Err(format!(
+                    "sample {} is listed with different indexes",
+                    sample.sample_id
+                ))
+            }
+            None => samples.push(sample),
+        }
+    }
+
+    Ok(samples)
+}
+
+fn split_row(row: &str) -> Vec<&str> {
+    row.split(',').map(|field| field.trim()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_sample_sheet;
+
+    #[test]
+    fn test_parse_sample_sheet_reads_data_section() {
+        let sheet = "[Header]\n\
+                     IEMFileVersion,4\n\
+                     \n\
+                     [Data]\n\
+                     Sample_ID,Sample_Name,I7_Index_ID,index,index2\n\
+                     S1,liver,D701,attactcg,TATAGCCT\n\
+                     S2,,D702,TCCGGAGA,ATAGAGGC\n\
+                     ,,,,\n";
+
+        let samples = parse_sample_sheet(sheet).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].sample_name, "liver");
+        assert_eq!(samples[0].index, "ATTACTCG");
+        assert_eq!(samples[1].sample_name, "S2");
+        assert_eq!(samples[1].index2.as_deref(), Some("ATAGAGGC"));
+    }
+
+    #[test]
+    fn test_parse_sample_sheet_merges_lanes() {
+        let sheet = "Lane,Sample_ID,index\n\
+                     1,S1,ACGTAC\n\
+                     2,S1,ACGTAC\n\
+                     1,S2,TTTTTT\n\
+                     2,S2,TTTTTT\n";
+
+        let samples = parse_sample_sheet(sheet).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].sample_id, "S2");
+        assert!(parse_sample_sheet("Lane,Sample_ID,index\n1,S1,ACGTAC\n2,S1,TTTTTT\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_sample_sheet_rejects_unsafe_sample_ids() {
+        assert!(parse_sample_sheet("Sample_ID,index\n../S1,ACGTAC\n").is_err());
+        assert!(parse_sample_sheet("Sample_ID,index\nS 1,ACGTAC\n").is_err());
+        assert!(parse_sample_sheet("Sample_ID,index\nS1_a-2,ACGTAC\n").is_ok());
+    }
+
+    #[test]
+    fn test_parse_sample_sheet_rejects_colliding_file_names() {
+        assert!(parse_sample_sheet("Sample_ID,index\nUndetermined,ACGTAC\n").is_err());
+        assert!(parse_sample_sheet("Sample_ID,index\nundetermined,ACGTAC\n").is_err());
+        assert!(parse_sample_sheet("Sample_ID,index\nS1,ACGTAC\ns1,TTTTTT\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_sample_sheet_errors_without_index() {
+        assert!(parse_sample_sheet("Sample_ID,Description\nS1,x\n").is_err());
+        assert!(parse_sample_sheet("Sample_ID,index\nS1,\n").is_err());
+    }
+}
@@ -16,20 +16,24 @@ import {
   Box,
 } from '@chakra-ui/react'
 import { open } from '@tauri-apps/api/dialog'
-import FastaResultPanel from './components/FastaResultPanel'
-import FastqResultPanel from './components/FastqResultPanel'
 import FileInput from './components/FileInput'
 import FQModal from './components/FQModal'
 import LoadingIndicator from './components/LoadingIndicator'
+import ResultsList from './components/ResultsList'
 import TextInput from './components/TextInput'
-import { analyseFileSequences, analyseTextSequences } from './analysis'
-import ToggleSwitch from './components/ToggleSwitch'
+import { analyseTextSequences, closeResults, getResultsPage, openFileResults } from './analysis'
+
+// How many records of a file to analyse until the whole file is asked for
+const PREVIEW_RECORDS = 100000
 
 function App() {
   const textSequences = useRef('')
   const fileSequences = useRef('')
-  const [seqFormat, setSeqFormat] = useState('fastq')
-  const [results, setResults] = useState([])
+  const fileReference = useRef(null)
+  // How many results there are and how to fetch a page of them, once analysed
+  const [resultsSource, setResultsSource] = useState(null)
+  const [resultsHandle, setResultsHandle] = useState(null)
+  const [isPreview, setIsPreview] = useState(false)
 
   const { isOpen, onOpen, onClose } = useDisclosure()
 
@@ -47,22 +51,35 @@ function App() {
         filters: [
           {
             name: 'Sequence files',
-            extensions: ['fq', 'fastq', 'fa', 'fasta']
+            extensions: ['fq', 'fastq', 'fa', 'fasta', 'sam', 'bam', 'cram', 'gz', 'bz2', 'xz', 'zst']
           }
         ]
       }
     )
 
+    // Aligned CRAM reads are stored against the reference they were aligned to
+    fileReference.current = null
+    if (filePath && filePath.toLowerCase().endsWith('.cram')) {
+      fileReference.current = await open(
+        {
+          title: 'Choose the FASTA reference the CRAM file was aligned against',
+          directory: false,
+          multiple: false,
+          filters: [
+            {
+              name: 'FASTA files',
+              extensions: ['fa', 'fasta', 'fna', 'gz']
+            }
+          ]
+        }
+      )
+    }
+
     let fileInput = document.getElementById('file-input')
     fileInput.value = filePath
     fileSequences.current = filePath
   }
 
-  // Change the sequence format
-  const handleFormatSwitch = (event) => {
-    setSeqFormat(event.target.value === "fastq" ? "fasta" : "fastq")
-  }
-
   // Clear the input fields and reset the state
   const clearInputs = () => {
     let textInput = document.getElementById('text-input')
@@ -72,26 +89,61 @@ function App() {
     let fileInput = document.getElementById('file-input')
     fileInput.value = ''
     fileSequences.current = ''
+    fileReference.current = null
   }
 
   // Send the text sequences to the backend and return the analytics
   const analyseText = async () => {
     onOpen()
-    let results = await analyseTextSequences(textSequences.current, seqFormat)
-    setResults(results)
+    try {
+      let results = await analyseTextSequences(textSequences.current)
+      setResultsSource({
+        total: results.length,
+        loadPage: async (offset, limit) => ({ results: results.slice(offset, offset + limit) }),
+      })
+    } catch (error) {
+      onClose()
+      alert(error)
+    }
   }
 
-  // Send the file sequences to the backend and return the analytics
-  const analyseFile = async () => {
+  // Send the file sequences to the backend, which keeps the analytics for the
+  // results list to fetch a page at a time.
+  // Only the first records are analysed unless the whole file is asked for.
+  const analyseFile = async (wholeFile = false) => {
     onOpen()
-    let results = await analyseFileSequences(fileSequences.current, seqFormat)
-    setResults(results)
+    if (resultsHandle !== null) {
+      closeResults(resultsHandle)
+      setResultsHandle(null)
+      setResultsSource(null)
+    }
+    try {
+      let handle = await openFileResults(
+        fileSequences.current,
+        fileReference.current,
+        wholeFile ? null : PREVIEW_RECORDS
+      )
+      setResultsHandle(handle.handle)
+      setIsPreview(handle.is_preview)
+      setResultsSource({
+        total: handle.n_records,
+        loadPage: (offset, limit) => getResultsPage(handle.handle, offset, limit),
+      })
+    } catch (error) {
+      onClose()
+      alert(error)
+    }
   }
 
   //Clear the results when the modal is closed
   const closeAndClearResults = () => {
+    if (resultsHandle !== null) {
+      closeResults(resultsHandle)
+      setResultsHandle(null)
+      setIsPreview(false)
+    }
     clearInputs()
-    setResults([])
+    setResultsSource(null)
     onClose()
   }
 
@@ -103,32 +155,21 @@ function App() {
         isOpen={isOpen}
         onClose={closeAndClearResults}
       >
-        {results.length > 0 ? (
-            <Accordion allowMultiple allowToggle>
-              {
-                results.map(
-                  (result, index) => (
-                    <AccordionItem key={index}>
-                      <AccordionButton>
-                        <Heading as='h4' size='md'>
-                          {result.id}
-                        </Heading>
-                        <Spacer />
-                        <AccordionIcon />
-                      </AccordionButton>
-                      <AccordionPanel>
-                        {result.result_type === "fastq" ? (
-                            <FastqResultPanel result={result} />
-                          ) : (
-                            <FastaResultPanel result={result} />
-                          )
-                        }
-                      </AccordionPanel>
-                    </AccordionItem>
-                  )
-                )
-              }
-            </Accordion>
+        {isPreview && resultsSource !== null && (
+          <Center>
+            <Text>Showing the first {resultsSource.total} records of the file.</Text>
+            <Button variant='ghost' onClick={() => analyseFile(true)}>
+              Analyse the whole file
+            </Button>
+          </Center>
+        )}
+        {resultsSource !== null ? (
+            <ResultsList
+              key={resultsHandle}
+              total={resultsSource.total}
+              loadPage={resultsSource.loadPage}
+              handle={resultsHandle}
+            />
           ) : (
             <Center>
               <LoadingIndicator message={'Loading results...'}/>
@@ -139,13 +180,6 @@ function App() {
 
       <Heading>Fastq Analyser</Heading>
       {/* The input options */}
-      <ToggleSwitch
-        id={"format-switch"}
-        title={"Sequence type:"}
-        value={seqFormat}
-        onChange={handleFormatSwitch}
-        isChecked={seqFormat === "fastq"}
-      />
       <Accordion allowMultiple allowToggle>
         <AccordionItem>
           <AccordionButton>
@@ -1,35 +1,39 @@
 import { invoke } from '@tauri-apps/api'
 
-// Send the sequence text to the backend and return the analytics
-const analyseTextSequences = async (sequences, format) => {
-    let choice;
-    switch (format) {
-        case "fastq":
-            choice = "analyse_fastq_sequences"
-            break
-        case "fasta":
-            choice = "analyse_fasta_sequences"
-            break
-        default: throw new Error(`Choice "${format}" is not a valid command`)
-    }
-    let results = await invoke(choice, {sequences})
+// Send the sequence text to the backend and return the analytics.
+// The backend works out whether the text is fastq or fasta.
+const analyseTextSequences = async (sequences) => {
+    let results = await invoke("analyse_sequences", {sequences})
     return results
 }
 
-// Send the sequene file to the backend and return the analytics
-const analyseFileSequences = async (path, format) => {
-    let choice;
-    switch (format) {
-        case "fastq":
-            choice = "analyse_fastq_file"
-            break
-        case "fasta":
-            choice = "analyse_fasta_file"
-            break
-        default: throw new Error(`Choice "${format}" is not a valid command`)
-    }
-    let results = await invoke(choice, {path})
-    return results
+// Analyse the sequence file and keep the results in the backend.
+// Returns a handle for fetching the results a page at a time.
+// Aligned CRAM reads need the FASTA reference they were aligned against.
+// Give maxRecords to analyse only the first records of a large file.
+const openFileResults = async (path, reference = null, maxRecords = null) => {
+    return await invoke("open_results", {path, reference, maxRecords})
+}
+
+// Fetch one page of the results behind a handle.
+const getResultsPage = async (handle, offset, limit) => {
+    return await invoke("get_results_page", {handle, offset, limit})
+}
+
+// Fetch the sequence, qualities, ORFs and adapter position of one stored record.
+const getRecord = async (handle, index) => {
+    return await invoke("get_record", {handle, index})
+}
+
+// Free the results behind a handle in the backend.
+const closeResults = async (handle) => {
+    return await invoke("close_results", {handle})
 }
 
-export { analyseFileSequences, analyseTextSequences }
\ No newline at end of file
+export {
+    analyseTextSequences,
+    closeResults,
+    getRecord,
+    getResultsPage,
+    openFileResults,
+}
@@ -9,15 +9,29 @@ function FastaResultPanel({ result }) {
             <Text>
                 <strong>Record is valid?</strong>&nbsp;{result.is_valid ? 'Yes' : 'No'}
             </Text>
+            {result.alphabet_error && (
+                <Text>
+                    <strong>Invalid character:</strong>&nbsp;'{result.alphabet_error.character}' at position {result.alphabet_error.position + 1}
+                </Text>
+            )}
             <Text>
                 <strong>Sequence length:</strong>&nbsp;{result.seq_len} bases
             </Text>
+            <Text>
+                <strong>Ns:</strong>&nbsp;{result.n_count} (longest run {result.longest_n_run}), other ambiguous bases: {result.ambiguous_count}
+            </Text>
             <Text>
                 <strong>GC %:</strong>&nbsp;{result.gc * 100}%
             </Text>
             <Text>
                 <strong>No.# ORFs:</strong>&nbsp;{result.n_orfs}
             </Text>
+            <Text>
+                <strong>DUST score:</strong>&nbsp;{result.complexity.dust_score.toFixed(1)}
+            </Text>
+            <Text>
+                <strong>Longest homopolymer:</strong>&nbsp;{result.complexity.longest_homopolymer} bases
+            </Text>
         </>
     )
 }
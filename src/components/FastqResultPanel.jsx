@@ -6,14 +6,30 @@ function FastqResultPanel({ result }) {
             <Text>
                 <strong>Description:</strong>&nbsp;{result.desc}
             </Text>
+            {result.illumina && (
+                <Text>
+                    <strong>Flowcell position:</strong>&nbsp;lane {result.illumina.lane}, tile {result.illumina.tile}
+                </Text>
+            )}
             <Text>
                 <strong>Record is valid?</strong>&nbsp;{result.is_valid ? 'Yes' : 'No'}
             </Text>
+            {result.alphabet_error && (
+                <Text>
+                    <strong>Invalid character:</strong>&nbsp;'{result.alphabet_error.character}' at position {result.alphabet_error.position + 1}
+                </Text>
+            )}
             <Text>
                 <strong>Sequence length:</strong>&nbsp;{result.seq_len} bases
             </Text>
             <Text>
-                <strong>PHRED score per base:</strong>&nbsp;{result.phred_score / result.seq_len}
+                <strong>Ns:</strong>&nbsp;{result.n_count} (longest run {result.longest_n_run}), other ambiguous bases: {result.ambiguous_count}
+            </Text>
+            <Text>
+                <strong>Mean PHRED score:</strong>&nbsp;{result.mean_quality.toFixed(1)}
+            </Text>
+            <Text>
+                <strong>Expected errors:</strong>&nbsp;{result.expected_errors.toFixed(2)}
             </Text>
             <Text>
                 <strong>GC %:</strong>&nbsp;{result.gc * 100}%
@@ -21,6 +37,12 @@ function FastqResultPanel({ result }) {
             <Text>
                 <strong>No.# ORFs:</strong>&nbsp;{result.n_orfs}
             </Text>
+            <Text>
+                <strong>DUST score:</strong>&nbsp;{result.complexity.dust_score.toFixed(1)}
+            </Text>
+            <Text>
+                <strong>Longest homopolymer:</strong>&nbsp;{result.complexity.longest_homopolymer} bases
+            </Text>
         </>
     )
 }
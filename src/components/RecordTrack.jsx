@@ -0,0 +1,57 @@
+import { useState } from 'react'
+import { Box, Button, Text } from '@chakra-ui/react'
+import { getRecord } from '../analysis'
+
+// Colour a base by its Phred score, or leave it plain for FASTA records
+const qualityColour = (phred) => {
+    if (phred === undefined) {
+        return 'inherit'
+    } else if (phred < 20) {
+        return 'red.500'
+    } else if (phred < 30) {
+        return 'orange.400'
+    }
+    return 'green.500'
+}
+
+function RecordTrack({ handle, index }) {
+    const [record, setRecord] = useState(null)
+
+    const showRecord = async () => {
+        try {
+            setRecord(await getRecord(handle, index))
+        } catch (error) {
+            alert(error)
+        }
+    }
+
+    if (record === null) {
+        return (
+            <Button size='sm' variant='outline' onClick={showRecord}>
+                Show sequence
+            </Button>
+        )
+    }
+
+    const inOrf = (pos) => record.orfs.some((orf) => pos >= orf.start && pos < orf.end)
+    const inAdapter = (pos) => record.adapter_start !== null && pos >= record.adapter_start
+
+    return (
+        <Box fontFamily='mono' fontSize='sm' wordBreak='break-all'>
+            {[...record.seq].map((base, pos) => (
+                <Text
+                    as='span'
+                    key={pos}
+                    color={qualityColour(record.phred?.[pos])}
+                    textDecoration={inOrf(pos) ? 'underline' : 'none'}
+                    bg={inAdapter(pos) ? 'gray.200' : 'transparent'}
+                    title={record.phred ? `Q${record.phred[pos]}` : undefined}
+                >
+                    {base}
+                </Text>
+            ))}
+        </Box>
+    )
+}
+
+export default RecordTrack
@@ -0,0 +1,103 @@
+import { useRef, useState } from 'react'
+import { Box, Heading, Text } from '@chakra-ui/react'
+import { FixedSizeList } from 'react-window'
+import FastaResultPanel from './FastaResultPanel'
+import FastqResultPanel from './FastqResultPanel'
+import RecordTrack from './RecordTrack'
+
+// How many results to fetch at a time, and the size of the list
+const PAGE_SIZE = 100
+const ROW_HEIGHT = 36
+const LIST_HEIGHT = 360
+
+// One row of the list, showing the record's ID once its page has arrived
+const ResultRow = ({ index, style, data }) => {
+    const loaded = data.resultAt(index)
+    return (
+        <Box
+            style={style}
+            px={2}
+            borderBottomWidth='1px'
+            cursor={loaded ? 'pointer' : 'default'}
+            bg={data.selected === index ? 'gray.100' : 'transparent'}
+            onClick={() => loaded && data.onSelect(index)}
+        >
+            <Text noOfLines={1} lineHeight={`${ROW_HEIGHT}px`}>
+                {loaded ? loaded.result.id : 'Loading...'}
+            </Text>
+        </Box>
+    )
+}
+
+// Renders only the rows in view, fetching the pages under them as the list
+// scrolls. loadPage(offset, limit) resolves to the results of a page, and the
+// index of each within the file when they can be read back from it.
+function ResultsList({ total, loadPage, handle }) {
+    const [pages, setPages] = useState({})
+    const [selected, setSelected] = useState(null)
+    const requested = useRef(new Set())
+
+    const fetchVisiblePages = ({ visibleStartIndex, visibleStopIndex }) => {
+        let first = Math.floor(visibleStartIndex / PAGE_SIZE)
+        let last = Math.floor(visibleStopIndex / PAGE_SIZE)
+        for (let page = first; page <= last; page++) {
+            if (requested.current.has(page)) {
+                continue
+            }
+            requested.current.add(page)
+            loadPage(page * PAGE_SIZE, PAGE_SIZE)
+                .then((loaded) => setPages((pages) => ({ ...pages, [page]: loaded })))
+                .catch((error) => {
+                    requested.current.delete(page)
+                    alert(error)
+                })
+        }
+    }
+
+    const resultAt = (index) => {
+        let page = pages[Math.floor(index / PAGE_SIZE)]
+        if (page === undefined) {
+            return null
+        }
+        let offset = index % PAGE_SIZE
+        return { result: page.results[offset], recordIndex: page.indices?.[offset] }
+    }
+
+    const selectedResult = selected === null ? null : resultAt(selected)
+
+    return (
+        <Box>
+            <FixedSizeList
+                height={Math.min(LIST_HEIGHT, total * ROW_HEIGHT)}
+                width='100%'
+                itemCount={total}
+                itemSize={ROW_HEIGHT}
+                itemData={{ resultAt, selected, onSelect: setSelected }}
+                onItemsRendered={fetchVisiblePages}
+            >
+                {ResultRow}
+            </FixedSizeList>
+            {selectedResult !== null && (
+                <Box mt={4}>
+                    <Heading as='h4' size='md'>
+                        {selectedResult.result.id}
+                    </Heading>
+                    {selectedResult.result.result_type === 'fastq' ? (
+                        <FastqResultPanel result={selectedResult.result} />
+                    ) : (
+                        <FastaResultPanel result={selectedResult.result} />
+                    )}
+                    {handle !== null && selectedResult.recordIndex !== undefined && (
+                        <RecordTrack
+                            key={selectedResult.recordIndex}
+                            handle={handle}
+                            index={selectedResult.recordIndex}
+                        />
+                    )}
+                </Box>
+            )}
+        </Box>
+    )
+}
+
+export default ResultsList